@@ -0,0 +1,335 @@
+use std::{fmt, str::FromStr};
+
+/// Bytes that aren't valid in the file's encoding are kept as chars from the end of plane 16's
+/// private use area, one per byte, and written back out unchanged. Real chars in this range are
+/// themselves stored as escaped bytes when they come from UTF-8, and as escaped surrogates when
+/// they come from UTF-16, so nothing is lost either way.
+const BYTE_ESCAPE_BASE: u32 = 0x10FF00;
+/// Unpaired UTF-16 surrogates get the 2048 code points just below the byte escapes.
+const SURROGATE_ESCAPE_BASE: u32 = 0x10F700;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Windows-1252 for 0x80..=0x9F. The five unassigned bytes are `None`, and a file containing
+/// them is read as Latin-1 instead.
+const CP1252_HIGH: [Option<char>; 32] = [
+    Some('€'),
+    None,
+    Some('‚'),
+    Some('ƒ'),
+    Some('„'),
+    Some('…'),
+    Some('†'),
+    Some('‡'),
+    Some('ˆ'),
+    Some('‰'),
+    Some('Š'),
+    Some('‹'),
+    Some('Œ'),
+    None,
+    Some('Ž'),
+    None,
+    None,
+    Some('‘'),
+    Some('’'),
+    Some('“'),
+    Some('”'),
+    Some('•'),
+    Some('–'),
+    Some('—'),
+    Some('˜'),
+    Some('™'),
+    Some('š'),
+    Some('›'),
+    Some('œ'),
+    None,
+    Some('ž'),
+    Some('Ÿ'),
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+            Encoding::Windows1252 => "cp1252",
+        })
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" | "utf-16" | "utf16" => Ok(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            "cp1252" | "windows-1252" => Ok(Encoding::Windows1252),
+            _ => Err(format!("Invalid encoding: {s}")),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoded {
+    pub text: String,
+    pub encoding: Encoding,
+    pub bom: bool,
+}
+
+/// Works out how `bytes` are encoded and decodes them without losing anything.
+///
+/// A BOM wins. Otherwise the text is UTF-8 unless it has bytes above 0x7F and not a single valid
+/// multi-byte sequence, in which case it's a legacy 8-bit file.
+pub fn decode(bytes: &[u8]) -> Decoded {
+    if let Some(rest) = bytes.strip_prefix(UTF8_BOM) {
+        return Decoded {
+            text: decode_utf8(rest),
+            encoding: Encoding::Utf8,
+            bom: true,
+        };
+    }
+    if let Some(rest) = bytes.strip_prefix(UTF16LE_BOM) {
+        return Decoded {
+            text: decode_utf16(rest, u16::from_le_bytes),
+            encoding: Encoding::Utf16Le,
+            bom: true,
+        };
+    }
+    if let Some(rest) = bytes.strip_prefix(UTF16BE_BOM) {
+        return Decoded {
+            text: decode_utf16(rest, u16::from_be_bytes),
+            encoding: Encoding::Utf16Be,
+            bom: true,
+        };
+    }
+
+    let encoding = if looks_like_legacy(bytes) {
+        if bytes
+            .iter()
+            .all(|&b| !(0x80..=0x9F).contains(&b) || CP1252_HIGH[(b - 0x80) as usize].is_some())
+        {
+            Encoding::Windows1252
+        } else {
+            Encoding::Latin1
+        }
+    } else {
+        Encoding::Utf8
+    };
+    Decoded {
+        text: decode_as(bytes, encoding),
+        encoding,
+        bom: false,
+    }
+}
+
+/// Decodes `bytes` as `encoding` without looking for a BOM, for re-reading a file in an
+/// encoding the user picked.
+pub fn decode_as(bytes: &[u8], encoding: Encoding) -> String {
+    match encoding {
+        Encoding::Utf8 => decode_utf8(bytes),
+        Encoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+        Encoding::Utf16Be => decode_utf16(bytes, u16::from_be_bytes),
+        Encoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+        Encoding::Windows1252 => bytes
+            .iter()
+            .map(|&b| match b {
+                0x80..=0x9F => CP1252_HIGH[(b - 0x80) as usize].unwrap_or(escape_byte(b)),
+                _ => b as char,
+            })
+            .collect(),
+    }
+}
+
+/// A char that can't be written in the target encoding, reported by [`encode`].
+#[derive(Debug)]
+pub struct Unencodable(pub char);
+
+pub fn encode(text: &str, encoding: Encoding, bom: bool) -> Result<Vec<u8>, Unencodable> {
+    let mut out = Vec::with_capacity(text.len());
+    match encoding {
+        Encoding::Utf8 => {
+            if bom {
+                out.extend_from_slice(UTF8_BOM);
+            }
+            for ch in text.chars() {
+                match escaped_byte(ch) {
+                    Some(b) => out.push(b),
+                    None => out.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+                }
+            }
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let to_bytes = if encoding == Encoding::Utf16Le {
+                u16::to_le_bytes
+            } else {
+                u16::to_be_bytes
+            };
+            if bom {
+                out.extend_from_slice(&to_bytes(0xFEFF));
+            }
+            for ch in text.chars() {
+                if let Some(b) = escaped_byte(ch) {
+                    out.push(b);
+                } else if let Some(unit) = escaped_surrogate(ch) {
+                    out.extend_from_slice(&to_bytes(unit));
+                } else {
+                    for unit in ch.encode_utf16(&mut [0; 2]) {
+                        out.extend_from_slice(&to_bytes(*unit));
+                    }
+                }
+            }
+        }
+        Encoding::Latin1 => {
+            for ch in text.chars() {
+                match escaped_byte(ch) {
+                    Some(b) => out.push(b),
+                    None if (ch as u32) <= 0xFF => out.push(ch as u8),
+                    None => return Err(Unencodable(ch)),
+                }
+            }
+        }
+        Encoding::Windows1252 => {
+            for ch in text.chars() {
+                if let Some(b) = escaped_byte(ch) {
+                    out.push(b);
+                } else if let Some(i) = CP1252_HIGH.iter().position(|&c| c == Some(ch)) {
+                    out.push(0x80 + i as u8);
+                } else if (ch as u32) <= 0xFF && !(0x80..=0x9F).contains(&(ch as u32)) {
+                    out.push(ch as u8);
+                } else {
+                    return Err(Unencodable(ch));
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// How `ch` should be drawn if it stands for raw data rather than text: `<xx>` for a byte and
+/// `<dxxx>` for a lone surrogate.
+pub fn placeholder(ch: char) -> Option<String> {
+    if let Some(b) = escaped_byte(ch) {
+        Some(format!("<{b:02x}>"))
+    } else {
+        escaped_surrogate(ch).map(|unit| format!("<{unit:04x}>"))
+    }
+}
+
+/// How many cells [`placeholder`] takes for `ch`, without making it.
+pub fn placeholder_width(ch: char) -> Option<usize> {
+    if escaped_byte(ch).is_some() {
+        Some(4)
+    } else {
+        escaped_surrogate(ch).map(|_| 6)
+    }
+}
+
+fn escape_byte(b: u8) -> char {
+    char::from_u32(BYTE_ESCAPE_BASE + b as u32).unwrap()
+}
+
+fn escaped_byte(ch: char) -> Option<u8> {
+    (ch as u32).checked_sub(BYTE_ESCAPE_BASE).map(|b| b as u8)
+}
+
+fn escaped_surrogate(ch: char) -> Option<u16> {
+    let offset = (ch as u32).checked_sub(SURROGATE_ESCAPE_BASE)?;
+    (offset < 0x800).then(|| 0xD800 + offset as u16)
+}
+
+fn is_escape(ch: char) -> bool {
+    ch as u32 >= SURROGATE_ESCAPE_BASE
+}
+
+fn decode_utf8(mut bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                push_utf8(&mut text, valid);
+                return text;
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                push_utf8(&mut text, std::str::from_utf8(valid).unwrap());
+                let bad = e.error_len().unwrap_or(rest.len());
+                text.extend(rest[..bad].iter().map(|&b| escape_byte(b)));
+                bytes = &rest[bad..];
+            }
+        }
+    }
+}
+
+fn push_utf8(text: &mut String, valid: &str) {
+    for ch in valid.chars() {
+        if is_escape(ch) {
+            text.extend(ch.encode_utf8(&mut [0; 4]).bytes().map(escape_byte));
+        } else {
+            text.push(ch);
+        }
+    }
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]));
+    let escape_surrogate =
+        |unit: u16| char::from_u32(SURROGATE_ESCAPE_BASE + (unit - 0xD800) as u32).unwrap();
+    let mut text = String::with_capacity(bytes.len() / 2);
+    for unit in char::decode_utf16(units) {
+        match unit {
+            // Real chars in the escape range are kept as their two surrogates, as
+            // `push_utf8` keeps them as their bytes.
+            Ok(ch) if is_escape(ch) => {
+                text.extend(
+                    ch.encode_utf16(&mut [0; 2])
+                        .iter()
+                        .map(|&u| escape_surrogate(u)),
+                );
+            }
+            Ok(ch) => text.push(ch),
+            Err(e) => text.push(escape_surrogate(e.unpaired_surrogate())),
+        }
+    }
+    if let Some(&odd) = bytes.chunks_exact(2).remainder().first() {
+        text.push(escape_byte(odd));
+    }
+    text
+}
+
+fn looks_like_legacy(bytes: &[u8]) -> bool {
+    let mut has_high = false;
+    let mut rest = bytes;
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => return has_high && valid.is_ascii(),
+            Err(e) => {
+                let (valid, tail) = rest.split_at(e.valid_up_to());
+                // Any real multi-byte sequence means this is UTF-8 with a few bad bytes.
+                if !std::str::from_utf8(valid).unwrap().is_ascii() {
+                    return false;
+                }
+                has_high = true;
+                rest = &tail[e.error_len().unwrap_or(tail.len())..];
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::{fmt, str::FromStr};
+
+/// Line terminator used when writing a buffer back out, named after vim's `fileformat` values.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    #[default]
+    Unix,
+    Dos,
+    Mac,
+}
+
+impl FileFormat {
+    pub fn line_ending(self) -> &'static str {
+        match self {
+            FileFormat::Unix => "\n",
+            FileFormat::Dos => "\r\n",
+            FileFormat::Mac => "\r",
+        }
+    }
+
+    /// What new files get when nothing can be detected.
+    pub fn native() -> Self {
+        if cfg!(windows) {
+            FileFormat::Dos
+        } else {
+            FileFormat::Unix
+        }
+    }
+}
+
+impl fmt::Display for FileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FileFormat::Unix => "unix",
+            FileFormat::Dos => "dos",
+            FileFormat::Mac => "mac",
+        })
+    }
+}
+
+impl FromStr for FileFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unix" => Ok(FileFormat::Unix),
+            "dos" => Ok(FileFormat::Dos),
+            "mac" => Ok(FileFormat::Mac),
+            _ => Err(format!("Invalid fileformat: {s}")),
+        }
+    }
+}
+
+/// Result of splitting loaded text into lines.
+#[derive(Debug)]
+pub struct SplitText {
+    pub lines: Vec<String>,
+    pub format: FileFormat,
+    /// More than one kind of terminator was found; `format` is the most common one and saving
+    /// will normalise every line to it.
+    pub mixed: bool,
+    /// The last line had a terminator.
+    pub eol: bool,
+}
+
+/// Splits `text` on LF and CRLF, stripping the terminators and working out which one the file
+/// uses. A lone CR only ends a line in a file without any LF, which is then `mac`; elsewhere it
+/// stays in the line and shows as `^M`. Files without any terminator get `default`.
+pub fn split_lines(text: &str, default: FileFormat) -> SplitText {
+    let mac = !text.contains('\n') && text.contains('\r');
+    let mut lines = Vec::new();
+    let (mut lf, mut crlf) = (0usize, 0usize);
+    let bytes = text.as_bytes();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => {
+                lf += 1;
+                lines.push(text[start..i].to_owned());
+                start = i + 1;
+            }
+            b'\r' if bytes.get(i + 1) == Some(&b'\n') => {
+                crlf += 1;
+                lines.push(text[start..i].to_owned());
+                i += 1;
+                start = i + 1;
+            }
+            b'\r' if mac => {
+                lines.push(text[start..i].to_owned());
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    let eol = start == text.len() && !lines.is_empty();
+    if !eol {
+        lines.push(text[start..].to_owned());
+    }
+
+    let format = if mac {
+        FileFormat::Mac
+    } else if lf == 0 && crlf == 0 {
+        default
+    } else if crlf >= lf {
+        FileFormat::Dos
+    } else {
+        FileFormat::Unix
+    };
+    SplitText {
+        lines,
+        format,
+        mixed: lf > 0 && crlf > 0,
+        eol,
+    }
+}
+
+/// Inverse of [`split_lines`].
+pub fn join_lines(lines: &[String], format: FileFormat, eol: bool) -> String {
+    let ending = format.line_ending();
+    let mut text = lines.join(ending);
+    if eol {
+        text.push_str(ending);
+    }
+    text
+}
@@ -0,0 +1,292 @@
+//! Read-only view of files too big to load, see [`LARGE_FILE_THRESHOLD`].
+//!
+//! Nothing but a sparse line index is kept in memory: the byte offset of every
+//! [`CHECKPOINT_INTERVAL`]th line, built by a background thread. Lines are read from disk on
+//! demand around whatever is being looked at, so the first screen shows up immediately and the
+//! rest of the file becomes reachable as indexing progresses.
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+};
+
+use super::{Encoding, FileFormat, encoding};
+
+/// Files at least this big are opened in large-file mode.
+pub const LARGE_FILE_THRESHOLD: u64 = 256 * 1024 * 1024;
+
+const CHECKPOINT_INTERVAL: usize = 1024;
+/// How much of the file is looked at to guess its encoding and line endings.
+const SAMPLE_SIZE: usize = 64 * 1024;
+const INDEX_CHUNK_SIZE: usize = 1024 * 1024;
+/// Lines read into the cache around the one asked for.
+const WINDOW_LINES: usize = 256;
+/// Anything past this many bytes of a single line is dropped from view, and the status line
+/// says how many lines that happened to.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Default)]
+struct Index {
+    /// Byte offset of line `i * CHECKPOINT_INTERVAL`.
+    checkpoints: Vec<u64>,
+    line_count: usize,
+    bytes_indexed: u64,
+    /// Lines longer than [`MAX_LINE_BYTES`], which only show in part.
+    long_lines: usize,
+    done: bool,
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    index: Mutex<Index>,
+    cancel: AtomicBool,
+}
+
+#[derive(Debug)]
+pub struct LargeFile {
+    file: RefCell<File>,
+    len: u64,
+    encoding: Encoding,
+    file_format: FileFormat,
+    shared: Arc<Shared>,
+    /// First line number and the lines read from there.
+    window: RefCell<(usize, Vec<String>)>,
+}
+
+impl LargeFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+        (&mut file)
+            .take(SAMPLE_SIZE as u64)
+            .read_to_end(&mut sample)?;
+        let decoded = encoding::decode(trim_cut_char(&sample));
+        if matches!(decoded.encoding, Encoding::Utf16Le | Encoding::Utf16Be) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "large-file mode only handles 8-bit encodings",
+            ));
+        }
+        let data_start = if decoded.bom { 3 } else { 0 };
+        let file_format = match decoded.text.find(['\r', '\n']) {
+            Some(i) if decoded.text[i..].starts_with("\r\n") => FileFormat::Dos,
+            _ => FileFormat::Unix,
+        };
+
+        let shared = Arc::new(Shared {
+            index: Mutex::new(Index {
+                checkpoints: vec![data_start],
+                ..Index::default()
+            }),
+            cancel: AtomicBool::new(false),
+        });
+        let indexer = File::open(path)?;
+        let thread_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            let result = build_index(indexer, data_start, len, &thread_shared);
+            let mut index = thread_shared.index.lock().unwrap();
+            index.done = true;
+            if let Err(e) = result {
+                index.error = Some(e.to_string());
+            }
+        });
+
+        Ok(Self {
+            file: RefCell::new(file),
+            len,
+            encoding: decoded.encoding,
+            file_format,
+            shared,
+            window: RefCell::new((0, Vec::new())),
+        })
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn file_format(&self) -> FileFormat {
+        self.file_format
+    }
+
+    /// Lines found so far. Grows until indexing is done.
+    pub fn line_count(&self) -> usize {
+        self.shared.index.lock().unwrap().line_count.max(1)
+    }
+
+    /// Percentage of the file indexed, or `None` once it's complete.
+    pub fn indexing_progress(&self) -> Option<u8> {
+        let index = self.shared.index.lock().unwrap();
+        if index.done {
+            return None;
+        }
+        Some((index.bytes_indexed * 100 / self.len.max(1)) as u8)
+    }
+
+    pub fn index_error(&self) -> Option<String> {
+        self.shared.index.lock().unwrap().error.clone()
+    }
+
+    /// Lines found so far that are too long to show whole.
+    pub fn long_lines(&self) -> usize {
+        self.shared.index.lock().unwrap().long_lines
+    }
+
+    pub fn line(&self, idx: usize) -> Option<Cow<'_, str>> {
+        {
+            let window = self.window.borrow();
+            if let Some(line) = idx.checked_sub(window.0).and_then(|i| window.1.get(i)) {
+                return Some(Cow::Owned(line.clone()));
+            }
+        }
+        let start = idx.saturating_sub(WINDOW_LINES / 4);
+        let lines = self.read_lines(start, WINDOW_LINES).ok()?;
+        let line = lines.get(idx - start).cloned();
+        *self.window.borrow_mut() = (start, lines);
+        line.map(Cow::Owned)
+    }
+
+    /// Reads up to `count` lines starting at line `start`, walking forward from the nearest
+    /// checkpoint.
+    pub fn read_lines(&self, start: usize, count: usize) -> io::Result<Vec<String>> {
+        // Lines past the indexed part can still be read as long as their checkpoint is known,
+        // which is what lets the first screen show before the indexer has got anywhere.
+        let (offset, skip) = {
+            let index = self.shared.index.lock().unwrap();
+            let checkpoint = start / CHECKPOINT_INTERVAL;
+            match index.checkpoints.get(checkpoint) {
+                Some(&offset) => (offset, start - checkpoint * CHECKPOINT_INTERVAL),
+                None => return Ok(Vec::new()),
+            }
+        };
+
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(&mut *file);
+        let mut buf = Vec::new();
+        for _ in 0..skip {
+            read_line(&mut reader, &mut buf)?;
+        }
+        let mut lines = Vec::with_capacity(count);
+        for _ in 0..count {
+            if !read_line(&mut reader, &mut buf)? {
+                break;
+            }
+            if buf.last() == Some(&b'\r') {
+                buf.pop();
+            }
+            lines.push(encoding::decode_as(&buf, self.encoding));
+        }
+        Ok(lines)
+    }
+}
+
+impl Drop for LargeFile {
+    fn drop(&mut self) {
+        self.shared.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Reads one line into `buf` without its `\n`, keeping at most [`MAX_LINE_BYTES`] of it.
+/// Returns `false` at the end of the file.
+fn read_line(reader: &mut impl BufRead, buf: &mut Vec<u8>) -> io::Result<bool> {
+    buf.clear();
+    let mut read_any = false;
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(read_any);
+        }
+        read_any = true;
+        let (chunk, found) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (&available[..i], Some(i + 1)),
+            None => (available, None),
+        };
+        let room = MAX_LINE_BYTES.saturating_sub(buf.len());
+        buf.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        let consumed = found.unwrap_or(available.len());
+        reader.consume(consumed);
+        if found.is_some() {
+            return Ok(true);
+        }
+    }
+}
+
+fn build_index(mut file: File, data_start: u64, len: u64, shared: &Shared) -> io::Result<()> {
+    file.seek(SeekFrom::Start(data_start))?;
+    let mut chunk = vec![0; INDEX_CHUNK_SIZE];
+    let mut offset = data_start;
+    let mut lines = 0;
+    let mut line_start = data_start;
+    let mut long_lines = 0;
+    let mut new_checkpoints = Vec::new();
+    let mut last_byte = None;
+
+    loop {
+        if shared.cancel.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let n = file.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        for (i, _) in chunk[..n].iter().enumerate().filter(|&(_, &b)| b == b'\n') {
+            let end = offset + i as u64;
+            if end - line_start > MAX_LINE_BYTES as u64 {
+                long_lines += 1;
+            }
+            line_start = end + 1;
+            lines += 1;
+            if lines % CHECKPOINT_INTERVAL == 0 {
+                new_checkpoints.push(offset + i as u64 + 1);
+            }
+        }
+        offset += n as u64;
+        last_byte = chunk[..n].last().copied();
+
+        let mut index = shared.index.lock().unwrap();
+        index.checkpoints.append(&mut new_checkpoints);
+        // An unterminated last line still counts while indexing, so partially indexed lines
+        // aren't shown as missing.
+        index.line_count = lines + 1;
+        index.bytes_indexed = offset - data_start;
+        index.long_lines = long_lines;
+    }
+
+    let mut index = shared.index.lock().unwrap();
+    index.line_count = if last_byte == Some(b'\n') || offset == data_start {
+        lines.max(1)
+    } else {
+        lines + 1
+    };
+    index.bytes_indexed = len;
+    if offset - line_start > MAX_LINE_BYTES as u64 {
+        index.long_lines = long_lines + 1;
+    }
+    Ok(())
+}
+
+/// `sample` without a char its end cuts in two, which would otherwise look like a stray byte
+/// and make a UTF-8 file seem to be in some other encoding.
+fn trim_cut_char(sample: &[u8]) -> &[u8] {
+    let tail = sample.len().saturating_sub(3);
+    let lead = sample[tail..]
+        .iter()
+        .rposition(|&b| b & 0xC0 != 0x80)
+        .map_or(sample.len(), |i| tail + i);
+    match std::str::from_utf8(&sample[lead..]) {
+        Err(e) if e.error_len().is_none() => &sample[..lead],
+        _ => sample,
+    }
+}
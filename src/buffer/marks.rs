@@ -0,0 +1,192 @@
+//! Positions that move with the text around them: marks, and the entries of the change list
+//! and the jump list. Every edit goes past [`Marks::adjust`] so they stay on the text they
+//! were set on as lines are inserted and deleted above them.
+
+use std::collections::{HashMap, VecDeque};
+
+use super::Position;
+
+/// How many changes `g;` can go back through, vim's number.
+const CHANGE_LIST_LEN: usize = 100;
+
+/// One edit as marks see it: the text between `start` and `old_end` became the text between
+/// `start` and `new_end`.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Edit {
+    pub start: Position,
+    pub old_end: Position,
+    pub new_end: Position,
+    /// Lines the edit removed entirely, as rows before it, line break included.
+    pub removed_rows: (usize, usize),
+}
+
+impl Edit {
+    /// Where `pos` is after the edit, or `None` when its line was deleted and `keep` isn't
+    /// set. Other positions inside the edited text move to where it starts, or stay in the
+    /// replacement when there is one.
+    fn moved(&self, pos: Position, keep: bool) -> Option<Position> {
+        if pos < self.start {
+            return Some(pos);
+        }
+        if pos >= self.old_end {
+            return Some(if pos.row == self.old_end.row {
+                Position::new(
+                    self.new_end.row,
+                    self.new_end.col + pos.col - self.old_end.col,
+                )
+            } else {
+                Position::new(pos.row - self.old_end.row + self.new_end.row, pos.col)
+            });
+        }
+        let (first, last) = self.removed_rows;
+        if !keep && (first..last).contains(&pos.row) {
+            return None;
+        }
+        Some(pos.min(self.new_end))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Marks {
+    /// The marks set with `m`, and the ones the editor keeps itself like `'.` and `'<`.
+    named: HashMap<char, Position>,
+    /// Where changes were made, oldest first, for `g;` and `g,`.
+    changes: Vec<Position>,
+    /// The entry `g;` went to last, `changes.len()` when it hasn't since the last change.
+    change_index: usize,
+    /// Where the jump list's entries for this buffer are, by the id it knows them by.
+    jumps: HashMap<u64, Position>,
+    /// Lines `:global` still has to visit, in order.
+    marked_lines: VecDeque<usize>,
+}
+
+impl Marks {
+    pub fn get(&self, name: char) -> Option<Position> {
+        self.named.get(&name).copied()
+    }
+
+    pub fn set(&mut self, name: char, pos: Position) {
+        self.named.insert(name, pos);
+    }
+
+    pub fn remove(&mut self, name: char) -> Option<Position> {
+        self.named.remove(&name)
+    }
+
+    /// All marks set, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (char, Position)> + '_ {
+        self.named.iter().map(|(&name, &pos)| (name, pos))
+    }
+
+    /// Notes a change at `pos` for the change list. A change on the same line as the last
+    /// one replaces it, so typing a line leaves one entry rather than one per char.
+    pub(super) fn record_change(&mut self, pos: Position) {
+        match self.changes.last_mut() {
+            Some(last) if last.row == pos.row => *last = pos,
+            _ => {
+                self.changes.push(pos);
+                if self.changes.len() > CHANGE_LIST_LEN {
+                    self.changes.remove(0);
+                }
+            }
+        }
+        self.change_index = self.changes.len();
+    }
+
+    /// `g;` with a negative `delta`, `g,` with a positive one: the change that many entries
+    /// away from the last one gone to.
+    pub fn step_change(&mut self, delta: isize) -> Result<Position, ChangeListEnd> {
+        if self.changes.is_empty() {
+            return Err(ChangeListEnd::Empty);
+        }
+        let index = self.change_index.min(self.changes.len()) as isize + delta;
+        let index = match index {
+            ..0 if self.change_index == 0 => return Err(ChangeListEnd::Start),
+            ..0 => 0,
+            index if index as usize >= self.changes.len() => {
+                if self.change_index + 1 >= self.changes.len() {
+                    return Err(ChangeListEnd::End);
+                }
+                self.changes.len() - 1
+            }
+            index => index as usize,
+        };
+        self.change_index = index;
+        Ok(self.changes[index])
+    }
+
+    pub fn jump(&self, id: u64) -> Option<Position> {
+        self.jumps.get(&id).copied()
+    }
+
+    pub fn set_jump(&mut self, id: u64, pos: Position) {
+        self.jumps.insert(id, pos);
+    }
+
+    pub fn remove_jump(&mut self, id: u64) {
+        self.jumps.remove(&id);
+    }
+
+    /// Marks `rows`, in order, for `:global` to go through, in place of any marked before.
+    pub fn mark_lines(&mut self, rows: impl IntoIterator<Item = usize>) {
+        self.marked_lines = rows.into_iter().collect();
+    }
+
+    /// The first line still marked, unmarking it. Lines deleted or joined to another one since
+    /// they were marked are left out.
+    pub fn next_marked_line(&mut self) -> Option<usize> {
+        self.marked_lines.pop_front()
+    }
+
+    pub(super) fn adjust(&mut self, edit: &Edit) {
+        // Like vim, marks the user set go with their line, the others move out of the way.
+        self.named.retain(
+            |&name, pos| match edit.moved(*pos, !name.is_ascii_alphabetic()) {
+                Some(moved) => {
+                    *pos = moved;
+                    true
+                }
+                None => false,
+            },
+        );
+        for pos in self.changes.iter_mut().chain(self.jumps.values_mut()) {
+            *pos = edit.moved(*pos, true).unwrap_or(*pos);
+        }
+        // Lines before the edit stay where they are.
+        let unmoved = self
+            .marked_lines
+            .partition_point(|&row| Position::new(row, 0) < edit.start);
+        let mut idx = unmoved;
+        while idx < self.marked_lines.len() {
+            let start = Position::new(self.marked_lines[idx], 0);
+            // Text put in at the start of a line leaves it where it is. Otherwise a line that
+            // doesn't start a line any more was joined to another one, and is gone as much as
+            // a deleted one.
+            let row = match edit.moved(start, false) {
+                Some(moved) if moved.col == 0 => Some(moved.row),
+                Some(_) if start == edit.start => Some(start.row),
+                _ => None,
+            };
+            match row {
+                Some(row) => {
+                    self.marked_lines[idx] = row;
+                    idx += 1;
+                }
+                None => {
+                    self.marked_lines.remove(idx);
+                }
+            }
+        }
+    }
+}
+
+/// Why `g;` or `g,` didn't move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ChangeListEnd {
+    #[error("Change list is empty")]
+    Empty,
+    #[error("At start of changelist")]
+    Start,
+    #[error("At end of changelist")]
+    End,
+}
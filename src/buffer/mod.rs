@@ -0,0 +1,660 @@
+use std::{
+    borrow::Cow,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::fileio::{self, FileStamp, WriteOptions};
+
+pub use encoding::Encoding;
+pub use fileformat::FileFormat;
+pub use large::LARGE_FILE_THRESHOLD;
+pub use undo::TimeTravel;
+
+pub mod encoding;
+mod fileformat;
+mod large;
+pub mod marks;
+pub mod undo;
+pub mod undofile;
+
+use large::LargeFile;
+use marks::{Edit, Marks};
+use undo::{Change, Steps, UndoTree};
+use undofile::UndoFileError;
+
+/// A place in the buffer. `col` counts chars, not bytes or screen cells.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub row: usize,
+    pub col: usize,
+}
+
+impl Position {
+    pub fn new(row: usize, col: usize) -> Self {
+        Self { row, col }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BufferError {
+    #[error("Could not read {0}: [{1}]")]
+    Read(PathBuf, io::Error),
+    #[error("Could not write {0}: [{1}]")]
+    Write(PathBuf, io::Error),
+    #[error("{0}: {1:?} can't be written as {2}")]
+    Unencodable(PathBuf, char, Encoding),
+    #[error("No file name")]
+    NoFileName,
+    #[error("Buffer is read-only")]
+    ReadOnly,
+}
+
+/// An open document: the text split into lines plus where it came from.
+#[derive(Debug)]
+pub struct Buffer {
+    path: Option<PathBuf>,
+    /// Lines without their terminators, see `file_format`.
+    lines: Vec<String>,
+    /// Set by changes that aren't part of the text, like switching `file_format`.
+    modified: bool,
+    undo: UndoTree,
+    /// Undo state that matches the file on disk.
+    saved_state: usize,
+    /// [`fileio::content_hash`] of the file as last read or written.
+    disk_hash: Option<u64>,
+    disk_stamp: Option<FileStamp>,
+    /// Bumped on every change to the text, including undo and redo.
+    change_tick: u64,
+    read_only: bool,
+    file_format: FileFormat,
+    /// The file on disk mixed line endings; cleared once it's been written in one format.
+    mixed_line_endings: bool,
+    /// Whether the last line is terminated when written.
+    eol: bool,
+    encoding: Encoding,
+    /// Write a byte order mark, kept from the file that was read.
+    bom: bool,
+    /// Set for files over [`LARGE_FILE_THRESHOLD`], which are read from disk as needed instead
+    /// of being held in `lines`.
+    large: Option<LargeFile>,
+    marks: Marks,
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self {
+            path: None,
+            lines: vec![String::new()],
+            modified: false,
+            undo: UndoTree::new(),
+            saved_state: 0,
+            disk_hash: None,
+            disk_stamp: None,
+            change_tick: 0,
+            read_only: false,
+            file_format: FileFormat::native(),
+            mixed_line_endings: false,
+            eol: true,
+            encoding: Encoding::Utf8,
+            bom: false,
+            large: None,
+            marks: Marks::default(),
+        }
+    }
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens `path`, or starts an empty buffer for it if the file doesn't exist yet.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, BufferError> {
+        let path = path.into();
+        if fs::metadata(&path).is_ok_and(|meta| meta.len() >= LARGE_FILE_THRESHOLD) {
+            match LargeFile::open(&path) {
+                Ok(large) => {
+                    return Ok(Self {
+                        disk_stamp: FileStamp::of(&path),
+                        file_format: large.file_format(),
+                        encoding: large.encoding(),
+                        lines: Vec::new(),
+                        path: Some(path),
+                        large: Some(large),
+                        ..Self::default()
+                    });
+                }
+                // Encodings the index can't scan are loaded normally.
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => {}
+                Err(e) => return Err(BufferError::Read(path, e)),
+            }
+        }
+        let loaded = match Loaded::read(&path) {
+            Ok(loaded) => loaded,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    path: Some(path),
+                    ..Self::default()
+                });
+            }
+            Err(e) => return Err(BufferError::Read(path, e)),
+        };
+        Ok(Self {
+            path: Some(path),
+            disk_hash: Some(loaded.hash),
+            disk_stamp: loaded.stamp,
+            lines: loaded.split.lines,
+            file_format: loaded.split.format,
+            mixed_line_endings: loaded.split.mixed,
+            eol: loaded.split.eol,
+            encoding: loaded.encoding,
+            bom: loaded.bom,
+            ..Self::default()
+        })
+    }
+
+    /// Looks at the file on disk to see if something else changed it since it was last read or
+    /// written. Files that were only touched, or rewritten with the same contents, don't count.
+    pub fn check_disk(&mut self) -> DiskState {
+        let Some(path) = &self.path else {
+            return DiskState::Unchanged;
+        };
+        let stamp = FileStamp::of(path);
+        if stamp == self.disk_stamp {
+            return DiskState::Unchanged;
+        }
+        if stamp.is_none() {
+            // Reported once; if the file comes back its new stamp counts as a change.
+            self.disk_stamp = None;
+            return DiskState::Deleted;
+        }
+        if self.large.is_none()
+            && let Ok(bytes) = fs::read(path)
+            && Some(fileio::content_hash(&bytes)) == self.disk_hash
+        {
+            self.disk_stamp = stamp;
+            return DiskState::Unchanged;
+        }
+        // Only reported once, whatever the user decides to do about it.
+        self.disk_stamp = stamp;
+        DiskState::Changed
+    }
+
+    /// Re-reads the file. The new contents replace the old as a single undo step, so a reload
+    /// (say after a `git checkout`) can be undone like any other change.
+    pub fn reload(&mut self, cursor: Position) -> Result<(), BufferError> {
+        let path = self.path.clone().ok_or(BufferError::NoFileName)?;
+        if self.large.is_some() {
+            *self = Self::open(path)?;
+            return Ok(());
+        }
+        let loaded = Loaded::read(&path).map_err(|e| BufferError::Read(path, e))?;
+        self.commit_change();
+        self.begin_change(cursor);
+        self.replace(
+            Position::default(),
+            self.end(),
+            &loaded.split.lines.join("\n"),
+        );
+        self.commit_change();
+        self.saved_state = self.undo.current();
+        self.modified = false;
+        self.disk_hash = Some(loaded.hash);
+        self.disk_stamp = loaded.stamp;
+        self.file_format = loaded.split.format;
+        self.mixed_line_endings = loaded.split.mixed;
+        self.eol = loaded.split.eol;
+        self.encoding = loaded.encoding;
+        self.bom = loaded.bom;
+        Ok(())
+    }
+
+    /// The lines of the file as it is on disk now, for comparing against the buffer.
+    pub fn read_disk_lines(&self) -> Result<Vec<String>, BufferError> {
+        let path = self.path.clone().ok_or(BufferError::NoFileName)?;
+        let loaded = Loaded::read(&path).map_err(|e| BufferError::Read(path, e))?;
+        Ok(loaded.split.lines)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified || self.undo.current() != self.saved_state || self.undo.has_pending()
+    }
+
+    pub fn file_format(&self) -> FileFormat {
+        self.file_format
+    }
+
+    /// Changes the line endings the buffer is written with. All lines are converted on the next
+    /// save, including any that had a different ending in a mixed file.
+    pub fn set_file_format(&mut self, format: FileFormat) {
+        if format != self.file_format || self.mixed_line_endings {
+            self.file_format = format;
+            self.mixed_line_endings = false;
+            self.modified = true;
+        }
+    }
+
+    pub fn has_mixed_line_endings(&self) -> bool {
+        self.mixed_line_endings
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Changes the encoding used on the next save. The text itself is unaffected.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        if encoding != self.encoding {
+            self.encoding = encoding;
+            self.modified = true;
+        }
+    }
+
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    pub fn set_bom(&mut self, bom: bool) {
+        if bom != self.bom {
+            self.bom = bom;
+            self.modified = true;
+        }
+    }
+
+    /// Whether the last line ends in a line break, in the file as read or once written.
+    pub fn has_eol(&self) -> bool {
+        self.eol
+    }
+
+    pub fn set_eol(&mut self, eol: bool) {
+        if eol != self.eol {
+            self.eol = eol;
+            self.modified = true;
+        }
+    }
+
+    /// Large files can only be viewed, other buffers can be opened read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only || self.large.is_some()
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick
+    }
+
+    /// How far along the line index of a large file is, as a percentage, while it's being built.
+    pub fn indexing_progress(&self) -> Option<u8> {
+        self.large.as_ref()?.indexing_progress()
+    }
+
+    pub fn index_error(&self) -> Option<String> {
+        self.large.as_ref()?.index_error()
+    }
+
+    /// How many lines of a large file are too long to show whole.
+    pub fn long_lines(&self) -> usize {
+        self.large.as_ref().map_or(0, LargeFile::long_lines)
+    }
+
+    pub fn line_count(&self) -> usize {
+        match &self.large {
+            Some(large) => large.line_count(),
+            None => self.lines.len(),
+        }
+    }
+
+    pub fn line(&self, idx: usize) -> Option<Cow<'_, str>> {
+        match &self.large {
+            Some(large) => large.line(idx),
+            None => self.lines.get(idx).map(|line| Cow::Borrowed(line.as_str())),
+        }
+    }
+
+    /// Number of chars in line `idx`, which is what cursor columns are measured in.
+    pub fn line_len(&self, idx: usize) -> usize {
+        self.line(idx).map_or(0, |line| line.chars().count())
+    }
+
+    /// Last position in the buffer, just past the end of the last line.
+    pub fn end(&self) -> Position {
+        let row = self.line_count().saturating_sub(1);
+        Position::new(row, self.line_len(row))
+    }
+
+    /// Moves `pos` onto existing text: no further than the end of its line, and anything past the
+    /// last line becomes the end of the buffer.
+    pub fn clamp(&self, pos: Position) -> Position {
+        if pos.row >= self.line_count() {
+            return self.end();
+        }
+        Position::new(pos.row, pos.col.min(self.line_len(pos.row)))
+    }
+
+    /// The whole text, lines joined with `\n` whatever the file format.
+    pub fn text(&self) -> String {
+        match &self.large {
+            Some(_) => self.text_range(Position::default(), self.end()),
+            None => self.lines.join("\n"),
+        }
+    }
+
+    /// Text between `start` and `end`, lines joined with `\n`.
+    pub fn text_range(&self, start: Position, end: Position) -> String {
+        let (start, end) = (self.clamp(start), self.clamp(end));
+        if start >= end {
+            return String::new();
+        }
+        // Through `line`, since a large file's lines aren't in `lines`.
+        let first = self.line(start.row).unwrap_or_default();
+        if start.row == end.row {
+            return first[byte_offset(&first, start.col)..byte_offset(&first, end.col)].to_owned();
+        }
+        let mut text = first[byte_offset(&first, start.col)..].to_owned();
+        for row in start.row + 1..end.row {
+            text.push('\n');
+            text.push_str(&self.line(row).unwrap_or_default());
+        }
+        let last = self.line(end.row).unwrap_or_default();
+        text.push('\n');
+        text.push_str(&last[..byte_offset(&last, end.col)]);
+        text
+    }
+
+    /// Replaces the text between `start` and `end` with `text`, which may span lines.
+    /// Every edit goes through here so it ends up in the undo history.
+    /// Returns the position just after the inserted text.
+    pub fn replace(&mut self, start: Position, end: Position, text: &str) -> Position {
+        let (start, end) = (self.clamp(start), self.clamp(end));
+        let (start, end) = (start.min(end), start.max(end));
+        let removed = self.text_range(start, end);
+        if removed.is_empty() && text.is_empty() {
+            return start;
+        }
+        // `'[` and `']` span everything changed in the undo step so far.
+        let extend = self.undo.has_pending();
+        self.undo.record(Change {
+            start,
+            removed,
+            inserted: text.to_owned(),
+        });
+        let inserted_end = self.splice(start, end, text);
+        let (first, last) = match (self.marks.get('['), self.marks.get(']')) {
+            (Some(first), Some(last)) if extend => (first.min(start), last.max(inserted_end)),
+            _ => (start, inserted_end),
+        };
+        self.marks.set('[', first);
+        self.marks.set(']', last);
+        self.marks.set('.', start);
+        self.marks.record_change(start);
+        inserted_end
+    }
+
+    pub fn insert(&mut self, pos: Position, text: &str) -> Position {
+        self.replace(pos, pos, text)
+    }
+
+    /// Removes the text between `start` and `end` and returns it.
+    pub fn delete(&mut self, start: Position, end: Position) -> String {
+        let removed = self.text_range(start.min(end), start.max(end));
+        self.replace(start, end, "");
+        removed
+    }
+
+    pub fn insert_char(&mut self, row: usize, col: usize, ch: char) {
+        self.insert(Position::new(row, col), ch.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Splits line `row` at `col`, moving everything after the cursor onto a new line below.
+    pub fn split_line(&mut self, row: usize, col: usize) {
+        self.insert(Position::new(row, col), "\n");
+    }
+
+    /// Removes the char before `col`, joining with the previous line when at the start of one.
+    /// Returns where the cursor ends up.
+    pub fn delete_char_before(&mut self, row: usize, col: usize) -> (usize, usize) {
+        let end = Position::new(row, col);
+        let start = if col > 0 {
+            Position::new(row, col - 1)
+        } else if row > 0 {
+            Position::new(row - 1, self.line_len(row - 1))
+        } else {
+            return (row, col);
+        };
+        self.delete(start, end);
+        (start.row, start.col)
+    }
+
+    /// Starts an undo step at `cursor` if one isn't already open. Everything changed until
+    /// [`Buffer::commit_change`] is undone together and puts the cursor back here.
+    pub fn begin_change(&mut self, cursor: Position) {
+        self.undo.begin(cursor);
+    }
+
+    pub fn commit_change(&mut self) {
+        self.undo.commit();
+    }
+
+    /// Undoes the last step, returning where the cursor should go.
+    pub fn undo(&mut self) -> Option<Position> {
+        let steps = self.undo.undo()?;
+        Some(self.apply_steps(steps))
+    }
+
+    pub fn redo(&mut self) -> Option<Position> {
+        let steps = self.undo.redo()?;
+        Some(self.apply_steps(steps))
+    }
+
+    /// `g-`/`g+`: moves `delta` states through the history in the order they were created,
+    /// crossing between branches.
+    pub fn undo_chronologically(&mut self, delta: isize) -> Option<Position> {
+        let steps = self.undo.step_chronologically(delta)?;
+        Some(self.apply_steps(steps))
+    }
+
+    /// `:earlier`/`:later`.
+    pub fn time_travel(&mut self, travel: TimeTravel, back: bool) -> Option<Position> {
+        let steps = match travel {
+            TimeTravel::Steps(n) => {
+                // More steps than there are states only goes as far as the history does.
+                let n = isize::try_from(n).unwrap_or(isize::MAX);
+                self.undo.step_chronologically(if back { -n } else { n })
+            }
+            TimeTravel::Time(offset) => self.undo.travel_in_time(offset, back),
+        }?;
+        Some(self.apply_steps(steps))
+    }
+
+    pub fn marks(&self) -> &Marks {
+        &self.marks
+    }
+
+    pub fn marks_mut(&mut self) -> &mut Marks {
+        &mut self.marks
+    }
+
+    pub fn undo_tree(&self) -> &UndoTree {
+        &self.undo
+    }
+
+    /// Saves the undo history to `undo_dir` so it's still there next time the file is opened.
+    /// Only meaningful right after the buffer was read or written.
+    pub fn write_undo_file(&self, undo_dir: &Path) -> Result<(), UndoFileError> {
+        match (&self.path, self.disk_hash) {
+            (Some(path), Some(hash)) => undofile::write(undo_dir, path, hash, &self.undo),
+            _ => Ok(()),
+        }
+    }
+
+    /// Picks up the history saved by [`Buffer::write_undo_file`], if the file hasn't changed
+    /// since. Returns whether there was one.
+    pub fn read_undo_file(&mut self, undo_dir: &Path) -> Result<bool, UndoFileError> {
+        let (Some(path), Some(hash)) = (&self.path, self.disk_hash) else {
+            return Ok(false);
+        };
+        if self.is_modified() {
+            return Ok(false);
+        }
+        match undofile::read(undo_dir, path, hash)? {
+            Some(tree) => {
+                self.undo = tree;
+                self.saved_state = self.undo.current();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn apply_steps(&mut self, steps: Steps) -> Position {
+        let mut cursor = None;
+        for &idx in &steps.undo {
+            let node = self.undo.node(idx);
+            let changes = node.changes.clone();
+            cursor = Some(node.cursor);
+            for change in changes.iter().rev() {
+                let start = self.clamp(change.start);
+                let end = self.clamp(end_of(start, &change.inserted));
+                self.splice(start, end, &change.removed);
+            }
+        }
+        for &idx in &steps.redo {
+            let changes = self.undo.node(idx).changes.clone();
+            cursor = changes.first().map(|change| change.start);
+            for change in &changes {
+                let start = self.clamp(change.start);
+                let end = self.clamp(end_of(start, &change.removed));
+                self.splice(start, end, &change.inserted);
+            }
+        }
+        self.clamp(cursor.unwrap_or_default())
+    }
+
+    /// Replaces `start..end` with `text` without touching the undo history.
+    fn splice(&mut self, start: Position, end: Position, text: &str) -> Position {
+        let removed_rows = if !text.is_empty() || start.row == end.row {
+            (0, 0)
+        } else if start.col == 0 {
+            (start.row, end.row)
+        } else if end.col >= self.line_len(end.row) {
+            (start.row + 1, end.row + 1)
+        } else {
+            (start.row + 1, end.row)
+        };
+        let head = &self.lines[start.row];
+        let head = head[..byte_offset(head, start.col)].to_owned();
+        let tail = &self.lines[end.row];
+        let tail = &tail[byte_offset(tail, end.col)..];
+
+        let mut new_lines: Vec<String> = text.split('\n').map(str::to_owned).collect();
+        let inserted_end = end_of(start, text);
+        new_lines[0].insert_str(0, &head);
+        new_lines.last_mut().unwrap().push_str(tail);
+        self.lines.splice(start.row..=end.row, new_lines);
+        self.change_tick += 1;
+        self.marks.adjust(&Edit {
+            start,
+            old_end: end,
+            new_end: inserted_end,
+            removed_rows,
+        });
+        inserted_end
+    }
+
+    /// The file contents as they'd be written: terminators, encoding and BOM applied.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, encoding::Unencodable> {
+        let text = fileformat::join_lines(&self.lines, self.file_format, self.eol);
+        encoding::encode(&text, self.encoding, self.bom)
+    }
+
+    pub fn save(&mut self, options: &WriteOptions) -> Result<(), BufferError> {
+        if self.is_read_only() {
+            return Err(BufferError::ReadOnly);
+        }
+        let path = self.path.clone().ok_or(BufferError::NoFileName)?;
+        let bytes = self
+            .to_bytes()
+            .map_err(|e| BufferError::Unencodable(path.clone(), e.0, self.encoding))?;
+        fileio::atomic_write(&path, &bytes, options)
+            .map_err(|e| BufferError::Write(path.clone(), e))?;
+        self.disk_hash = Some(fileio::content_hash(&bytes));
+        self.disk_stamp = FileStamp::of(&path);
+        self.undo.commit();
+        self.saved_state = self.undo.current();
+        self.modified = false;
+        self.mixed_line_endings = false;
+        Ok(())
+    }
+
+    pub fn save_as(
+        &mut self,
+        path: impl Into<PathBuf>,
+        options: &WriteOptions,
+    ) -> Result<(), BufferError> {
+        // A write that fails leaves the buffer with the name it had.
+        let previous = self.path.replace(path.into());
+        let result = self.save(options);
+        if result.is_err() {
+            self.path = previous;
+        }
+        result
+    }
+}
+
+/// What [`Buffer::check_disk`] found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskState {
+    Unchanged,
+    Changed,
+    Deleted,
+}
+
+/// A file read and decoded into lines.
+struct Loaded {
+    hash: u64,
+    stamp: Option<FileStamp>,
+    split: fileformat::SplitText,
+    encoding: Encoding,
+    bom: bool,
+}
+
+impl Loaded {
+    fn read(path: &Path) -> io::Result<Self> {
+        // Stamped before reading, so a write that races with the read is noticed later on.
+        let stamp = FileStamp::of(path);
+        let bytes = fs::read(path)?;
+        let decoded = encoding::decode(&bytes);
+        Ok(Self {
+            hash: fileio::content_hash(&bytes),
+            stamp,
+            split: fileformat::split_lines(&decoded.text, FileFormat::native()),
+            encoding: decoded.encoding,
+            bom: decoded.bom,
+        })
+    }
+}
+
+/// Where text inserted at `start` ends.
+fn end_of(start: Position, text: &str) -> Position {
+    match text.rsplit_once('\n') {
+        Some((before, last)) => Position::new(
+            start.row + before.matches('\n').count() + 1,
+            last.chars().count(),
+        ),
+        None => Position::new(start.row, start.col + text.chars().count()),
+    }
+}
+
+fn byte_offset(line: &str, col: usize) -> usize {
+    line.char_indices().nth(col).map_or(line.len(), |(i, _)| i)
+}
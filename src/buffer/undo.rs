@@ -0,0 +1,241 @@
+//! Branching undo history, modelled on vim's undo tree.
+//!
+//! Every state the buffer has been in is a node; undoing moves to the parent and making a new
+//! change after an undo starts a new branch instead of throwing the old one away. Nodes are
+//! numbered in the order they were created, which is what `g-`/`g+` and `:earlier`/`:later`
+//! step through.
+
+use std::time::{Duration, SystemTime};
+
+use super::Position;
+
+/// One primitive edit: `removed` was replaced by `inserted`, both starting at `start`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub start: Position,
+    pub removed: String,
+    pub inserted: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct UndoNode {
+    pub parent: usize,
+    /// Applied in order to get from the parent's text to this node's.
+    pub changes: Vec<Change>,
+    /// Where the cursor was before the changes, restored when they're undone.
+    pub cursor: Position,
+    pub time: SystemTime,
+    /// Child that redo follows, the most recently visited branch.
+    pub redo_child: Option<usize>,
+}
+
+/// Which changes to apply, in order, to move between two states.
+#[derive(Debug, Default)]
+pub struct Steps {
+    /// Node whose changes are undone, i.e. applied in reverse.
+    pub undo: Vec<usize>,
+    /// Node whose changes are redone.
+    pub redo: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct UndoTree {
+    /// `nodes[0]` is the text as it was loaded and has no changes.
+    nodes: Vec<UndoNode>,
+    current: usize,
+    pending: Option<UndoNode>,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoTree {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![UndoNode {
+                parent: 0,
+                changes: Vec::new(),
+                cursor: Position::default(),
+                time: SystemTime::now(),
+                redo_child: None,
+            }],
+            current: 0,
+            pending: None,
+        }
+    }
+
+    pub fn from_nodes(nodes: Vec<UndoNode>, current: usize) -> Self {
+        Self {
+            nodes,
+            current,
+            pending: None,
+        }
+    }
+
+    pub fn nodes(&self) -> &[UndoNode] {
+        &self.nodes
+    }
+
+    pub fn node(&self, idx: usize) -> &UndoNode {
+        &self.nodes[idx]
+    }
+
+    /// Number of the state the buffer is in, 0 being the text as loaded.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Opens a group if there isn't one, remembering `cursor` as where to go back to.
+    pub fn begin(&mut self, cursor: Position) {
+        self.pending.get_or_insert_with(|| UndoNode {
+            parent: self.current,
+            changes: Vec::new(),
+            cursor,
+            time: SystemTime::now(),
+            redo_child: None,
+        });
+    }
+
+    pub fn record(&mut self, change: Change) {
+        self.begin(change.start);
+        let pending = self.pending.as_mut().unwrap();
+        // Typing a word shouldn't leave one change per char behind.
+        if let Some(last) = pending.changes.last_mut()
+            && last.removed.is_empty()
+            && change.removed.is_empty()
+            && !last.inserted.contains('\n')
+            && last.start.row == change.start.row
+            && last.start.col + last.inserted.chars().count() == change.start.col
+        {
+            last.inserted.push_str(&change.inserted);
+            return;
+        }
+        pending.changes.push(change);
+    }
+
+    /// Closes the open group, making it a new state. Empty groups are dropped.
+    pub fn commit(&mut self) {
+        let Some(mut node) = self.pending.take() else {
+            return;
+        };
+        if node.changes.is_empty() {
+            return;
+        }
+        node.time = SystemTime::now();
+        let idx = self.nodes.len();
+        self.nodes[node.parent].redo_child = Some(idx);
+        self.nodes.push(node);
+        self.current = idx;
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.pending
+            .as_ref()
+            .is_some_and(|node| !node.changes.is_empty())
+    }
+
+    /// Steps for `u`: back to the parent state.
+    pub fn undo(&mut self) -> Option<Steps> {
+        self.commit();
+        if self.current == 0 {
+            return None;
+        }
+        Some(self.goto(self.nodes[self.current].parent))
+    }
+
+    /// Steps for Ctrl-R: forward along the most recently used branch.
+    pub fn redo(&mut self) -> Option<Steps> {
+        self.commit();
+        let child = self.nodes[self.current].redo_child?;
+        Some(self.goto(child))
+    }
+
+    /// Steps for `g-`/`g+` and counted `:earlier`/`:later`: `delta` states back or forward in
+    /// creation order, whichever branch they're on.
+    pub fn step_chronologically(&mut self, delta: isize) -> Option<Steps> {
+        self.commit();
+        let target = self
+            .current
+            .saturating_add_signed(delta)
+            .min(self.nodes.len() - 1);
+        (target != self.current).then(|| self.goto(target))
+    }
+
+    /// Steps for `:earlier {time}` (`back`) and `:later {time}`: the newest state that existed
+    /// `offset` before or after the current one was made.
+    pub fn travel_in_time(&mut self, offset: Duration, back: bool) -> Option<Steps> {
+        self.commit();
+        let now = self.nodes[self.current].time;
+        let when = if back {
+            Some(now.checked_sub(offset).unwrap_or(SystemTime::UNIX_EPOCH))
+        } else {
+            now.checked_add(offset)
+        };
+        // Too far ahead for a `SystemTime` is past the newest state.
+        let target = match when {
+            Some(when) => self
+                .nodes
+                .iter()
+                .rposition(|node| node.time <= when)
+                .unwrap_or(0),
+            None => self.nodes.len() - 1,
+        };
+        (target != self.current).then(|| self.goto(target))
+    }
+
+    /// Moves to `target`, undoing up to the common ancestor and redoing down from there. Redo
+    /// pointers along the way are switched to the branch that was taken.
+    fn goto(&mut self, target: usize) -> Steps {
+        let ancestors = |mut node: usize| {
+            let mut path = vec![node];
+            while node != 0 {
+                node = self.nodes[node].parent;
+                path.push(node);
+            }
+            path
+        };
+        let from = ancestors(self.current);
+        let to = ancestors(target);
+        let common = *from.iter().find(|node| to.contains(node)).unwrap();
+
+        let undo: Vec<usize> = from.iter().take_while(|&&n| n != common).copied().collect();
+        let mut redo: Vec<usize> = to.iter().take_while(|&&n| n != common).copied().collect();
+        redo.reverse();
+        for &node in &redo {
+            let parent = self.nodes[node].parent;
+            self.nodes[parent].redo_child = Some(node);
+        }
+        self.current = target;
+        Steps { undo, redo }
+    }
+}
+
+/// Parses the argument of `:earlier`/`:later`: a plain count of states, or a number followed by
+/// `s`, `m`, `h` or `d`.
+pub fn parse_time_travel(arg: &str) -> Option<TimeTravel> {
+    let arg = arg.trim();
+    if arg.is_empty() {
+        return Some(TimeTravel::Steps(1));
+    }
+    let unit_at = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    let n: u64 = arg[..unit_at].parse().ok()?;
+    let secs = match &arg[unit_at..] {
+        "" => return Some(TimeTravel::Steps(n as usize)),
+        "s" => n,
+        // Longer ago than anything in the history is as good as forever.
+        "m" => n.saturating_mul(60),
+        "h" => n.saturating_mul(60 * 60),
+        "d" => n.saturating_mul(60 * 60 * 24),
+        _ => return None,
+    };
+    Some(TimeTravel::Time(Duration::from_secs(secs)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeTravel {
+    Steps(usize),
+    Time(Duration),
+}
@@ -0,0 +1,227 @@
+//! Undo history kept across sessions. There's one file per edited file in the undo directory,
+//! named after its flattened path. Each records the hash of the contents it was written for and
+//! is only used if the file still hashes the same.
+//!
+//! The format is line based with length-prefixed text so any content survives:
+//!
+//! ```text
+//! editui-undo 1
+//! hash <hex> current <state>
+//! node <parent> <row> <col> <secs> <nanos> <redo child or -> <changes>
+//! change <row> <col> <removed bytes> <inserted bytes>
+//! <removed><inserted>
+//! ```
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use thiserror::Error;
+
+use super::{
+    Position,
+    undo::{Change, UndoNode, UndoTree},
+};
+use crate::{
+    fileio::{self, WriteOptions},
+    paths,
+};
+
+const MAGIC: &str = "editui-undo 1\n";
+
+#[derive(Debug, Error)]
+pub enum UndoFileError {
+    #[error("Could not access undo file {0}: [{1}]")]
+    Io(PathBuf, io::Error),
+    #[error("Undo file {0} is corrupt, ignoring it")]
+    Corrupt(PathBuf),
+    #[error("File contents changed, not using undo file {0}")]
+    Mismatch(PathBuf),
+}
+
+pub fn undo_file_path(undo_dir: &Path, file: &Path) -> PathBuf {
+    undo_dir.join(paths::flatten(file))
+}
+
+pub fn write(
+    undo_dir: &Path,
+    file: &Path,
+    hash: u64,
+    tree: &UndoTree,
+) -> Result<(), UndoFileError> {
+    let path = undo_file_path(undo_dir, file);
+    let io_err = |e| UndoFileError::Io(path.clone(), e);
+    fs::create_dir_all(undo_dir).map_err(io_err)?;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC.as_bytes());
+    out.extend_from_slice(format!("hash {hash:016x} current {}\n", tree.current()).as_bytes());
+    for node in tree.nodes() {
+        let time = node
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let redo = node
+            .redo_child
+            .map_or_else(|| "-".to_owned(), |child| child.to_string());
+        out.extend_from_slice(
+            format!(
+                "node {} {} {} {} {} {redo} {}\n",
+                node.parent,
+                node.cursor.row,
+                node.cursor.col,
+                time.as_secs(),
+                time.subsec_nanos(),
+                node.changes.len()
+            )
+            .as_bytes(),
+        );
+        for change in &node.changes {
+            out.extend_from_slice(
+                format!(
+                    "change {} {} {} {}\n",
+                    change.start.row,
+                    change.start.col,
+                    change.removed.len(),
+                    change.inserted.len()
+                )
+                .as_bytes(),
+            );
+            out.extend_from_slice(change.removed.as_bytes());
+            out.extend_from_slice(change.inserted.as_bytes());
+            out.push(b'\n');
+        }
+    }
+    fileio::atomic_write(&path, &out, &WriteOptions::default()).map_err(io_err)
+}
+
+/// Reads the undo history for `file`. `Ok(None)` means there simply isn't one.
+pub fn read(undo_dir: &Path, file: &Path, hash: u64) -> Result<Option<UndoTree>, UndoFileError> {
+    let path = undo_file_path(undo_dir, file);
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(UndoFileError::Io(path, e)),
+    };
+    let mut reader = Reader { bytes: &bytes };
+    match reader.parse(hash) {
+        Some(Parsed::Tree(tree)) => Ok(Some(tree)),
+        Some(Parsed::Mismatch) => Err(UndoFileError::Mismatch(path)),
+        None => Err(UndoFileError::Corrupt(path)),
+    }
+}
+
+enum Parsed {
+    Tree(UndoTree),
+    Mismatch,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn parse(&mut self, expected_hash: u64) -> Option<Parsed> {
+        if self.line()? != MAGIC.trim_end() {
+            return None;
+        }
+        let header = self.line()?;
+        let mut fields = header.split(' ');
+        let hash = match (fields.next()?, fields.next()?) {
+            ("hash", hash) => u64::from_str_radix(hash, 16).ok()?,
+            _ => return None,
+        };
+        if hash != expected_hash {
+            return Some(Parsed::Mismatch);
+        }
+        let current: usize = match (fields.next()?, fields.next()?) {
+            ("current", current) => current.parse().ok()?,
+            _ => return None,
+        };
+
+        let mut nodes = Vec::new();
+        while !self.bytes.is_empty() {
+            let line = self.line()?;
+            let mut fields = line.split(' ');
+            if fields.next()? != "node" {
+                return None;
+            }
+            let parent: usize = field(&mut fields)?;
+            let cursor = Position::new(field(&mut fields)?, field(&mut fields)?);
+            let secs = field(&mut fields)?;
+            let nanos: u32 = field(&mut fields)?;
+            if nanos >= 1_000_000_000 {
+                return None;
+            }
+            let time = SystemTime::UNIX_EPOCH.checked_add(Duration::new(secs, nanos))?;
+            let redo_child = match fields.next()? {
+                "-" => None,
+                child => Some(child.parse().ok()?),
+            };
+            let count: usize = field(&mut fields)?;
+            let mut changes = Vec::with_capacity(count.min(1024));
+            for _ in 0..count {
+                changes.push(self.change()?);
+            }
+            nodes.push(UndoNode {
+                parent,
+                changes,
+                cursor,
+                time,
+                redo_child,
+            });
+        }
+
+        // Links have to point at nodes that exist, and parents always come first.
+        let valid = !nodes.is_empty()
+            && current < nodes.len()
+            && nodes.iter().enumerate().all(|(i, node)| {
+                (i == 0 || node.parent < i) && node.redo_child.is_none_or(|c| c < nodes.len())
+            });
+        valid.then(|| Parsed::Tree(UndoTree::from_nodes(nodes, current)))
+    }
+
+    fn change(&mut self) -> Option<Change> {
+        let line = self.line()?;
+        let mut fields = line.split(' ');
+        if fields.next()? != "change" {
+            return None;
+        }
+        let start = Position::new(field(&mut fields)?, field(&mut fields)?);
+        let removed_len: usize = field(&mut fields)?;
+        let inserted_len: usize = field(&mut fields)?;
+        let removed = self.take(removed_len)?;
+        let inserted = self.take(inserted_len)?;
+        if self.take(1)? != "\n" {
+            return None;
+        }
+        Some(Change {
+            start,
+            removed,
+            inserted,
+        })
+    }
+
+    fn line(&mut self) -> Option<String> {
+        let end = self.bytes.iter().position(|&b| b == b'\n')?;
+        let line = self.take(end)?;
+        self.bytes = &self.bytes[1..];
+        Some(line)
+    }
+
+    fn take(&mut self, len: usize) -> Option<String> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (text, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        String::from_utf8(text.to_vec()).ok()
+    }
+}
+
+fn field<'a, T: FromStr>(fields: &mut impl Iterator<Item = &'a str>) -> Option<T> {
+    fields.next()?.parse().ok()
+}
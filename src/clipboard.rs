@@ -0,0 +1,159 @@
+//! The system clipboard, which the `"+` and `"*` registers read and write.
+//!
+//! On Windows that's the Win32 clipboard. Elsewhere it's a pair of programs that copy what's
+//! written to them and print what's there, `wl-copy`/`wl-paste` or `xclip` by default. Without
+//! those, text is sent to the terminal with OSC 52, which can't be read back.
+
+use std::{
+    env,
+    io::{self, Write},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use thiserror::Error;
+
+use crate::{
+    command::{Command as _, SetClipboard},
+    sys,
+};
+
+#[derive(Debug, Error)]
+pub enum ClipboardError {
+    #[error(transparent)]
+    System(#[from] sys::ClipboardError),
+    #[error("Could not run {0}: {1}")]
+    Spawn(String, io::Error),
+    #[error("{0} failed: {1}")]
+    Failed(String, ExitStatus),
+    #[error("Could not send the clipboard to the terminal: {0}")]
+    Terminal(sys::ConsoleError),
+}
+
+/// What the clipboard is reached through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Provider {
+    /// The OS's own clipboard.
+    System,
+    /// Programs taking the text to copy on stdin and printing the clipboard on stdout, each
+    /// a program followed by its arguments.
+    Commands {
+        copy: Vec<String>,
+        paste: Vec<String>,
+    },
+    /// The terminal, with OSC 52. Write-only.
+    Osc52,
+}
+
+impl Provider {
+    /// `$EDITUI_CLIPBOARD_COPY` and `$EDITUI_CLIPBOARD_PASTE` when both are set. Otherwise the
+    /// Win32 clipboard on Windows, elsewhere wl-clipboard or xclip when there's a display to
+    /// talk to, OSC 52 when there isn't.
+    pub fn detect() -> Self {
+        if let (Some(copy), Some(paste)) = (
+            env::var_os("EDITUI_CLIPBOARD_COPY"),
+            env::var_os("EDITUI_CLIPBOARD_PASTE"),
+        ) {
+            return Self::commands(&copy.to_string_lossy(), &paste.to_string_lossy());
+        }
+        if cfg!(windows) {
+            Self::System
+        } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+            Self::commands("wl-copy", "wl-paste --no-newline")
+        } else if env::var_os("DISPLAY").is_some() {
+            Self::commands(
+                "xclip -selection clipboard",
+                "xclip -selection clipboard -o",
+            )
+        } else {
+            Self::Osc52
+        }
+    }
+
+    /// Commands given as words separated by spaces.
+    pub fn commands(copy: &str, paste: &str) -> Self {
+        let words = |command: &str| command.split_whitespace().map(str::to_owned).collect();
+        Self::Commands {
+            copy: words(copy),
+            paste: words(paste),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Clipboard {
+    pub provider: Provider,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self {
+            provider: Provider::detect(),
+        }
+    }
+}
+
+impl Clipboard {
+    /// The text on the clipboard, `None` when it holds no text or can't be read.
+    pub fn get(&self) -> Result<Option<String>, ClipboardError> {
+        match &self.provider {
+            Provider::System => Ok(sys::get_clipboard()?),
+            Provider::Commands { paste, .. } => {
+                let (program, args) = split(paste)?;
+                let output = Command::new(program)
+                    .args(args)
+                    .stdin(Stdio::null())
+                    .stderr(Stdio::null())
+                    .output()
+                    .map_err(|e| ClipboardError::Spawn(program.clone(), e))?;
+                if !output.status.success() {
+                    return Err(ClipboardError::Failed(program.clone(), output.status));
+                }
+                Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+            }
+            Provider::Osc52 => Ok(None),
+        }
+    }
+
+    pub fn set(&self, text: &str) -> Result<(), ClipboardError> {
+        match &self.provider {
+            Provider::System => Ok(sys::set_clipboard(text)?),
+            Provider::Commands { copy, .. } => {
+                let (program, args) = split(copy)?;
+                let spawn_error = |e| ClipboardError::Spawn(program.clone(), e);
+                let mut child = Command::new(program)
+                    .args(args)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .map_err(spawn_error)?;
+                // Dropping stdin closes it, which is how the program knows it has everything.
+                let written = child
+                    .stdin
+                    .take()
+                    .map_or(Ok(()), |mut stdin| stdin.write_all(text.as_bytes()));
+                let status = child.wait().map_err(spawn_error)?;
+                written.map_err(spawn_error)?;
+                if !status.success() {
+                    return Err(ClipboardError::Failed(program.clone(), status));
+                }
+                Ok(())
+            }
+            Provider::Osc52 => {
+                let mut escape = String::new();
+                // Writing to a String can't fail.
+                let _ = SetClipboard(text).write_ansi(&mut escape);
+                sys::write_stdout(&escape).map_err(ClipboardError::Terminal)
+            }
+        }
+    }
+}
+
+fn split(command: &[String]) -> Result<(&String, &[String]), ClipboardError> {
+    command.split_first().ok_or_else(|| {
+        ClipboardError::Spawn(
+            String::new(),
+            io::Error::new(io::ErrorKind::InvalidInput, "no clipboard command"),
+        )
+    })
+}
@@ -18,20 +18,22 @@ impl Command for Clear {
         match self.0 {
             // To also clear the scroll back, emit L"\x1b[3j" as well.
             // 2j only clears the visible window and 3j only clears the scroll back.
-            ClearType::All => write!(&mut writer, "{CSI}2j")?,
+            ClearType::All => write!(&mut writer, "{CSI}2J")?,
             ClearType::StartTillCursor => {
-                write!(&mut writer, "{CSI}1j")?;
+                write!(&mut writer, "{CSI}1J")?;
             }
             ClearType::CursorTillEnd => {
-                write!(&mut writer, "{CSI}j")?;
+                write!(&mut writer, "{CSI}J")?;
             }
+            ClearType::CurrentLine => write!(&mut writer, "{CSI}2K")?,
         }
         Ok(())
         // escape_sequences ->
-        //      \x1b[j - clears from the cursor to the end
-        //      \x1b[0j - same as \x1b[j
-        //      \x1b[1j - clears upto the cursor
-        //      \x1b[2j - Clear Screen
+        //      \x1b[J - clears from the cursor to the end
+        //      \x1b[0J - same as \x1b[J
+        //      \x1b[1J - clears upto the cursor
+        //      \x1b[2J - Clear Screen
+        //      \x1b[2K - clears the line the cursor is on
         // \0x1B is the hexadecimal value of ESC
     }
 }
@@ -39,18 +41,40 @@ pub enum ClearType {
     All,
     StartTillCursor,
     CursorTillEnd,
+    CurrentLine,
+}
+/// Cursor shape, DECSCUSR. Terminals that don't know it ignore it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorStyle {
+    /// Whatever the user's terminal is configured with.
+    Default,
+    Block,
+    Underline,
+    Bar,
+}
+impl Command for CursorStyle {
+    fn write_ansi<T: fmt::Write>(&self, mut writer: &mut T) -> fmt::Result {
+        let shape = match self {
+            CursorStyle::Default => 0,
+            CursorStyle::Block => 2,
+            CursorStyle::Underline => 4,
+            CursorStyle::Bar => 6,
+        };
+        write!(&mut writer, "{CSI}{shape} q")?;
+        Ok(())
+    }
 }
 pub struct MoveUp(pub u32);
 impl Command for MoveUp {
     fn write_ansi<T: fmt::Write>(&self, mut writer: &mut T) -> Result<(), fmt::Error> {
-        write!(&mut writer, "{CSI}{}a", self.0)?;
+        write!(&mut writer, "{CSI}{}A", self.0)?;
         Ok(())
     }
 }
 pub struct MoveDown(pub u32);
 impl Command for MoveDown {
     fn write_ansi<T: fmt::Write>(&self, mut writer: &mut T) -> fmt::Result {
-        write!(&mut writer, "{CSI}{}b", self.0)?;
+        write!(&mut writer, "{CSI}{}B", self.0)?;
         Ok(())
     }
 }
@@ -60,7 +84,8 @@ pub struct MoveTo {
 }
 impl Command for MoveTo {
     fn write_ansi<T: fmt::Write>(&self, mut writer: &mut T) -> fmt::Result {
-        write!(&mut writer, "{CSI}{};{}h", self.y, self.x)?;
+        // CUP is 1-based, MoveTo is 0-based like the rest of the editor.
+        write!(&mut writer, "{CSI}{};{}H", self.y + 1, self.x + 1)?;
         Ok(())
     }
 }
@@ -69,3 +94,31 @@ impl MoveTo {
         Self { x, y }
     }
 }
+/// OSC 52: asks the terminal to put text on the system clipboard, which works over ssh too.
+/// Terminals that don't support it ignore it.
+pub struct SetClipboard<'a>(pub &'a str);
+impl Command for SetClipboard<'_> {
+    fn write_ansi<T: fmt::Write>(&self, mut writer: &mut T) -> fmt::Result {
+        write!(&mut writer, "{ESC}]52;c;")?;
+        base64(self.0.as_bytes(), writer)?;
+        writer.write_char('\x07')
+    }
+}
+
+fn base64<T: fmt::Write>(bytes: &[u8], writer: &mut T) -> fmt::Result {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3f;
+                writer.write_char(ALPHABET[index as usize] as char)?;
+            } else {
+                writer.write_char('=')?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+//! Line diff (Myers' algorithm), for showing what changed on disk under a modified buffer.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Same,
+    Removed,
+    Added,
+}
+
+/// Past this many differing lines the diff just reports everything as replaced; the
+/// backtracking data grows with the square of it, to some 8 MB here.
+const MAX_EDIT_DISTANCE: usize = 1024;
+
+/// The edit script turning `old` into `new`, one entry per line of either.
+pub fn diff<S: AsRef<str>>(old: &[S], new: &[S]) -> Vec<(Edit, usize)> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = ((n + m) as usize).min(MAX_EDIT_DISTANCE) as isize;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+
+    let mut found = false;
+    'outer: for d in 0..=max {
+        // Only diagonals -d - 1..=d + 1 matter for backtracking from step `d`.
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize].as_ref() == new[y as usize].as_ref() {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'outer;
+            }
+        }
+    }
+    if !found {
+        let mut edits: Vec<_> = (0..old.len()).map(|i| (Edit::Removed, i)).collect();
+        edits.extend((0..new.len()).map(|i| (Edit::Added, i)));
+        return edits;
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let offset = d + 1;
+        let k = x - y;
+        let prev_k =
+            if k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]) {
+                k + 1
+            } else {
+                k - 1
+            };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push((Edit::Same, x as usize));
+        }
+        if d > 0 {
+            if x == prev_x {
+                edits.push((Edit::Added, prev_y as usize));
+            } else {
+                edits.push((Edit::Removed, prev_x as usize));
+            }
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    edits.reverse();
+    edits
+}
+
+/// `diff -u` style hunks with `context` unchanged lines around each change.
+pub fn unified<S: AsRef<str>>(old: &[S], new: &[S], context: usize) -> Vec<String> {
+    let edits = diff(old, new);
+    // Line number in old and new at each edit.
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut o, mut n) = (0, 0);
+    for &(edit, _) in &edits {
+        positions.push((o, n));
+        match edit {
+            Edit::Same => {
+                o += 1;
+                n += 1;
+            }
+            Edit::Removed => o += 1,
+            Edit::Added => n += 1,
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < edits.len() {
+        if edits[i].0 == Edit::Same {
+            i += 1;
+            continue;
+        }
+        let start = i.saturating_sub(context);
+        let mut end = i;
+        // Extend the hunk while the next change is within two contexts' reach.
+        loop {
+            while end < edits.len() && edits[end].0 != Edit::Same {
+                end += 1;
+            }
+            let next_change = edits[end..]
+                .iter()
+                .position(|&(edit, _)| edit != Edit::Same)
+                .map(|p| end + p);
+            match next_change {
+                Some(next) if next - end <= 2 * context => end = next,
+                _ => break,
+            }
+        }
+        let end = (end + context).min(edits.len());
+
+        let (old_start, new_start) = positions[start];
+        let old_len = edits[start..end]
+            .iter()
+            .filter(|e| e.0 != Edit::Added)
+            .count();
+        let new_len = edits[start..end]
+            .iter()
+            .filter(|e| e.0 != Edit::Removed)
+            .count();
+        out.push(format!(
+            "@@ -{},{old_len} +{},{new_len} @@",
+            old_start + 1,
+            new_start + 1
+        ));
+        for &(edit, idx) in &edits[start..end] {
+            out.push(match edit {
+                Edit::Same => format!(" {}", old[idx].as_ref()),
+                Edit::Removed => format!("-{}", old[idx].as_ref()),
+                Edit::Added => format!("+{}", new[idx].as_ref()),
+            });
+        }
+        i = end;
+    }
+    out
+}
@@ -0,0 +1,246 @@
+//! Turns buffer text into terminal cells. Cursor columns count chars, but on screen a tab, a
+//! wide char or a `<xx>` placeholder for an undecodable byte takes several cells.
+
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::buffer::encoding;
+use crate::command::CSI;
+
+/// How far apart tab stops are unless `tabstop` says otherwise.
+pub const TAB_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Selection,
+    /// A match of the last search.
+    Match,
+    /// The match the cursor is on, or that a search typed so far finds.
+    CurrentMatch,
+    /// Line numbers, with `number`.
+    LineNumber,
+}
+
+/// The colors styles are drawn in, picked with `colorscheme`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorScheme {
+    #[default]
+    Default,
+    /// No colors, for terminals without them: only reverse video, bold and underline.
+    Mono,
+}
+
+impl FromStr for ColorScheme {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "default" => Ok(Self::Default),
+            "mono" => Ok(Self::Mono),
+            _ => Err(format!("Cannot find color scheme '{name}'")),
+        }
+    }
+}
+
+impl fmt::Display for ColorScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Default => "default",
+            Self::Mono => "mono",
+        })
+    }
+}
+
+impl Style {
+    fn sgr(self, colors: ColorScheme) -> &'static str {
+        match (colors, self) {
+            (_, Self::Selection) => "7",
+            (ColorScheme::Default, Self::Match) => "30;43",
+            (ColorScheme::Default, Self::CurrentMatch) => "30;45",
+            (ColorScheme::Default, Self::LineNumber) => "33",
+            (ColorScheme::Mono, Self::Match) => "4",
+            (ColorScheme::Mono, Self::CurrentMatch) => "1;7",
+            (ColorScheme::Mono, Self::LineNumber) => "2",
+        }
+    }
+}
+
+/// How text is drawn: which screen columns of a line show, how wide tabs are and in what
+/// colors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct View {
+    /// The screen columns of the line to show, as counted from its start.
+    pub cols: Range<usize>,
+    pub tabstop: usize,
+    pub colors: ColorScheme,
+}
+
+/// Chars `cols` of a line drawn in `style`. A span reaching past the end of the line shows as
+/// one styled cell there, the way an empty line looks selected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub cols: Range<usize>,
+    pub style: Style,
+}
+
+/// Screen text for `line`, at most `width` cells wide.
+pub fn render_line(line: &str, width: usize) -> String {
+    let view = View {
+        cols: 0..width,
+        tabstop: TAB_WIDTH,
+        colors: ColorScheme::Default,
+    };
+    render_spans(line, &view, &[])
+}
+
+/// The columns of `line` in `view`, with parts of it styled. Later spans win where they
+/// overlap. Of a tab or wide char cut off at either side, what shows is blank.
+pub fn render_spans(line: &str, view: &View, spans: &[Span]) -> String {
+    let style_at = |i: usize| {
+        spans
+            .iter()
+            .rev()
+            .find(|span| span.cols.contains(&i))
+            .map(|span| span.style)
+    };
+    let View { cols, tabstop, .. } = view;
+    let mut out = String::new();
+    let mut col = 0;
+    let mut current = None;
+    let mut len = 0;
+    for (i, ch) in line.chars().enumerate() {
+        let (text, cells) = cell(ch, col, *tabstop);
+        len = i + 1;
+        let end = col + cells;
+        if end > cols.end {
+            if col < cols.end {
+                set_style(&mut out, &mut current, style_at(i), view.colors);
+                out.push_str(&" ".repeat(cols.end - col.max(cols.start)));
+            }
+            col = end;
+            break;
+        }
+        if end > cols.start {
+            set_style(&mut out, &mut current, style_at(i), view.colors);
+            if col >= cols.start {
+                out.push_str(&text);
+            } else {
+                out.push_str(&" ".repeat(end - cols.start));
+            }
+        }
+        col = end;
+    }
+    if col < cols.end
+        && col >= cols.start
+        && let Some(style) = style_at(len)
+    {
+        set_style(&mut out, &mut current, Some(style), view.colors);
+        out.push(' ');
+    }
+    set_style(&mut out, &mut current, None, view.colors);
+    out
+}
+
+/// Line number `number` in a gutter `width` cells wide.
+pub fn render_number(number: Option<usize>, width: usize, colors: ColorScheme) -> String {
+    let mut out = String::new();
+    let mut current = None;
+    set_style(&mut out, &mut current, Some(Style::LineNumber), colors);
+    match number {
+        Some(number) => out.push_str(&format!("{number:>0$} ", width - 1)),
+        None => out.push_str(&" ".repeat(width)),
+    }
+    set_style(&mut out, &mut current, None, colors);
+    out
+}
+
+fn set_style(
+    out: &mut String,
+    current: &mut Option<Style>,
+    style: Option<Style>,
+    colors: ColorScheme,
+) {
+    if *current == style {
+        return;
+    }
+    match style {
+        Some(style) => out.push_str(&format!("{CSI}0;{}m", style.sgr(colors))),
+        None => out.push_str(&format!("{CSI}0m")),
+    }
+    *current = style;
+}
+
+/// Screen column at which char `col` of `line` starts.
+pub fn screen_col(line: &str, col: usize, tabstop: usize) -> usize {
+    line.chars()
+        .take(col)
+        .fold(0, |screen, ch| screen + cell_width(ch, screen, tabstop))
+}
+
+/// How many cells `text` takes on screen.
+pub fn width(text: &str, tabstop: usize) -> usize {
+    screen_col(text, usize::MAX, tabstop)
+}
+
+/// The char of `line` drawn on screen column `screen`, or the length of the line if it ends
+/// before.
+pub fn char_at(line: &str, screen: usize, tabstop: usize) -> usize {
+    let mut col = 0;
+    for (i, ch) in line.chars().enumerate() {
+        col += cell_width(ch, col, tabstop);
+        if col > screen {
+            return i;
+        }
+    }
+    line.chars().count()
+}
+
+/// How many cells `ch` takes starting at screen column `screen_col`. Combining marks take
+/// none, they go on the char before them.
+pub fn char_width(ch: char, screen_col: usize, tabstop: usize) -> usize {
+    cell_width(ch, screen_col, tabstop)
+}
+
+/// What `ch` starting at screen column `screen_col` is drawn as, and how many cells that
+/// takes.
+fn cell(ch: char, screen_col: usize, tabstop: usize) -> (String, usize) {
+    let cells = cell_width(ch, screen_col, tabstop);
+    if ch == '\t' {
+        return (" ".repeat(cells), cells);
+    }
+    if let Some(placeholder) = encoding::placeholder(ch) {
+        return (placeholder, cells);
+    }
+    if ch.is_ascii_control() {
+        // Shown the way vim does, ^M for a stray CR and so on.
+        let caret = format!("^{}", ((ch as u8) ^ 0x40) as char);
+        return (caret, cells);
+    }
+    if ch.is_control() {
+        // C1 controls would be taken as escape sequences by some terminals.
+        let code = format!("<u{:04x}>", ch as u32);
+        return (code, cells);
+    }
+    (ch.to_string(), cells)
+}
+
+/// The cells of [`cell`] alone, which measuring a line needs without drawing it.
+fn cell_width(ch: char, screen_col: usize, tabstop: usize) -> usize {
+    if ch == '\t' {
+        let tabstop = tabstop.max(1);
+        return tabstop - screen_col % tabstop;
+    }
+    if let Some(cells) = encoding::placeholder_width(ch) {
+        return cells;
+    }
+    if ch.is_ascii_control() {
+        2
+    } else if ch.is_control() {
+        7
+    } else {
+        ch.width().unwrap_or(1)
+    }
+}
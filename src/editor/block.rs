@@ -0,0 +1,170 @@
+//! Visual block mode. A block is a rectangle on screen, so its sides are screen columns rather
+//! than chars: a tab or a wide char the side of a block goes through is split into spaces, or
+//! taken whole where it can't be.
+
+use std::ops::Range;
+
+use super::Editor;
+use super::mode::{Mode, VisualKind};
+use crate::buffer::{Buffer, Position};
+use crate::display;
+
+/// Where a line meets the screen columns of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Part {
+    /// Chars `start..end` are at least partly in the block. When none are, both are the char
+    /// after the ones left of the block.
+    pub start: usize,
+    pub end: usize,
+    /// Cells of the first of those chars left of the block, and of the last right of it.
+    pub before: usize,
+    pub after: usize,
+    /// Cells missing for the line to reach the block, when it ends before.
+    pub short: usize,
+}
+
+impl Part {
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// The chars of `line` with the screen cells each covers. Marks that take no cells of their
+/// own share those of the char before them.
+fn cells(line: &str, tabstop: usize) -> impl Iterator<Item = (char, Range<usize>)> + '_ {
+    let mut cells = 0..0;
+    line.chars().map(move |ch| {
+        let width = display::char_width(ch, cells.end, tabstop);
+        if width > 0 {
+            cells = cells.end..cells.end + width;
+        }
+        (ch, cells.clone())
+    })
+}
+
+/// The screen cells char `col` of `line` covers, a single one for a column past its end.
+pub fn cells_at(line: &str, col: usize, tabstop: usize) -> Range<usize> {
+    match cells(line, tabstop).nth(col) {
+        Some((_, cells)) if !cells.is_empty() => cells,
+        Some((_, cells)) => cells.start..cells.start + 1,
+        None => {
+            let start = display::width(line, tabstop) + (col - line.chars().count());
+            start..start + 1
+        }
+    }
+}
+
+/// How `line` meets screen columns `left..right`.
+pub fn part(line: &str, left: usize, right: usize, tabstop: usize) -> Part {
+    let mut part = Part::default();
+    let mut found = false;
+    let mut width = 0;
+    for (i, (_, cells)) in cells(line, tabstop).enumerate() {
+        if cells.start >= right && !cells.is_empty() {
+            return part;
+        }
+        width = cells.end;
+        if cells.end <= left {
+            part.start = i + 1;
+            part.end = i + 1;
+            continue;
+        }
+        if !found {
+            part.start = i;
+            part.before = left.saturating_sub(cells.start);
+            found = true;
+        }
+        part.end = i + 1;
+        part.after = cells.end.saturating_sub(right);
+    }
+    if !found {
+        part.short = left.saturating_sub(width);
+    }
+    part
+}
+
+/// What of `line` is in screen columns `left..right`. Of a tab or wide char sticking out of
+/// them, what's in them turns into spaces.
+pub fn text(line: &str, left: usize, right: usize, tabstop: usize) -> String {
+    cells(line, tabstop)
+        .filter(|(_, cells)| cells.start < right && cells.end > left)
+        .map(|(ch, cells)| {
+            if cells.start < left || cells.end > right {
+                " ".repeat(cells.end.min(right) - cells.start.max(left))
+            } else {
+                ch.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Makes screen column `col` of `row` the start of a char and returns which, for text to go
+/// in there. A tab the column goes through is split into spaces, and a line that ends before
+/// it filled up with them. `usize::MAX` is the end of the line.
+pub fn split_at(buffer: &mut Buffer, row: usize, col: usize, tabstop: usize) -> usize {
+    let len = buffer.line_len(row);
+    if col == usize::MAX {
+        return len;
+    }
+    let line = buffer.line(row).unwrap_or_default();
+    let part = part(&line, col, col + 1, tabstop);
+    let tab = line.chars().nth(part.start) == Some('\t');
+    if part.short > 0 {
+        buffer.insert(Position::new(row, len), &" ".repeat(part.short));
+        return len + part.short;
+    }
+    if part.before == 0 || !tab {
+        return part.start;
+    }
+    let spaces = " ".repeat(part.before + 1 + part.after);
+    buffer.replace(
+        Position::new(row, part.start),
+        Position::new(row, part.start + 1),
+        &spaces,
+    );
+    part.start + part.before
+}
+
+impl Editor {
+    /// `I` and `A` in Visual block mode: what's typed goes in before or after the block on
+    /// every line. Lines too short to reach the block are left alone by `I`, and padded with
+    /// spaces by `A`, which after `$` appends to the end of each line.
+    pub(super) fn block_insert(&mut self, append: bool) {
+        let region = self.selection(VisualKind::Block);
+        self.mode = Mode::Normal;
+        let (left, right) = region.block_cols();
+        let (first, last) = (region.start.row, region.end.row);
+        let doc = self.doc();
+        let reaches = |&row: &usize| {
+            let line = doc.buffer.line(row).unwrap_or_default();
+            !part(&line, left, right, doc.options.tabstop).is_empty()
+        };
+        let others = (first + 1..=last).filter(|row| append || reaches(row));
+        let rows = std::iter::once(first).chain(others).collect();
+        self.insert_block(if append { right } else { left }, rows);
+    }
+
+    /// `O` in Visual block mode: the cursor goes to the other corner on the same line. In the
+    /// other Visual modes it's `o`.
+    pub(super) fn swap_selection_corner(&mut self) {
+        let anchor = self.visual_anchor;
+        let doc = self.buffers.current_mut();
+        if self.mode != Mode::Visual(VisualKind::Block) {
+            std::mem::swap(&mut doc.cursor, &mut self.visual_anchor);
+        } else {
+            let (cursor, tabstop) = (doc.cursor, doc.options.tabstop);
+            let col_at = |pos: Position, screen: usize| {
+                let line = doc.buffer.line(pos.row).unwrap_or_default();
+                display::char_at(&line, screen, tabstop)
+            };
+            let screen = |pos: Position| {
+                let line = doc.buffer.line(pos.row).unwrap_or_default();
+                cells_at(&line, pos.col, tabstop).start
+            };
+            let (cursor_screen, anchor_screen) = (screen(cursor), screen(anchor));
+            self.visual_anchor.col = col_at(anchor, cursor_screen);
+            doc.cursor.col = col_at(cursor, anchor_screen);
+        }
+        doc.remember_col();
+    }
+}
@@ -0,0 +1,355 @@
+//! Command-line mode: typing an Ex command after `:`, or a search pattern after `/` or `?`.
+
+use super::Editor;
+use super::keys::{self, ctrl};
+use super::mode::Mode;
+use super::motion::Motion;
+use super::search::{self, Match, Pattern, PatternError, Search};
+use super::word::char_class;
+use crate::buffer::Position;
+
+/// How many lines each history keeps.
+const HISTORY_LEN: usize = 100;
+
+/// What the line being typed is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Prompt {
+    #[default]
+    Ex,
+    Search {
+        forward: bool,
+    },
+}
+
+impl Prompt {
+    /// Shown before the text typed.
+    pub fn char(self) -> char {
+        match self {
+            Self::Ex => ':',
+            Self::Search { forward: true } => '/',
+            Self::Search { forward: false } => '?',
+        }
+    }
+}
+
+/// Lines entered before, oldest first, without duplicates.
+#[derive(Debug, Default)]
+struct History {
+    entries: Vec<String>,
+}
+
+impl History {
+    fn add(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry != line);
+        self.entries.push(line.to_owned());
+        if self.entries.len() > HISTORY_LEN {
+            self.entries.remove(0);
+        }
+    }
+}
+
+/// Where Ctrl-P and Ctrl-N are in the history: the entry shown, and the text typed before,
+/// which entries have to start with.
+#[derive(Debug)]
+struct Browsing {
+    index: usize,
+    typed: String,
+}
+
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    pub prompt: Prompt,
+    pub text: String,
+    /// Where typing goes, in chars into `text`.
+    pub cursor: usize,
+    /// The mode to go back to, which for a search can be Visual or Operator-pending mode: the
+    /// search is then the motion.
+    from: Mode,
+    /// The count typed before `/` or `?`.
+    count: Option<usize>,
+    /// The cursor and the first row on screen when the line was started, which a search
+    /// previewed while typing goes back to.
+    origin: (Position, usize),
+    /// The match the pattern typed so far finds, with `incsearch`.
+    pub preview: Option<Match>,
+    browsing: Option<Browsing>,
+    /// Ctrl-R or Ctrl-V was typed: the next key is a register to insert, or a key to insert
+    /// as it is.
+    prefix: Option<char>,
+    ex_history: History,
+    search_history: History,
+}
+
+impl CommandLine {
+    /// Whether the next key is taken as it is, after Ctrl-V or Ctrl-R, and not mapped.
+    pub fn takes_literal(&self) -> bool {
+        self.prefix.is_some()
+    }
+
+    fn history(&mut self) -> &mut History {
+        match self.prompt {
+            Prompt::Ex => &mut self.ex_history,
+            Prompt::Search { .. } => &mut self.search_history,
+        }
+    }
+
+    fn byte_index(&self, col: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(col)
+            .map_or(self.text.len(), |(idx, _)| idx)
+    }
+
+    fn insert(&mut self, text: &str) {
+        let at = self.byte_index(self.cursor);
+        self.text.insert_str(at, text);
+        self.cursor += text.chars().count();
+    }
+
+    /// Deletes from `col` up to the cursor.
+    fn delete_back_to(&mut self, col: usize) {
+        let (start, end) = (self.byte_index(col), self.byte_index(self.cursor));
+        self.text.replace_range(start..end, "");
+        self.cursor = col;
+    }
+
+    /// Ctrl-W: the word before the cursor, and the spaces after it.
+    fn word_start(&self) -> usize {
+        let before: Vec<char> = self.text.chars().take(self.cursor).collect();
+        let mut col = before.len();
+        while col > 0 && before[col - 1].is_whitespace() {
+            col -= 1;
+        }
+        if let Some(&last) = before[..col].last() {
+            let class = char_class(last);
+            while col > 0 && char_class(before[col - 1]) == class {
+                col -= 1;
+            }
+        }
+        col
+    }
+
+    /// Ctrl-P with a negative `delta`, Ctrl-N with a positive one: the next older or newer
+    /// history entry starting with what was typed, or back to what was typed past the newest.
+    fn browse(&mut self, delta: isize) {
+        let browsing = self.browsing.take().unwrap_or_else(|| Browsing {
+            index: usize::MAX,
+            typed: self.text.clone(),
+        });
+        let entries = &self.history().entries;
+        let matching = |idx: &usize| entries[*idx].starts_with(&browsing.typed);
+        let found = if delta < 0 {
+            (0..browsing.index.min(entries.len())).rev().find(matching)
+        } else {
+            (browsing.index.saturating_add(1)..entries.len()).find(matching)
+        };
+        let (index, text) = match found {
+            Some(index) => (index, entries[index].clone()),
+            // Past the oldest entry nothing changes, past the newest the typed text is back.
+            None if delta < 0 => (browsing.index, self.text.clone()),
+            None => (usize::MAX, browsing.typed.clone()),
+        };
+        self.text = text;
+        self.cursor = self.text.chars().count();
+        self.browsing = Some(Browsing { index, ..browsing });
+    }
+}
+
+impl Editor {
+    /// Starts typing a command line. An Ex command typed in Visual mode starts with the
+    /// selection's range, one typed after a count with that many lines.
+    pub(super) fn enter_command_line(&mut self, prompt: Prompt, count: Option<usize>) {
+        let text = match (prompt, self.mode, count) {
+            (Prompt::Ex, Mode::Visual(_), _) => "'<,'>".to_owned(),
+            (Prompt::Ex, _, Some(1)) => ".".to_owned(),
+            (Prompt::Ex, _, Some(count)) => format!(".,.+{}", count - 1),
+            _ => String::new(),
+        };
+        let line = &mut self.command_line;
+        line.prompt = prompt;
+        line.cursor = text.chars().count();
+        line.text = text;
+        line.from = match (prompt, self.mode) {
+            (Prompt::Ex, _) => Mode::Normal,
+            (_, mode) => mode,
+        };
+        line.count = count;
+        line.origin = (self.buffers.current().cursor, self.buffers.current().scroll);
+        line.preview = None;
+        line.browsing = None;
+        line.prefix = None;
+        self.mode = Mode::CommandLine;
+    }
+
+    pub(super) fn command_line_key(&mut self, key: char) {
+        if let Some(prefix) = self.command_line.prefix.take() {
+            if prefix == ctrl('v') {
+                if !keys::is_special(key) {
+                    self.command_line.insert(key.encode_utf8(&mut [0; 4]));
+                }
+            } else if key != keys::ESC {
+                match self.read_register(key) {
+                    // A line break goes in as Enter, as in vim, which `:normal` then types.
+                    Ok(register) => self.command_line.insert(&register.text.replace('\n', "\r")),
+                    Err(e) => self.message = e.to_string(),
+                }
+            }
+            self.preview_search();
+            return;
+        }
+        let line = &mut self.command_line;
+        if key != ctrl('p') && key != ctrl('n') {
+            line.browsing = None;
+        }
+        match key {
+            keys::ENTER => {
+                self.restore_origin();
+                let line = &mut self.command_line;
+                let text = std::mem::take(&mut line.text);
+                line.history().add(&text);
+                self.mode = line.from;
+                self.message.clear();
+                match line.prompt {
+                    Prompt::Ex => {
+                        if !text.trim().is_empty() {
+                            self.registers.command = Some(text.clone());
+                        }
+                        if let Err(e) = self.execute(&text) {
+                            self.message = e.to_string();
+                        }
+                    }
+                    Prompt::Search { forward } => self.search(text, forward),
+                }
+                self.clamp_cursor();
+            }
+            keys::ESC => self.leave_command_line(),
+            keys::BACKSPACE | keys::DEL if line.text.is_empty() => self.leave_command_line(),
+            keys::BACKSPACE | keys::DEL if line.cursor > 0 => {
+                line.delete_back_to(line.cursor - 1);
+            }
+            key if key == ctrl('w') => line.delete_back_to(line.word_start()),
+            key if key == ctrl('u') => line.delete_back_to(0),
+            key if key == ctrl('b') => line.cursor = 0,
+            key if key == ctrl('e') => line.cursor = line.text.chars().count(),
+            key if key == ctrl('p') => line.browse(-1),
+            key if key == ctrl('n') => line.browse(1),
+            key if key == ctrl('r') || key == ctrl('v') => line.prefix = Some(key),
+            key if !key.is_control() && !keys::is_special(key) => {
+                line.insert(key.encode_utf8(&mut [0; 4]))
+            }
+            _ => {}
+        }
+        if self.mode == Mode::CommandLine {
+            self.preview_search();
+        }
+    }
+
+    /// With `incsearch`, shows where the search typed so far goes: the cursor moves to the
+    /// match, or back to where it was when there's none.
+    fn preview_search(&mut self) {
+        let Prompt::Search { forward } = self.command_line.prompt else {
+            return;
+        };
+        if !self.search_options.incremental {
+            return;
+        }
+        let (cursor, _) = self.command_line.origin;
+        let text = &self.command_line.text;
+        let found = match self.compile_search(text) {
+            Ok(pattern) if !text.is_empty() => pattern.find(&self.doc().buffer, cursor, forward),
+            _ => None,
+        };
+        self.restore_origin();
+        self.command_line.preview = found;
+        if let Some((start, _)) = found {
+            self.doc_mut().cursor = start;
+        }
+    }
+
+    /// Puts the cursor and the view back to where they were before a search was previewed.
+    fn restore_origin(&mut self) {
+        if let Prompt::Search { .. } = self.command_line.prompt {
+            let (cursor, scroll) = self.command_line.origin;
+            let doc = self.doc_mut();
+            doc.cursor = cursor;
+            doc.scroll = scroll;
+            self.command_line.preview = None;
+        }
+    }
+
+    /// Back to where the command line was started from, minus any operator waiting for a
+    /// search.
+    fn leave_command_line(&mut self) {
+        self.restore_origin();
+        self.register = None;
+        self.mode = match self.command_line.from {
+            Mode::OperatorPending(_) => Mode::Normal,
+            mode => mode,
+        };
+    }
+
+    /// `/` or `?` followed by Enter: remembers the pattern, the last one if it's empty, and
+    /// moves to the next match.
+    fn search(&mut self, pattern: String, forward: bool) {
+        let pattern = match (pattern.is_empty(), &self.last_search) {
+            (false, _) => pattern,
+            (true, Some(last)) => last.pattern.clone(),
+            (true, None) => {
+                self.leave_command_line();
+                self.message = "No previous search".to_owned();
+                return;
+            }
+        };
+        self.last_search = Some(Search { pattern, forward });
+        let count = self.command_line.count;
+        let cursor = self.doc().cursor;
+        self.doc_mut().buffer.begin_change(cursor);
+        self.move_cursor(Motion::SearchNext { reverse: false }, count);
+        self.finish_normal();
+    }
+
+    /// `pattern` compiled with the case options set.
+    pub(super) fn compile_search(&self, pattern: &str) -> Result<Pattern, PatternError> {
+        Pattern::new(pattern, self.search_options.case)
+    }
+
+    /// `*` and `#`: searches for the keyword under the cursor, or the next one on the line, as
+    /// a whole word unless `whole` is off for `g*` and `g#`. Without a keyword, any other word
+    /// there will do.
+    pub(super) fn search_word(&mut self, forward: bool, whole: bool, count: Option<usize>) {
+        let cursor = self.doc().cursor;
+        let line = self.doc().buffer.line(cursor.row).unwrap_or_default();
+        let chars: Vec<char> = line.chars().collect();
+        let found = [2, 1].into_iter().find_map(|class| {
+            let at = (cursor.col..chars.len()).find(|&col| char_class(chars[col]) == class)?;
+            let start = (0..at)
+                .rev()
+                .take_while(|&col| char_class(chars[col]) == class)
+                .last()
+                .unwrap_or(at);
+            let end = (at..chars.len())
+                .find(|&col| char_class(chars[col]) != class)
+                .unwrap_or(chars.len());
+            Some((start, end, class))
+        });
+        let Some((start, end, class)) = found else {
+            self.message = "No string under cursor".to_owned();
+            self.fail();
+            return;
+        };
+        let word: String = chars[start..end].iter().collect();
+        let pattern = if whole && class == 2 {
+            format!(r"\<{}\>", search::escape(&word))
+        } else {
+            search::escape(&word)
+        };
+        self.command_line.search_history.add(&pattern);
+        self.last_search = Some(Search { pattern, forward });
+        // Going back from inside the word would only find its own start.
+        self.doc_mut().cursor.col = start;
+        self.move_cursor(Motion::SearchNext { reverse: false }, count);
+    }
+}
@@ -0,0 +1,748 @@
+//! Commands typed on the command line.
+
+use thiserror::Error;
+
+use super::Editor;
+use super::document::FindError;
+use super::keys::{self, Origin};
+use super::mapping::{self, MappingError};
+use super::marks;
+use super::mode::Mode;
+use super::motion::first_non_blank;
+use super::operator::{self, Operator, Region, RegionKind};
+use super::options::{OptionError, Target};
+use super::range::{self, LineRange, RangeError, split_pattern};
+use super::register;
+use super::search::{PatternError, Search};
+use super::substitute::SubstituteError;
+use crate::buffer::{Buffer, Position};
+
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("Not an editor command: {0}")]
+    Unknown(String),
+    #[error("No matching buffer for {0}")]
+    NoSuchBuffer(String),
+    #[error("More than one match for {0}")]
+    AmbiguousBuffer(String),
+    #[error("No alternate file")]
+    NoAlternate,
+    #[error("No write since last change (add ! to override)")]
+    Unsaved,
+    #[error("No write since last change for buffer {0} (add ! to override)")]
+    UnsavedBuffer(usize),
+    #[error("Argument required")]
+    ArgumentRequired,
+    #[error("Trailing characters: {0}")]
+    TrailingCharacters(String),
+    #[error("No range allowed")]
+    NoRange,
+    #[error("Positive count required")]
+    ZeroCount,
+    #[error("Cannot move a range of lines into itself")]
+    MoveIntoItself,
+    #[error("Regular expressions can't be delimited by letters")]
+    Delimiter,
+    #[error("Cannot do :global recursive")]
+    GlobalRecursive,
+    #[error("Pattern found in every line: {0}")]
+    FoundEverywhere(String),
+    #[error(transparent)]
+    Range(#[from] RangeError),
+    #[error(transparent)]
+    Option(#[from] OptionError),
+    #[error(transparent)]
+    Pattern(#[from] PatternError),
+    #[error(transparent)]
+    Substitute(#[from] SubstituteError),
+    #[error(transparent)]
+    Mapping(#[from] MappingError),
+}
+
+/// A command line split into its parts: `name[!] [arg]`.
+struct Parsed<'a> {
+    name: &'a str,
+    bang: bool,
+    arg: &'a str,
+    /// The argument with the blanks at its end kept, which are keys to `:normal`.
+    keys: &'a str,
+}
+
+fn parse(line: &str) -> Parsed<'_> {
+    let line = line.trim_start_matches([' ', ':']);
+    let name_len = match line.chars().next() {
+        // `:>>>` shifts three times.
+        Some(shift @ ('>' | '<')) => line.find(|ch| ch != shift).unwrap_or(line.len()),
+        // `:&&` is `:&` with the `&` flag.
+        Some('&' | '~' | '#') => 1,
+        _ => line
+            .find(|ch: char| !ch.is_ascii_alphabetic())
+            .unwrap_or(line.len()),
+    };
+    // The name ends at the first non-letter, so `:b3` is `:b 3`.
+    let (name, rest) = line.split_at(name_len);
+    let (bang, rest) = match rest.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    Parsed {
+        name,
+        bang,
+        arg: rest.trim(),
+        keys: rest.trim_start(),
+    }
+}
+
+/// Whether `name` is an abbreviation of `full` at least `min` characters long, the way vim
+/// accepts `:bn` for `:bnext`.
+pub(super) fn abbrev(name: &str, full: &str, min: usize) -> bool {
+    name.len() >= min && full.starts_with(name)
+}
+
+fn no_arg(arg: &str) -> Result<(), CommandError> {
+    if arg.is_empty() {
+        Ok(())
+    } else {
+        Err(CommandError::TrailingCharacters(arg.to_owned()))
+    }
+}
+
+fn no_range(range: Option<LineRange>) -> Result<(), CommandError> {
+    match range {
+        Some(_) => Err(CommandError::NoRange),
+        None => Ok(()),
+    }
+}
+
+/// The count some commands take after their other arguments, as in `:d 3`.
+pub(super) fn count(arg: &str) -> Result<Option<usize>, CommandError> {
+    if arg.is_empty() {
+        return Ok(None);
+    }
+    match arg.parse::<usize>() {
+        Ok(0) => Err(CommandError::ZeroCount),
+        Ok(count) => Ok(Some(count)),
+        Err(_) => Err(CommandError::TrailingCharacters(arg.to_owned())),
+    }
+}
+
+/// `[x] [count]` after `:d` and `:y`.
+fn register_and_count(arg: &str) -> Result<(Option<char>, Option<usize>), CommandError> {
+    match arg.chars().next() {
+        Some(name) if !name.is_ascii_digit() && register::is_name(name) => {
+            Ok((Some(name), count(arg[name.len_utf8()..].trim_start())?))
+        }
+        _ => Ok((None, count(arg)?)),
+    }
+}
+
+/// Puts `text`, whole lines, below line number `line`, 0 for above the first.
+fn put_lines_below(buffer: &mut Buffer, line: usize, text: &str) {
+    if line == 0 {
+        buffer.insert(Position::default(), &format!("{text}\n"));
+    } else {
+        let end = Position::new(line - 1, buffer.line_len(line - 1));
+        buffer.insert(end, &format!("\n{text}"));
+    }
+}
+
+impl Editor {
+    /// Runs an Ex command line. Whatever it changes is one undo step.
+    pub(super) fn execute(&mut self, line: &str) -> Result<(), CommandError> {
+        let doc = self.doc_mut();
+        let (id, cursor) = (doc.id, doc.cursor);
+        doc.buffer.begin_change(cursor);
+        self.ex_depth += 1;
+        let result = self.run_ex(line);
+        self.ex_depth -= 1;
+        // A `:s///c` still asking keeps the step open until it's done.
+        if let Some(idx) = self.buffers.index_of(id)
+            && self.ex_depth == 0
+            && self.substitute_prompt.is_none()
+        {
+            self.buffers.get_mut(idx).buffer.commit_change();
+        }
+        result
+    }
+
+    fn run_ex(&mut self, line: &str) -> Result<(), CommandError> {
+        let (range, rest) =
+            range::parse(line.trim_start_matches([' ', ':']), &self.range_context())?;
+        let Parsed {
+            name,
+            bang,
+            arg,
+            keys,
+        } = parse(rest);
+        match name {
+            "" => {
+                no_arg(arg)?;
+                if let Some(range) = range {
+                    self.goto_line(range.end);
+                }
+                Ok(())
+            }
+            "e" | "edit" => {
+                no_range(range)?;
+                if arg.is_empty() {
+                    self.reedit(bang)
+                } else {
+                    self.open(arg);
+                    Ok(())
+                }
+            }
+            "w" | "write" => {
+                no_range(range)?;
+                self.save((!arg.is_empty()).then_some(arg));
+                Ok(())
+            }
+            "q" | "quit" | "qa" | "qall" | "quita" | "quitall" => {
+                no_range(range)?;
+                no_arg(arg)?;
+                self.quit(bang)
+            }
+            "wq" | "x" | "xit" | "exi" | "exit" => {
+                no_range(range)?;
+                if name == "wq" || self.buffers.current().buffer.is_modified() {
+                    self.save((!arg.is_empty()).then_some(arg));
+                }
+                self.quit(bang)
+            }
+            "ls" | "buffers" | "files" => {
+                no_range(range)?;
+                no_arg(arg)?;
+                self.list_buffers();
+                Ok(())
+            }
+            name if abbrev(name, "registers", 3) || abbrev(name, "display", 2) => {
+                no_range(range)?;
+                self.list_registers(arg);
+                Ok(())
+            }
+            "marks" => {
+                no_range(range)?;
+                self.list_marks(arg);
+                Ok(())
+            }
+            name if abbrev(name, "mark", 2) => {
+                let mut chars = arg.chars();
+                let (Some(mark), None) = (chars.next(), chars.next()) else {
+                    return Err(CommandError::ArgumentRequired);
+                };
+                if !marks::is_settable(mark) {
+                    return Err(CommandError::TrailingCharacters(arg.to_owned()));
+                }
+                let (_, row) = self.rows(range);
+                self.set_mark(mark);
+                let doc = self.doc_mut();
+                doc.buffer.marks_mut().set(mark, Position::new(row, 0));
+                Ok(())
+            }
+            name if abbrev(name, "delete", 1) => self.cut_lines(Operator::Delete, range, arg),
+            name if abbrev(name, "yank", 1) => self.cut_lines(Operator::Yank, range, arg),
+            name if abbrev(name, "move", 1) => self.move_lines(range, arg, false),
+            name if name == "t" || abbrev(name, "copy", 2) => self.move_lines(range, arg, true),
+            name if abbrev(name, "join", 1) => self.join(range, bang, arg),
+            name if name.starts_with(['>', '<']) => self.shift_lines(range, name, arg),
+            name if abbrev(name, "normal", 4) => self.normal(range, bang, keys),
+            name if abbrev(name, "global", 1) => self.global(range, bang, keys),
+            name if abbrev(name, "vglobal", 1) => self.global(range, true, keys),
+            name if abbrev(name, "print", 1) => self.print_lines(range, arg, false),
+            name if name == "#" || abbrev(name, "number", 2) => self.print_lines(range, arg, true),
+            name if name == "&" || name == "~" || abbrev(name, "substitute", 1) => {
+                self.substitute(range, name, keys)
+            }
+            name if mapping::is_command(name) => {
+                no_range(range)?;
+                Ok(self.map_command(name, bang, keys)?)
+            }
+            name if abbrev(name, "confirm", 4) => {
+                no_range(range)?;
+                self.confirm(keys)
+            }
+            name if abbrev(name, "set", 2) => {
+                no_range(range)?;
+                Ok(self.set_options(arg, Target::Both)?)
+            }
+            name if abbrev(name, "setlocal", 4) => {
+                no_range(range)?;
+                Ok(self.set_options(arg, Target::Local)?)
+            }
+            name if abbrev(name, "setglobal", 4) => {
+                no_range(range)?;
+                Ok(self.set_options(arg, Target::Global)?)
+            }
+            name if abbrev(name, "nohlsearch", 3) => {
+                no_range(range)?;
+                no_arg(arg)?;
+                self.highlight_search = false;
+                Ok(())
+            }
+            name if abbrev(name, "bnext", 2) => {
+                no_range(range)?;
+                no_arg(arg)?;
+                self.switch_to(self.buffers.cycle(1));
+                Ok(())
+            }
+            name if abbrev(name, "bprevious", 2) || abbrev(name, "bNext", 2) => {
+                no_range(range)?;
+                no_arg(arg)?;
+                self.switch_to(self.buffers.cycle(-1));
+                Ok(())
+            }
+            name if abbrev(name, "bdelete", 2) => {
+                no_range(range)?;
+                let idx = if arg.is_empty() {
+                    self.buffers.current_index()
+                } else {
+                    self.find_buffer(arg)?
+                };
+                self.delete_buffer(idx, bang)
+            }
+            name if abbrev(name, "buffer", 1) => {
+                no_range(range)?;
+                if arg.is_empty() {
+                    return Err(CommandError::ArgumentRequired);
+                }
+                let idx = self.find_buffer(arg)?;
+                self.switch_to(idx);
+                Ok(())
+            }
+            _ => Err(CommandError::Unknown(name.to_owned())),
+        }
+    }
+
+    /// The range, name and argument of the command on `line`, if it has a valid range.
+    pub(super) fn typed_command<'a>(
+        &self,
+        line: &'a str,
+    ) -> Option<(Option<LineRange>, &'a str, &'a str)> {
+        let cx = self.range_context();
+        let (range, rest) = range::parse(line.trim_start_matches([' ', ':']), &cx).ok()?;
+        let parsed = parse(rest);
+        Some((range, parsed.name, parsed.keys))
+    }
+
+    /// What addresses in a range are looked up in.
+    fn range_context(&self) -> range::Context<'_> {
+        let doc = self.doc();
+        range::Context {
+            buffer: &doc.buffer,
+            row: doc.cursor.row,
+            last_search: self
+                .last_search
+                .as_ref()
+                .map(|search| search.pattern.as_str()),
+            case: self.search_options.case,
+        }
+    }
+
+    /// The rows a command acts on: the range given, or the cursor's line.
+    fn rows(&self, range: Option<LineRange>) -> (usize, usize) {
+        range.map_or_else(
+            || {
+                let row = self.doc().cursor.row;
+                (row, row)
+            },
+            LineRange::rows,
+        )
+    }
+
+    /// The rows for a command given a count as well, which counts from the end of the range.
+    pub(super) fn counted_rows(
+        &self,
+        range: Option<LineRange>,
+        count: Option<usize>,
+    ) -> (usize, usize) {
+        let (first, last) = self.rows(range);
+        match count {
+            Some(count) => {
+                let end = last
+                    .saturating_add(count.saturating_sub(1))
+                    .min(self.doc().buffer.line_count() - 1);
+                (last, end)
+            }
+            None => (first, last),
+        }
+    }
+
+    /// `:N`, a range on its own: goes to its last line.
+    fn goto_line(&mut self, line: usize) {
+        self.push_jump();
+        let doc = self.doc_mut();
+        let row = line.saturating_sub(1);
+        doc.cursor = Position::new(row, first_non_blank(&doc.buffer, row));
+        doc.remember_col();
+    }
+
+    /// `:d` and `:y`, which take a register and a count: `:d x 3`.
+    fn cut_lines(
+        &mut self,
+        op: Operator,
+        range: Option<LineRange>,
+        arg: &str,
+    ) -> Result<(), CommandError> {
+        let (register, count) = register_and_count(arg)?;
+        let (first, last) = self.counted_rows(range, count);
+        let cursor = self.doc().cursor;
+        self.register = register;
+        let region = Region {
+            start: Position::new(first, 0),
+            end: Position::new(last, 0),
+            kind: RegionKind::Line,
+        };
+        self.apply_operator(op, region);
+        self.register = None;
+        if op == Operator::Yank {
+            self.doc_mut().cursor = cursor;
+        }
+        Ok(())
+    }
+
+    /// `:m {address}`, or with `copy` `:t {address}`: puts the lines below line `address`,
+    /// 0 for the top.
+    fn move_lines(
+        &mut self,
+        range: Option<LineRange>,
+        arg: &str,
+        copy: bool,
+    ) -> Result<(), CommandError> {
+        let (first, last) = self.rows(range);
+        let doc = self.doc();
+        let (dest, rest) = range::address(arg, doc.cursor.row + 1, &self.range_context())?;
+        no_arg(rest.trim())?;
+        let dest = dest.ok_or(CommandError::ArgumentRequired)?;
+        if dest > doc.buffer.line_count() {
+            return Err(RangeError::Invalid.into());
+        }
+        if !copy && dest > first && dest <= last {
+            return Err(CommandError::MoveIntoItself);
+        }
+        if doc.buffer.is_read_only() {
+            self.message = "Buffer is read-only".to_owned();
+            return Ok(());
+        }
+        let count = last - first + 1;
+        let doc = self.doc_mut();
+        let buffer = &mut doc.buffer;
+        let end = Position::new(last, buffer.line_len(last));
+        let text = buffer.text_range(Position::new(first, 0), end);
+        let new_first = if copy {
+            put_lines_below(buffer, dest, &text);
+            dest
+        } else if dest == first || dest == last + 1 {
+            // Moved to where they are already.
+            first
+        } else if dest > last {
+            put_lines_below(buffer, dest, &text);
+            operator::delete_lines(buffer, first, last);
+            dest - count
+        } else {
+            operator::delete_lines(buffer, first, last);
+            put_lines_below(buffer, dest, &text);
+            dest
+        };
+        let row = new_first + count - 1;
+        doc.cursor = Position::new(row, first_non_blank(buffer, row));
+        Ok(())
+    }
+
+    /// `:j[!] [count]`. Without a range of several lines, joins the line with the next.
+    fn join(
+        &mut self,
+        range: Option<LineRange>,
+        bang: bool,
+        arg: &str,
+    ) -> Result<(), CommandError> {
+        let count = count(arg)?;
+        let (first, last) = match (range, count) {
+            (_, Some(count)) => {
+                let (_, last) = self.rows(range);
+                (last, last.saturating_add(count.max(2) - 1))
+            }
+            (Some(range), None) if range.start != range.end => range.rows(),
+            _ => {
+                let (first, _) = self.rows(range);
+                (first, first + 1)
+            }
+        };
+        let last = last.min(self.doc().buffer.line_count() - 1);
+        if first < last {
+            self.join_lines(first, last, bang);
+        }
+        Ok(())
+    }
+
+    /// `:>` and `:<`, shifting once more for each `>` or `<` repeated. The cursor ends on the
+    /// last line.
+    fn shift_lines(
+        &mut self,
+        range: Option<LineRange>,
+        name: &str,
+        arg: &str,
+    ) -> Result<(), CommandError> {
+        let (first, last) = self.counted_rows(range, count(arg)?);
+        let op = if name.starts_with('>') {
+            Operator::ShiftRight
+        } else {
+            Operator::ShiftLeft
+        };
+        let region = Region {
+            start: Position::new(first, 0),
+            end: Position::new(last, 0),
+            kind: RegionKind::Line,
+        };
+        for _ in 0..name.len() {
+            self.apply_operator(op, region);
+        }
+        let doc = self.doc_mut();
+        doc.cursor = Position::new(last, first_non_blank(&doc.buffer, last));
+        Ok(())
+    }
+
+    /// `:g/pat/cmd`, and `:v/pat/cmd` or `:g!/pat/cmd` with `invert` for the lines that don't
+    /// match: runs `cmd`, `:p` if it's left out, on each line of the range, the whole buffer
+    /// by default. The lines are marked first, so a line the command deletes or joins with
+    /// another one before its turn comes is left out.
+    fn global(
+        &mut self,
+        range: Option<LineRange>,
+        invert: bool,
+        arg: &str,
+    ) -> Result<(), CommandError> {
+        if self.in_global {
+            return Err(CommandError::GlobalRecursive);
+        }
+        let delimiter = arg.chars().next().ok_or(CommandError::ArgumentRequired)?;
+        if delimiter.is_alphanumeric() || matches!(delimiter, '\\' | '"' | '|') {
+            return Err(CommandError::Delimiter);
+        }
+        let (pattern, command) = split_pattern(&arg[delimiter.len_utf8()..], delimiter);
+        let pattern = match (pattern.is_empty(), &self.last_search) {
+            (false, _) => pattern,
+            (true, Some(last)) => last.pattern.clone(),
+            (true, None) => return Err(RangeError::NoPrevious.into()),
+        };
+        let compiled = self.compile_search(&pattern)?;
+        let buffer = &self.doc().buffer;
+        let (first, last) = range.map_or((0, buffer.line_count() - 1), LineRange::rows);
+        let mut matching: Vec<usize> = compiled
+            .matches(buffer, first..last + 1)
+            .into_iter()
+            .map(|(start, _)| start.row)
+            .collect();
+        matching.dedup();
+        let rows: Vec<usize> = if invert {
+            (first..=last)
+                .filter(|row| matching.binary_search(row).is_err())
+                .collect()
+        } else {
+            matching
+        };
+        if rows.is_empty() {
+            return Err(if invert {
+                CommandError::FoundEverywhere(pattern)
+            } else {
+                RangeError::NotFound(pattern).into()
+            });
+        }
+        let forward = self
+            .last_search
+            .as_ref()
+            .is_none_or(|search| search.forward);
+        self.last_search = Some(Search { pattern, forward });
+        self.highlight_search = true;
+        self.doc_mut().buffer.marks_mut().mark_lines(rows);
+        let command = if command.trim().is_empty() {
+            "p"
+        } else {
+            command
+        };
+        self.in_global = true;
+        let mut result = Ok(());
+        while let Some(row) = self.doc_mut().buffer.marks_mut().next_marked_line() {
+            self.doc_mut().cursor = Position::new(row, 0);
+            result = self.run_ex(command);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.doc_mut().buffer.marks_mut().mark_lines([]);
+        self.in_global = false;
+        self.clamp_cursor();
+        result
+    }
+
+    /// `:p`, and `:nu` or `:#` with `numbered`: shows the lines of the range, the cursor's
+    /// line without one. Under `:g` the lines add up to one list.
+    fn print_lines(
+        &mut self,
+        range: Option<LineRange>,
+        arg: &str,
+        numbered: bool,
+    ) -> Result<(), CommandError> {
+        let (first, last) = self.counted_rows(range, count(arg)?);
+        let buffer = &self.doc().buffer;
+        let lines: Vec<String> = (first..=last)
+            .map(|row| {
+                let line = buffer.line(row).unwrap_or_default();
+                if numbered {
+                    format!("{:>3} {line}", row + 1)
+                } else {
+                    line.into_owned()
+                }
+            })
+            .collect();
+        let col = first_non_blank(buffer, last);
+        self.pager.get_or_insert_with(Vec::new).extend(lines);
+        self.doc_mut().cursor = Position::new(last, col);
+        Ok(())
+    }
+
+    /// `:normal {keys}`: types `keys` in Normal mode, on each line of the range if there is
+    /// one. A command the keys leave unfinished is cancelled, as if Escape followed. With
+    /// `:normal!` mappings don't apply to them.
+    fn normal(
+        &mut self,
+        range: Option<LineRange>,
+        bang: bool,
+        keys: &str,
+    ) -> Result<(), CommandError> {
+        if keys.is_empty() {
+            return Err(CommandError::ArgumentRequired);
+        }
+        let keys: Vec<char> = keys.chars().collect();
+        let origin = if bang {
+            Origin::Unmapped
+        } else {
+            Origin::Typed
+        };
+        // Keys typed after the command line wait until `:normal` is done with its own.
+        let typed = std::mem::take(&mut self.pending);
+        match range.map(LineRange::rows) {
+            Some((first, last)) => {
+                for row in first..=last {
+                    if row >= self.doc().buffer.line_count() {
+                        break;
+                    }
+                    self.doc_mut().cursor = Position::new(row, 0);
+                    self.type_keys(&keys, origin);
+                }
+            }
+            None => self.type_keys(&keys, origin),
+        }
+        self.pending = typed;
+        Ok(())
+    }
+
+    fn type_keys(&mut self, keys: &[char], origin: Origin) {
+        self.discard_change();
+        self.pending.prepend(keys, origin);
+        self.process_keys(true);
+        self.pending.clear();
+        if self.mode != Mode::Normal {
+            self.pending.push(keys::ESC);
+            self.process_keys(true);
+            self.pending.clear();
+        }
+    }
+
+    /// `:confirm {cmd}`. Quitting with unsaved changes around asks first, and quits when
+    /// asked again; other commands just run.
+    fn confirm(&mut self, line: &str) -> Result<(), CommandError> {
+        let (range, rest) = range::parse(line, &self.range_context())?;
+        let parsed = parse(rest);
+        let quits = matches!(
+            parsed.name,
+            "q" | "quit" | "qa" | "qall" | "quita" | "quitall"
+        );
+        if range.is_none() && quits && !parsed.bang && parsed.arg.is_empty() {
+            self.request_quit();
+            Ok(())
+        } else {
+            self.run_ex(line)
+        }
+    }
+
+    fn find_buffer(&self, arg: &str) -> Result<usize, CommandError> {
+        self.buffers.find(arg).map_err(|e| match e {
+            FindError::NoMatch => CommandError::NoSuchBuffer(arg.to_owned()),
+            FindError::Ambiguous => CommandError::AmbiguousBuffer(arg.to_owned()),
+            FindError::NoAlternate => CommandError::NoAlternate,
+        })
+    }
+
+    /// `:e` without a file: reads the current file again, throwing away changes with `!`.
+    fn reedit(&mut self, bang: bool) -> Result<(), CommandError> {
+        let doc = self.buffers.current_mut();
+        if doc.buffer.is_modified() && !bang {
+            return Err(CommandError::Unsaved);
+        }
+        match doc.buffer.reload(doc.cursor) {
+            Ok(()) => {
+                doc.cursor = doc.buffer.clamp(doc.cursor);
+                doc.reload_prompt = false;
+                self.message = format!("\"{}\" reloaded", doc.name());
+            }
+            Err(e) => self.message = e.to_string(),
+        }
+        Ok(())
+    }
+
+    fn delete_buffer(&mut self, idx: usize, bang: bool) -> Result<(), CommandError> {
+        let doc = self.buffers.get_mut(idx);
+        if doc.buffer.is_modified() && !bang {
+            return Err(CommandError::UnsavedBuffer(doc.id));
+        }
+        self.stash_file_marks(idx);
+        self.buffers.remove(idx).close();
+        self.show_prompt();
+        Ok(())
+    }
+
+    /// Quits unless something would be lost, in which case the user has to say so with `!`.
+    pub(super) fn quit(&mut self, bang: bool) -> Result<(), CommandError> {
+        if bang {
+            self.wants_exit = true;
+            return Ok(());
+        }
+        match self.buffers.iter().find(|doc| doc.buffer.is_modified()) {
+            Some(doc) => Err(CommandError::UnsavedBuffer(doc.id)),
+            None => {
+                self.wants_exit = true;
+                Ok(())
+            }
+        }
+    }
+
+    fn list_buffers(&mut self) {
+        let current = self.buffers.current_index();
+        let alternate = self.buffers.alternate_index();
+        let lines = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(idx, doc)| {
+                let mark = if idx == current {
+                    "%a"
+                } else if Some(idx) == alternate {
+                    "#h"
+                } else {
+                    " h"
+                };
+                let flags = match (doc.buffer.is_read_only(), doc.buffer.is_modified()) {
+                    (true, _) => " =",
+                    (false, true) => " +",
+                    (false, false) => "  ",
+                };
+                format!(
+                    "{:3} {mark}{flags} \"{}\"  line {}",
+                    doc.id,
+                    doc.name(),
+                    doc.cursor.row + 1
+                )
+            })
+            .collect();
+        self.pager = Some(lines);
+    }
+}
@@ -0,0 +1,180 @@
+//! The config file read at startup. It's TOML: options at the top level by their `:set`
+//! names, set as with `:setglobal`, and mappings under `[keymaps]`, a table per mode.
+//!
+//! ```toml
+//! tabstop = 4
+//! expandtab = true
+//! colorscheme = "mono"
+//!
+//! [keymaps.n]
+//! "<Leader>w" = "<Cmd>write<CR>"
+//!
+//! [keymaps.i]
+//! jk = { rhs = "<Esc>", silent = true }
+//! ```
+//!
+//! Mappings are `:noremap`s unless they say `remap = true`.
+
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+use toml::de::{DeTable, DeValue};
+
+use super::Editor;
+use super::options::{self, Target, Value};
+use crate::paths;
+
+/// Something wrong in the config file, at `line`.
+#[derive(Debug, Error)]
+#[error("{}:{line}: {message}", path.display())]
+pub struct ConfigError {
+    path: PathBuf,
+    line: usize,
+    message: String,
+}
+
+/// What one mapping in `[keymaps]` says.
+struct Keymap {
+    rhs: String,
+    remap: bool,
+    silent: bool,
+}
+
+impl Editor {
+    /// Reads the config file, if there is one. What's wrong with it shows in the pager once the
+    /// editor is up, where opening the first file can't write over it; the rest still applies.
+    pub(super) fn load_config(&mut self) {
+        let Some(path) = paths::config_file() else {
+            return;
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                self.pager = Some(vec![format!("{}: {e}", path.display())]);
+                return;
+            }
+        };
+        let errors = self.apply_config(&path, &text);
+        if !errors.is_empty() {
+            self.pager = Some(errors.iter().map(ToString::to_string).collect());
+        }
+    }
+
+    /// Applies the config `text` read from `path`, skipping over what's wrong.
+    fn apply_config(&mut self, path: &Path, text: &str) -> Vec<ConfigError> {
+        let error = |span: Range<usize>, message: String| ConfigError {
+            path: path.to_owned(),
+            line: text[..span.start.min(text.len())].matches('\n').count() + 1,
+            message,
+        };
+        let table = match DeTable::parse(text) {
+            Ok(table) => table.into_inner(),
+            Err(e) => return vec![error(e.span().unwrap_or_default(), e.message().to_owned())],
+        };
+        let mut errors = Vec::new();
+        let mut keymaps = None;
+        for (key, value) in &table {
+            let name = key.get_ref().as_ref();
+            if name == "keymaps" {
+                keymaps = Some(value);
+                continue;
+            }
+            let Some(full) = options::full_name(name) else {
+                errors.push(error(key.span(), format!("Unknown option: {name}")));
+                continue;
+            };
+            let result = match option_value(full, value.get_ref()) {
+                Some(value) => self.set_option(full, value, Target::Global),
+                None => {
+                    let message = format!("Wrong type of value for option: {name}");
+                    errors.push(error(value.span(), message));
+                    continue;
+                }
+            };
+            if let Err(e) = result {
+                errors.push(error(value.span(), e.to_string()));
+            }
+        }
+        // After the options, so `mapleader` is set first.
+        let Some(keymaps) = keymaps else {
+            return errors;
+        };
+        let DeValue::Table(modes) = keymaps.get_ref() else {
+            errors.push(error(
+                keymaps.span(),
+                "Expected a table: keymaps".to_owned(),
+            ));
+            return errors;
+        };
+        for (letters, mappings) in modes {
+            let DeValue::Table(mappings) = mappings.get_ref() else {
+                let message = format!("Expected a table: keymaps.{}", letters.get_ref());
+                errors.push(error(mappings.span(), message));
+                continue;
+            };
+            for (lhs, keymap) in mappings {
+                let Some(Keymap { rhs, remap, silent }) = keymap_of(keymap.get_ref()) else {
+                    let message = format!("Invalid mapping for {}", lhs.get_ref());
+                    errors.push(error(keymap.span(), message));
+                    continue;
+                };
+                if let Err(e) =
+                    self.add_mapping(letters.get_ref(), lhs.get_ref(), &rhs, remap, silent)
+                {
+                    errors.push(error(lhs.span(), e.to_string()));
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// `value` as a value of option `name`, if it's of the right type.
+fn option_value(name: &str, value: &DeValue) -> Option<Value> {
+    match (options::default_value(name), value) {
+        (Value::Bool(_), DeValue::Boolean(on)) => Some(Value::Bool(*on)),
+        (Value::Number(_), DeValue::Integer(number)) => {
+            usize::from_str_radix(number.as_str(), number.radix())
+                .ok()
+                .map(Value::Number)
+        }
+        (Value::String(_), DeValue::String(string)) => Some(Value::String(string.to_string())),
+        _ => None,
+    }
+}
+
+/// A mapping in `[keymaps]`: its keys as a string, or a table with them as `rhs`.
+fn keymap_of(value: &DeValue) -> Option<Keymap> {
+    let table = match value {
+        DeValue::String(rhs) => {
+            return Some(Keymap {
+                rhs: rhs.to_string(),
+                remap: false,
+                silent: false,
+            });
+        }
+        DeValue::Table(table) => table,
+        _ => return None,
+    };
+    let mut keymap = Keymap {
+        rhs: String::new(),
+        remap: false,
+        silent: false,
+    };
+    let mut has_rhs = false;
+    for (key, value) in table {
+        match (key.get_ref().as_ref(), value.get_ref()) {
+            ("rhs", DeValue::String(rhs)) => {
+                keymap.rhs = rhs.to_string();
+                has_rhs = true;
+            }
+            ("remap", DeValue::Boolean(on)) => keymap.remap = *on,
+            ("silent", DeValue::Boolean(on)) => keymap.silent = *on,
+            _ => return None,
+        }
+    }
+    has_rhs.then_some(keymap)
+}
@@ -0,0 +1,245 @@
+//! Open files and the per-file state that goes with them.
+
+use std::path::Path;
+
+use super::mapping::Mappings;
+use super::options::BufferOptions;
+use crate::buffer::{Buffer, Position};
+use crate::display;
+use crate::swap::{ExistingSwap, SwapFile};
+use crate::watch::FileWatcher;
+
+/// A buffer plus everything that belongs to it rather than to the editor: where the cursor and
+/// view were, its swap file, and questions about it still waiting for an answer.
+#[derive(Debug, Default)]
+pub struct Document {
+    /// Buffer number, as shown by `:ls` and taken by `:b N`. Never reused.
+    pub id: usize,
+    pub buffer: Buffer,
+    pub cursor: Position,
+    /// Screen column `j` and `k` try to keep to, `usize::MAX` for the end of the line.
+    pub want_col: usize,
+    /// First buffer line shown at the top of the screen.
+    pub scroll: usize,
+    /// First screen column shown, when lines don't wrap.
+    pub left_col: usize,
+    pub swap: Option<SwapFile>,
+    /// A swap file found on open that the user has to decide about before editing.
+    pub swap_prompt: Option<ExistingSwap>,
+    pub watcher: Option<FileWatcher>,
+    /// The file changed on disk under unsaved changes; waiting for reload, keep or diff.
+    pub reload_prompt: bool,
+    /// Mappings made with `<buffer>`, which only apply here.
+    pub mappings: Mappings,
+    /// `:setlocal` options, which start out as the global ones.
+    pub options: BufferOptions,
+    /// What kind of file this is, `filetype`: empty when it isn't known.
+    pub filetype: String,
+}
+
+impl Document {
+    pub fn name(&self) -> String {
+        self.buffer
+            .path()
+            .map_or_else(|| "[No Name]".to_owned(), |p| p.display().to_string())
+    }
+
+    /// Makes `j` and `k` keep to the screen column the cursor is on now.
+    pub fn remember_col(&mut self) {
+        let line = self.buffer.line(self.cursor.row).unwrap_or_default();
+        self.want_col = display::screen_col(&line, self.cursor.col, self.options.tabstop);
+    }
+
+    /// A fresh empty buffer nobody has typed into, which opening a file can simply replace.
+    pub fn is_untouched(&self) -> bool {
+        self.buffer.path().is_none() && !self.buffer.is_modified() && self.buffer.change_tick() == 0
+    }
+
+    /// The question to show while a prompt is pending on this document.
+    pub fn prompt(&self) -> Option<String> {
+        if let Some(existing) = &self.swap_prompt {
+            return Some(if existing.running {
+                format!(
+                    "Already being edited by editui (pid {}): [O]pen read-only, [E]dit anyway, [Q]uit",
+                    existing.pid
+                )
+            } else {
+                "Found unsaved changes from a crashed editui: [R]ecover, [O]pen read-only, [E]dit anyway, [D]elete them, [Q]uit".to_owned()
+            });
+        }
+        self.reload_prompt
+            .then(|| "File changed on disk: [R]eload, [K]eep yours, [D]iff".to_owned())
+    }
+
+    /// Cleans up after the document when it's closed.
+    pub fn close(self) {
+        if let Some(swap) = self.swap {
+            swap.remove();
+        }
+    }
+}
+
+/// All open documents. There's always at least one, an empty buffer if nothing else.
+#[derive(Debug)]
+pub struct BufferList {
+    docs: Vec<Document>,
+    current: usize,
+    /// The previously current document, for `:b#`.
+    alternate: Option<usize>,
+    next_id: usize,
+}
+
+impl Default for BufferList {
+    fn default() -> Self {
+        Self {
+            docs: vec![Document {
+                id: 1,
+                ..Document::default()
+            }],
+            current: 0,
+            alternate: None,
+            next_id: 2,
+        }
+    }
+}
+
+impl BufferList {
+    pub fn current(&self) -> &Document {
+        &self.docs[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut Document {
+        &mut self.docs[self.current]
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn alternate_index(&self) -> Option<usize> {
+        self.alternate
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Document> {
+        self.docs.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Document> {
+        self.docs.iter_mut()
+    }
+
+    pub fn get_mut(&mut self, idx: usize) -> &mut Document {
+        &mut self.docs[idx]
+    }
+
+    /// Index of the document numbered `id`, if it's still open.
+    pub fn index_of(&self, id: usize) -> Option<usize> {
+        self.docs.iter().position(|doc| doc.id == id)
+    }
+
+    /// Adds a document and returns its index. It gets the next buffer number.
+    pub fn push(&mut self, buffer: Buffer) -> usize {
+        self.docs.push(Document {
+            id: self.next_id,
+            buffer,
+            ..Document::default()
+        });
+        self.next_id += 1;
+        self.docs.len() - 1
+    }
+
+    pub fn switch_to(&mut self, idx: usize) {
+        if idx != self.current {
+            self.alternate = Some(self.current);
+            self.current = idx;
+        }
+    }
+
+    /// The document `delta` places after (or before) the current one, wrapping around.
+    pub fn cycle(&self, delta: isize) -> usize {
+        let len = self.docs.len() as isize;
+        (self.current as isize + delta).rem_euclid(len) as usize
+    }
+
+    /// Index of the document editing `path`, if it's open.
+    pub fn find_path(&self, path: &Path) -> Option<usize> {
+        let wanted = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.docs.iter().position(|doc| {
+            doc.buffer
+                .path()
+                .is_some_and(|p| p.canonicalize().unwrap_or_else(|_| p.to_path_buf()) == wanted)
+        })
+    }
+
+    /// Finds a document the way `:b` does: by buffer number, `#` for the alternate, or a part
+    /// of its name. A name matching in full wins over partial matches.
+    pub fn find(&self, arg: &str) -> Result<usize, FindError> {
+        if arg == "#" {
+            return self.alternate.ok_or(FindError::NoAlternate);
+        }
+        if let Ok(id) = arg.parse::<usize>() {
+            return self.index_of(id).ok_or(FindError::NoMatch);
+        }
+        if let Some(idx) = self.docs.iter().position(|doc| doc.name() == arg) {
+            return Ok(idx);
+        }
+        let mut matches = self
+            .docs
+            .iter()
+            .enumerate()
+            .filter(|(_, doc)| doc.buffer.path().is_some() && doc.name().contains(arg));
+        match (matches.next(), matches.next()) {
+            (Some((idx, _)), None) => Ok(idx),
+            (Some(_), Some(_)) => Err(FindError::Ambiguous),
+            (None, _) => Err(FindError::NoMatch),
+        }
+    }
+
+    /// Takes a document out of the list. The alternate, or else a neighbour, becomes current;
+    /// removing the last document leaves an empty one in its place.
+    pub fn remove(&mut self, idx: usize) -> Document {
+        if self.docs.len() == 1 {
+            let id = self.next_id;
+            self.next_id += 1;
+            self.alternate = None;
+            return std::mem::replace(
+                &mut self.docs[0],
+                Document {
+                    id,
+                    ..Document::default()
+                },
+            );
+        }
+        let doc = self.docs.remove(idx);
+        let shift = |i: usize| if i > idx { i - 1 } else { i };
+        if idx == self.current {
+            self.current = match self.alternate {
+                Some(alt) if alt != idx => shift(alt),
+                _ => idx.min(self.docs.len() - 1),
+            };
+            self.alternate = None;
+        } else {
+            self.current = shift(self.current);
+            self.alternate = self.alternate.filter(|&alt| alt != idx).map(shift);
+        }
+        doc
+    }
+
+    /// Closes every document, for exiting.
+    pub fn close_all(&mut self) {
+        for doc in std::mem::take(self).docs {
+            doc.close();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindError {
+    NoMatch,
+    Ambiguous,
+    NoAlternate,
+}
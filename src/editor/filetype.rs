@@ -0,0 +1,225 @@
+//! Filetypes and the settings that come with a file: a filetype's defaults, then what
+//! `.editorconfig` files say, then modelines.
+//!
+//! The filetype is found from the file's name, then a script's `#!` line, and a modeline
+//! saying `ft=` beats both. The rest of the modelines come last, over everything else.
+
+use std::path::Path;
+
+use super::Editor;
+use super::modeline;
+use super::options::{Target, Value};
+use crate::editorconfig::{self, IndentSize, IndentStyle, Properties};
+
+/// Filetypes by name, with the extensions and whole file names of their files and the
+/// interpreters of scripts that are one.
+const DETECT: &[(&str, &[&str], &[&str])] = &[
+    ("c", &["c", "h"], &[]),
+    ("cpp", &["cpp", "cc", "cxx", "hpp", "hh", "hxx"], &[]),
+    ("cs", &["cs"], &[]),
+    ("css", &["css"], &[]),
+    ("dosbatch", &["bat", "cmd"], &[]),
+    ("gitcommit", &["COMMIT_EDITMSG"], &[]),
+    ("go", &["go"], &[]),
+    ("html", &["html", "htm"], &[]),
+    ("java", &["java"], &[]),
+    ("javascript", &["js", "mjs", "cjs"], &["node"]),
+    ("json", &["json"], &[]),
+    ("lua", &["lua"], &["lua"]),
+    ("mail", &["eml"], &[]),
+    (
+        "make",
+        &["mk", "Makefile", "makefile", "GNUmakefile"],
+        &["make"],
+    ),
+    ("markdown", &["md", "markdown"], &[]),
+    ("perl", &["pl", "pm"], &["perl"]),
+    ("ps1", &["ps1", "psm1", "psd1"], &["pwsh"]),
+    ("python", &["py", "pyw"], &["python"]),
+    ("ruby", &["rb", "Rakefile", "Gemfile"], &["ruby"]),
+    ("rust", &["rs"], &[]),
+    (
+        "sh",
+        &["sh", "bash", ".bashrc", ".profile"],
+        &["sh", "bash", "dash", "ksh"],
+    ),
+    ("text", &["txt"], &[]),
+    ("toml", &["toml", "Cargo.lock"], &[]),
+    ("typescript", &["ts", "mts", "cts"], &["deno"]),
+    ("vim", &["vim", ".vimrc", "_vimrc"], &[]),
+    ("xml", &["xml"], &[]),
+    ("yaml", &["yaml", "yml"], &[]),
+    ("zsh", &["zsh", ".zshrc"], &["zsh"]),
+];
+
+/// How a filetype indents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Indent {
+    /// `n` spaces a level: `shiftwidth=n` and `expandtab`.
+    Spaces(usize),
+    /// A tab a level.
+    Tabs,
+}
+
+/// What filetypes set besides their name: how they indent, `commentstring` and `textwidth`.
+/// Those left out keep the global values.
+const DEFAULTS: &[(&str, Option<Indent>, &str, usize)] = &[
+    ("c", None, "/* %s */", 0),
+    ("cpp", None, "// %s", 0),
+    ("cs", Some(Indent::Spaces(4)), "// %s", 0),
+    ("css", Some(Indent::Spaces(2)), "/* %s */", 0),
+    ("dosbatch", None, ":: %s", 0),
+    ("gitcommit", None, "# %s", 72),
+    ("go", Some(Indent::Tabs), "// %s", 0),
+    ("html", Some(Indent::Spaces(2)), "<!-- %s -->", 0),
+    ("java", Some(Indent::Spaces(4)), "// %s", 0),
+    ("javascript", Some(Indent::Spaces(2)), "// %s", 0),
+    ("json", Some(Indent::Spaces(2)), "", 0),
+    ("lua", Some(Indent::Spaces(2)), "-- %s", 0),
+    ("mail", None, "> %s", 72),
+    ("make", Some(Indent::Tabs), "# %s", 0),
+    ("markdown", None, "<!-- %s -->", 0),
+    ("perl", None, "# %s", 0),
+    ("ps1", Some(Indent::Spaces(4)), "# %s", 0),
+    ("python", Some(Indent::Spaces(4)), "# %s", 0),
+    ("ruby", Some(Indent::Spaces(2)), "# %s", 0),
+    ("rust", Some(Indent::Spaces(4)), "// %s", 0),
+    ("sh", None, "# %s", 0),
+    ("text", None, "", 0),
+    ("toml", None, "# %s", 0),
+    ("typescript", Some(Indent::Spaces(2)), "// %s", 0),
+    ("vim", Some(Indent::Spaces(2)), "\" %s", 0),
+    ("xml", Some(Indent::Spaces(2)), "<!-- %s -->", 0),
+    ("yaml", Some(Indent::Spaces(2)), "# %s", 0),
+    ("zsh", None, "# %s", 0),
+];
+
+/// The filetype of a file by its name: its extension, or the whole name for those like
+/// `Makefile`.
+fn by_name(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?;
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    DETECT
+        .iter()
+        .find(|(_, names, _)| {
+            names.iter().any(|&entry| {
+                entry == name || extension.is_some_and(|ext| ext.eq_ignore_ascii_case(entry))
+            })
+        })
+        .map(|&(filetype, ..)| filetype)
+}
+
+/// The filetype of a script by the interpreter its `#!` line runs, looking past `env`. A
+/// version after the interpreter's name, as in `python3`, doesn't matter.
+fn by_shebang(first_line: &str) -> Option<&'static str> {
+    let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit(['/', '\\']).next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    let program = program.trim_end_matches(|ch: char| ch.is_ascii_digit() || ch == '.');
+    DETECT
+        .iter()
+        .find(|(.., interpreters)| interpreters.contains(&program))
+        .map(|&(filetype, ..)| filetype)
+}
+
+impl Editor {
+    /// Gives the current buffer, just opened, the settings its file calls for.
+    pub(super) fn apply_file_settings(&mut self) {
+        let buffer = &self.doc().buffer;
+        let first_line = buffer.line(0).unwrap_or_default();
+        let settings = if self.modeline {
+            modeline::settings(buffer, self.modelines)
+        } else {
+            Vec::new()
+        };
+        let from_modeline = modeline::filetype(&settings).map(str::to_owned);
+        let filetype = from_modeline
+            .or_else(|| buffer.path().and_then(by_name).map(str::to_owned))
+            .or_else(|| by_shebang(&first_line).map(str::to_owned));
+        if let Some(filetype) = filetype {
+            self.set_filetype(&filetype);
+        }
+        if let Some(path) = self.doc().buffer.path() {
+            let properties = editorconfig::properties(path);
+            self.apply_editorconfig(&properties);
+        }
+        self.apply_modelines();
+    }
+
+    /// `:set filetype`: names the current buffer's filetype and gives it the filetype's
+    /// defaults, over whatever an earlier filetype set.
+    pub(super) fn set_filetype(&mut self, name: &str) {
+        let global = &self.buffer_options;
+        let doc = self.buffers.current_mut();
+        doc.filetype = name.to_owned();
+        let options = &mut doc.options;
+        options.shiftwidth = global.shiftwidth;
+        options.expandtab = global.expandtab;
+        options.commentstring = global.commentstring.clone();
+        options.textwidth = global.textwidth;
+        let Some(&(_, indent, comment, width)) = DEFAULTS.iter().find(|(ft, ..)| *ft == name)
+        else {
+            return;
+        };
+        match indent {
+            Some(Indent::Spaces(width)) => {
+                options.shiftwidth = width;
+                options.expandtab = true;
+            }
+            Some(Indent::Tabs) => {
+                options.shiftwidth = 0;
+                options.expandtab = false;
+            }
+            None => {}
+        }
+        options.commentstring = comment.to_owned();
+        options.textwidth = width;
+    }
+
+    /// Applies `.editorconfig` properties to the current buffer. `insert_final_newline`
+    /// turns `fixendofline` on or off, but doesn't take an existing final line break away.
+    /// Widths `:set` wouldn't take are ignored, like any other value the spec doesn't know.
+    fn apply_editorconfig(&mut self, properties: &Properties) {
+        // `tab_width` goes by `indent_size` unless it's given.
+        let tab_width = match (properties.tab_width, properties.indent_size) {
+            (Some(width), _) | (None, Some(IndentSize::Width(width))) => Some(width),
+            _ => None,
+        };
+        let shift_width = match properties.indent_size {
+            Some(IndentSize::Width(width)) => Some(width),
+            Some(IndentSize::Tab) => Some(0),
+            None => None,
+        };
+        let widths = [("tabstop", tab_width), ("shiftwidth", shift_width)];
+        for (name, width) in widths {
+            if let Some(width) = width {
+                let _ = self.set_option(name, Value::Number(width), Target::Local);
+            }
+        }
+        let doc = self.buffers.current_mut();
+        let options = &mut doc.options;
+        match properties.indent_style {
+            Some(IndentStyle::Tab) => options.expandtab = false,
+            Some(IndentStyle::Space) => options.expandtab = true,
+            None => {}
+        }
+        if let Some(trim) = properties.trim_trailing_whitespace {
+            options.trim_trailing_whitespace = trim;
+        }
+        if let Some(fix) = properties.insert_final_newline {
+            options.fixendofline = fix;
+        }
+        let buffer = &mut doc.buffer;
+        if !buffer.is_read_only() {
+            if let Some(format) = properties.end_of_line {
+                buffer.set_file_format(format);
+            }
+            if let Some((encoding, bom)) = properties.charset {
+                buffer.set_encoding(encoding);
+                buffer.set_bom(bom);
+            }
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Insert and Replace mode.
+
+use super::Editor;
+use super::block;
+use super::document::Document;
+use super::keys::{self, Lookup, ctrl};
+use super::mode::Mode;
+use crate::buffer::Position;
+use crate::display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertCmd {
+    Char(char),
+    /// The Tab key, which with `expandtab` inserts spaces. Ctrl-V Tab is a `Char`.
+    Tab,
+    Newline,
+    Backspace,
+    Leave,
+}
+
+/// What was typed since Insert or Replace mode started, to type again when it was started
+/// with a count.
+#[derive(Debug, Default)]
+pub struct InsertSession {
+    /// How many more times to type it on leaving.
+    extra: usize,
+    /// Whether each time goes on a new line, for `o` and `O`.
+    new_lines: bool,
+    /// After `I`, `A` or `c` in Visual block mode, where else it goes.
+    block: Option<BlockRows>,
+    typed: Vec<InsertCmd>,
+}
+
+/// Other lines of a block that text typed on its first line goes on too, at screen column
+/// `col`, or at the end of each line for `usize::MAX`.
+#[derive(Debug)]
+struct BlockRows {
+    rows: Vec<usize>,
+    col: usize,
+    /// Where typing started, which is where the cursor goes back to.
+    start: Position,
+}
+
+impl InsertSession {
+    /// The text typed, for the `".` register.
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for cmd in &self.typed {
+            match cmd {
+                InsertCmd::Char(ch) => text.push(*ch),
+                InsertCmd::Tab => text.push('\t'),
+                InsertCmd::Newline => text.push('\n'),
+                InsertCmd::Backspace => {
+                    text.pop();
+                }
+                InsertCmd::Leave => {}
+            }
+        }
+        text
+    }
+}
+
+/// The longest number Ctrl-V takes after each prefix, and its base.
+fn literal_number(prefix: char) -> Option<(usize, u32)> {
+    Some(match prefix {
+        'u' => (4, 16),
+        'U' => (8, 16),
+        'x' | 'X' => (2, 16),
+        'o' | 'O' => (3, 8),
+        _ => return None,
+    })
+}
+
+/// Ctrl-V: the next key is inserted as it is, or a char code like vim's `^V065`, `^Vu20ac`.
+/// A code is taken once it has all its digits, when a key that isn't a digit follows, or when
+/// no key follows in time.
+fn literal(keys: &[char]) -> Lookup<InsertCmd> {
+    let Some(&first) = keys.first() else {
+        return Lookup::Incomplete;
+    };
+    if keys::is_special(first) {
+        return Lookup::Invalid;
+    }
+    let (prefix_len, max_digits, radix) = match literal_number(first) {
+        Some((max, radix)) => (1, max, radix),
+        None if first.is_ascii_digit() => (0, 3, 10),
+        None => return Lookup::Complete(InsertCmd::Char(first), 1),
+    };
+    let digits: String = keys[prefix_len..]
+        .iter()
+        .take(max_digits)
+        .take_while(|key| key.is_digit(radix))
+        .collect();
+    if digits.is_empty() {
+        return match keys.len() {
+            1 => Lookup::Incomplete,
+            _ => Lookup::Complete(InsertCmd::Char(first), 1),
+        };
+    }
+    let used = prefix_len + digits.len();
+    let Some(ch) = u32::from_str_radix(&digits, radix)
+        .ok()
+        .and_then(char::from_u32)
+    else {
+        return Lookup::Invalid;
+    };
+    if digits.len() == max_digits || keys.len() > used {
+        Lookup::Complete(InsertCmd::Char(ch), used)
+    } else {
+        Lookup::Ambiguous(InsertCmd::Char(ch), used)
+    }
+}
+
+pub fn parse(keys: &[char]) -> Lookup<InsertCmd> {
+    let Some(&first) = keys.first() else {
+        return Lookup::Incomplete;
+    };
+    let cmd = match first {
+        keys::ESC => InsertCmd::Leave,
+        keys::ENTER => InsertCmd::Newline,
+        keys::BACKSPACE | keys::DEL => InsertCmd::Backspace,
+        key if key == ctrl('v') => {
+            return match literal(&keys[1..]) {
+                Lookup::Complete(cmd, used) => Lookup::Complete(cmd, used + 1),
+                Lookup::Ambiguous(cmd, used) => Lookup::Ambiguous(cmd, used + 1),
+                other => other,
+            };
+        }
+        keys::TAB => InsertCmd::Tab,
+        key if key.is_control() || keys::is_special(key) => return Lookup::Invalid,
+        key => InsertCmd::Char(key),
+    };
+    Lookup::Complete(cmd, 1)
+}
+
+impl Editor {
+    /// Starts Insert or Replace mode at the cursor. Everything typed until Escape is one undo
+    /// step, together with whatever the command that started it changed.
+    pub(super) fn enter_insert(&mut self, mode: Mode) {
+        if self.doc().buffer.is_read_only() {
+            self.message = "Buffer is read-only".to_owned();
+            self.mode = Mode::Normal;
+            return;
+        }
+        self.mode = mode;
+        self.replaced.clear();
+        self.insert_session = InsertSession::default();
+    }
+
+    /// Makes leaving Insert or Replace mode type the text again until it's there `count`
+    /// times, each time on a new line with `new_lines`.
+    pub(super) fn repeat_insert(&mut self, count: usize, new_lines: bool) {
+        self.insert_session.extra = count.saturating_sub(1);
+        self.insert_session.new_lines = new_lines;
+    }
+
+    /// Starts Insert mode at screen column `col` of the first of `rows`. On leaving, what was
+    /// typed goes on the others too, as long as it's all on one line.
+    pub(super) fn insert_block(&mut self, col: usize, rows: Vec<usize>) {
+        let Some((&first, others)) = rows.split_first() else {
+            return;
+        };
+        let doc = self.doc_mut();
+        let tabstop = doc.options.tabstop;
+        let start = Position::new(first, block::split_at(&mut doc.buffer, first, col, tabstop));
+        doc.cursor = start;
+        self.enter_insert(Mode::Insert);
+        self.insert_session.block = Some(BlockRows {
+            rows: others.to_vec(),
+            col,
+            start,
+        });
+    }
+
+    pub(super) fn run_insert(&mut self, cmd: InsertCmd) {
+        if cmd != InsertCmd::Leave {
+            self.insert_session.typed.push(cmd);
+        }
+        let replace = self.mode == Mode::Replace;
+        let doc = self.buffers.current_mut();
+        let Position { row, col } = doc.cursor;
+        let cmd = match cmd {
+            InsertCmd::Tab if replace || !doc.options.expandtab => InsertCmd::Char('\t'),
+            cmd => cmd,
+        };
+        match cmd {
+            // With `expandtab`, spaces up to the next tab stop.
+            InsertCmd::Tab => {
+                let line = doc.buffer.line(row).unwrap_or_default();
+                let tabstop = doc.options.tabstop.max(1);
+                let spaces = tabstop - display::screen_col(&line, col, tabstop) % tabstop;
+                doc.buffer.insert(doc.cursor, &" ".repeat(spaces));
+                doc.cursor.col += spaces;
+            }
+            InsertCmd::Char(ch) if replace => {
+                let len = doc.buffer.line_len(row);
+                let end = Position::new(row, (col + 1).min(len));
+                let old = doc.buffer.text_range(doc.cursor, end).chars().next();
+                doc.buffer
+                    .replace(doc.cursor, end, ch.encode_utf8(&mut [0; 4]));
+                self.replaced.push(old);
+                doc.cursor.col += 1;
+            }
+            InsertCmd::Char(ch) => {
+                doc.buffer.insert_char(row, col, ch);
+                doc.cursor.col += 1;
+                if !ch.is_whitespace() {
+                    break_line(doc);
+                }
+            }
+            InsertCmd::Newline => {
+                doc.buffer.split_line(row, col);
+                doc.cursor = Position::new(row + 1, 0);
+                // Backspace after a line break in Replace mode joins the lines again.
+                self.replaced.push(None);
+            }
+            // Replace mode puts back what was typed over, and only moves over the rest.
+            InsertCmd::Backspace if replace => match self.replaced.pop() {
+                Some(Some(old)) => {
+                    let start = Position::new(row, col - 1);
+                    doc.buffer
+                        .replace(start, doc.cursor, old.encode_utf8(&mut [0; 4]));
+                    doc.cursor = start;
+                }
+                Some(None) => {
+                    let (row, col) = doc.buffer.delete_char_before(row, col);
+                    doc.cursor = Position { row, col };
+                }
+                None => doc.cursor.col = col.saturating_sub(1),
+            },
+            InsertCmd::Backspace => {
+                let (row, col) = doc.buffer.delete_char_before(row, col);
+                doc.cursor = Position { row, col };
+            }
+            InsertCmd::Leave => self.leave_insert(),
+        }
+    }
+
+    fn leave_insert(&mut self) {
+        let session = std::mem::take(&mut self.insert_session);
+        let text = session.text();
+        self.registers.inserted = Some(text.clone());
+        for _ in 0..session.extra {
+            if session.new_lines {
+                let doc = self.doc_mut();
+                let row = doc.cursor.row;
+                doc.buffer
+                    .insert(Position::new(row, doc.buffer.line_len(row)), "\n");
+                doc.cursor = Position::new(row + 1, 0);
+            }
+            for &cmd in &session.typed {
+                self.run_insert(cmd);
+            }
+        }
+        let doc = self.buffers.current_mut();
+        if let Some(block) = &session.block
+            && !text.is_empty()
+            && !text.contains('\n')
+        {
+            for &row in &block.rows {
+                let at = block::split_at(&mut doc.buffer, row, block.col, doc.options.tabstop);
+                doc.buffer.insert(Position::new(row, at), &text);
+            }
+        }
+        self.commit_change();
+        let doc = self.buffers.current_mut();
+        doc.buffer.marks_mut().set('^', doc.cursor);
+        doc.cursor.col = doc.cursor.col.saturating_sub(1);
+        if let Some(block) = &session.block {
+            doc.cursor = block.start;
+        }
+        doc.remember_col();
+        self.mode = Mode::Normal;
+        self.clamp_cursor();
+    }
+}
+
+/// With `textwidth`, once typing goes past it, breaks the line at the last blanks before the
+/// cursor that leave what's before them within it. A word too long to fit stays.
+fn break_line(doc: &mut Document) {
+    let width = doc.options.textwidth;
+    let Position { row, col } = doc.cursor;
+    let line = doc.buffer.line(row).unwrap_or_default().into_owned();
+    let tabstop = doc.options.tabstop;
+    if width == 0 || display::screen_col(&line, col, tabstop) <= width {
+        return;
+    }
+    let chars: Vec<char> = line.chars().take(col).collect();
+    let mut blanks = None;
+    let mut start = None;
+    for (i, &ch) in chars.iter().enumerate() {
+        match (ch.is_whitespace(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(from)) => {
+                // Not the indent, and the text before fits.
+                if chars[..from].iter().any(|ch| !ch.is_whitespace())
+                    && display::screen_col(&line, from, tabstop) <= width
+                {
+                    blanks = Some((from, i));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    let Some((from, to)) = blanks else {
+        return;
+    };
+    doc.buffer
+        .replace(Position::new(row, from), Position::new(row, to), "\n");
+    doc.cursor = Position::new(row + 1, col - to);
+}
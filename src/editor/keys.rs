@@ -0,0 +1,246 @@
+//! Keys as the modes see them, and the keys typed so far that don't make a command yet.
+//!
+//! Ctrl+letter is the matching control character, the same as in a terminal, so a key is just a
+//! `char` and a typed sequence is a string that can be shown, stored and replayed. Keys without
+//! a char, like F5, get one from the Private Use Area.
+
+use std::time::{Duration, Instant};
+
+use crate::event::{self, ModifierKeyCode};
+
+pub const ESC: char = '\x1b';
+pub const ENTER: char = '\r';
+pub const TAB: char = '\t';
+pub const BACKSPACE: char = '\x08';
+/// What some terminals send for Backspace.
+pub const DEL: char = '\x7f';
+/// `<Cmd>` in a mapping: the keys after it up to Enter are an Ex command, run in whatever mode
+/// the editor is in. Only what a mapping puts in counts, not the char typed.
+pub const CMD: char = '\u{f700}';
+
+/// How long a sequence that's already complete waits for a key that would make it a longer one.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// The control character for Ctrl+`letter`.
+pub const fn ctrl(letter: char) -> char {
+    ((letter as u8) & 0x1f) as char
+}
+
+/// The key for a key event from the terminal.
+pub fn from_event(ch: char, modifiers: ModifierKeyCode) -> char {
+    // AltGr is reported as Ctrl+Alt and produces a normal char.
+    if modifiers.is_ctrl_pressed() && !modifiers.is_alt_pressed() && ch.is_ascii_alphabetic() {
+        ctrl(ch.to_ascii_lowercase())
+    } else {
+        ch
+    }
+}
+
+/// Whether `key` is one without a char of its own, which can't be typed into text.
+pub fn is_special(key: char) -> bool {
+    key == CMD || event::function_key_number(key).is_some()
+}
+
+/// How `keys` look while being typed, `^V` for control keys as vim's `showcmd` does.
+pub fn show(keys: &[char]) -> String {
+    keys.iter()
+        .map(|&key| match key {
+            '\x00'..='\x1f' => format!("^{}", ((key as u8) ^ 0x40) as char),
+            DEL => "^?".to_owned(),
+            key if is_special(key) => notation(&[key]),
+            key => key.to_string(),
+        })
+        .collect()
+}
+
+/// Names of keys in `<>` notation, besides `<C-x>`, `<F1>` to `<F12>`, `<Leader>` and `<Nop>`.
+/// The first name of a key is the one it's shown with.
+const KEY_NAMES: &[(&str, char)] = &[
+    ("Esc", ESC),
+    ("CR", ENTER),
+    ("Enter", ENTER),
+    ("Return", ENTER),
+    ("NL", '\n'),
+    ("Tab", TAB),
+    ("BS", BACKSPACE),
+    ("Del", DEL),
+    ("Space", ' '),
+    ("lt", '<'),
+    ("Bar", '|'),
+    ("Bslash", '\\'),
+    ("Cmd", CMD),
+];
+
+/// The keys `<name>` stands for, `leader` for `<Leader>`.
+fn named_keys(name: &str, leader: &[char]) -> Option<Vec<char>> {
+    if name.eq_ignore_ascii_case("Leader") {
+        return Some(leader.to_vec());
+    }
+    if name.eq_ignore_ascii_case("Nop") {
+        return Some(Vec::new());
+    }
+    if let Some(&(_, key)) = KEY_NAMES
+        .iter()
+        .find(|(known, _)| name.eq_ignore_ascii_case(known))
+    {
+        return Some(vec![key]);
+    }
+    let mut chars = name.chars();
+    let key = match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some('C' | 'c'), Some('-'), Some(ch), None) if ch.is_ascii_alphabetic() => {
+            ctrl(ch.to_ascii_lowercase())
+        }
+        (Some('C' | 'c'), Some('-'), Some(ch @ ('@' | '[' | '\\' | ']' | '^' | '_')), None) => {
+            ctrl(ch)
+        }
+        (Some('F' | 'f'), ..) => event::function_key(name[1..].parse().ok()?)?,
+        _ => return None,
+    };
+    Some(vec![key])
+}
+
+/// Keys written the way `:map` takes them: `<C-w>`, `<Esc>`, `<F5>`, `<lt>` for a `<` and
+/// `<Leader>` for `leader`. A `<` that doesn't start a key name is just that.
+pub fn parse_notation(text: &str, leader: &[char]) -> Vec<char> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+    while let Some(ch) = rest.chars().next() {
+        if ch == '<'
+            && let Some(end) = rest.find('>')
+            && let Some(named) = named_keys(&rest[1..end], leader)
+        {
+            keys.extend(named);
+            rest = &rest[end + 1..];
+            continue;
+        }
+        keys.push(ch);
+        rest = &rest[ch.len_utf8()..];
+    }
+    keys
+}
+
+/// `keys` in `<>` notation, the way `:map` lists them.
+pub fn notation(keys: &[char]) -> String {
+    if keys.is_empty() {
+        return "<Nop>".to_owned();
+    }
+    keys.iter()
+        .map(|&key| {
+            if let Some(n) = event::function_key_number(key) {
+                return format!("<F{n}>");
+            }
+            match KEY_NAMES.iter().find(|&&(_, known)| known == key) {
+                Some((name, _)) if !matches!(key, '<' | '|' | '\\') => format!("<{name}>"),
+                _ if key.is_ascii_control() => {
+                    let letter = ((key as u8) ^ 0x40) as char;
+                    format!("<C-{}>", letter.to_ascii_lowercase())
+                }
+                _ => key.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// What a sequence of keys means to a mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lookup<T> {
+    /// Not a command and no more keys will make it one.
+    Invalid,
+    /// A command so far, which the next keys complete.
+    Incomplete,
+    /// A command that uses this many of the keys. The rest are looked up afterwards.
+    Complete(T, usize),
+    /// A command that uses this many of the keys, but more keys could still make it a different
+    /// one. It's taken once a key arrives that doesn't continue it, or after the timeout.
+    Ambiguous(T, usize),
+}
+
+/// Where a pending key came from, which decides whether mappings apply to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Origin {
+    /// Typed, or played from a macro or by `:normal`.
+    #[default]
+    Typed,
+    /// Typed, but a mapping it could have started timed out, so it's taken as it is.
+    Unmapped,
+    /// Put there by a mapping, which maps them again if it's recursive. Those of a `<silent>`
+    /// one don't show.
+    Mapped { remap: bool, silent: bool },
+}
+
+impl Origin {
+    pub fn remaps(self) -> bool {
+        matches!(self, Self::Typed | Self::Mapped { remap: true, .. })
+    }
+}
+
+/// Keys typed but not yet turned into a command, and where each came from.
+#[derive(Debug, Default)]
+pub struct PendingKeys {
+    keys: Vec<char>,
+    origins: Vec<Origin>,
+    last_key: Option<Instant>,
+}
+
+impl PendingKeys {
+    pub fn push(&mut self, key: char) {
+        self.keys.push(key);
+        self.origins.push(Origin::Typed);
+        self.last_key = Some(Instant::now());
+    }
+
+    pub fn keys(&self) -> &[char] {
+        &self.keys
+    }
+
+    pub fn origin(&self, idx: usize) -> Origin {
+        self.origins.get(idx).copied().unwrap_or_default()
+    }
+
+    pub fn set_origin(&mut self, idx: usize, origin: Origin) {
+        if let Some(old) = self.origins.get_mut(idx) {
+            *old = origin;
+        }
+    }
+
+    /// The keys to show in the status line, without those of `<silent>` mappings.
+    pub fn shown(&self) -> Vec<char> {
+        self.keys
+            .iter()
+            .zip(&self.origins)
+            .filter(|(_, origin)| !matches!(origin, Origin::Mapped { silent: true, .. }))
+            .map(|(&key, _)| key)
+            .collect()
+    }
+
+    /// Puts `keys` before the ones typed, to be taken as if they'd been typed first.
+    pub fn prepend(&mut self, keys: &[char], origin: Origin) {
+        self.keys.splice(..0, keys.iter().copied());
+        self.origins.splice(..0, keys.iter().map(|_| origin));
+    }
+
+    /// Replaces `len` keys from `at`, the ones a mapping was made from, with what it maps them
+    /// to.
+    pub fn expand(&mut self, at: usize, len: usize, keys: &[char], origin: Origin) {
+        self.keys.splice(at..at + len, keys.iter().copied());
+        self.origins
+            .splice(at..at + len, keys.iter().map(|_| origin));
+    }
+
+    /// Drops the first `count` keys, the ones a command was made from.
+    pub fn consume(&mut self, count: usize) {
+        let count = count.min(self.keys.len());
+        self.keys.drain(..count);
+        self.origins.drain(..count);
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.origins.clear();
+    }
+
+    /// Whether it's been longer than `timeout` since the last key.
+    pub fn timed_out(&self, timeout: Duration) -> bool {
+        !self.keys.is_empty() && self.last_key.is_some_and(|last| last.elapsed() >= timeout)
+    }
+}
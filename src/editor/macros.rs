@@ -0,0 +1,97 @@
+//! Macros: keys recorded into a register with `q` and played back with `@`. They're kept as
+//! the keys' text, so a macro can be put into the buffer, edited and yanked back.
+
+use super::Editor;
+use super::keys::Origin;
+use super::register::Register;
+
+#[derive(Debug, Default)]
+pub struct Macros {
+    /// The register being recorded into and the keys typed since.
+    recording: Option<(char, String)>,
+    /// The register last played, which `@@` plays again.
+    last_played: Option<char>,
+}
+
+impl Macros {
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(name, _)| *name)
+    }
+}
+
+/// Whether `q` followed by `name` records a macro.
+pub fn is_recordable(name: char) -> bool {
+    name.is_ascii_alphanumeric() || name == '"'
+}
+
+impl Editor {
+    /// Notes a key typed while recording.
+    pub(super) fn record_key(&mut self, key: char) {
+        if let Some((_, keys)) = &mut self.macros.recording {
+            keys.push(key);
+        }
+    }
+
+    /// `q{register}`
+    pub(super) fn start_recording(&mut self, name: char) {
+        self.macros.recording = Some((name, String::new()));
+    }
+
+    /// `q` while recording: the keys typed, minus that `q`, go to the register.
+    pub(super) fn stop_recording(&mut self) {
+        let Some((name, mut keys)) = self.macros.recording.take() else {
+            return;
+        };
+        keys.pop();
+        if let Err(e) = self
+            .registers
+            .store(Some(name), Register::chars(&keys), true)
+        {
+            self.message = e.to_string();
+        }
+    }
+
+    /// `@{register}`, `count` times over. `@@` plays the last one again and `@:` repeats the
+    /// last Ex command.
+    pub(super) fn play_macro(&mut self, name: char, count: usize) {
+        let name = match name {
+            '@' => match self.macros.last_played {
+                Some(name) => name,
+                None => {
+                    self.message = "No previously used register".to_owned();
+                    return;
+                }
+            },
+            name => name,
+        };
+        self.macros.last_played = Some(name);
+        if name == ':' {
+            let Some(command) = self.registers.command.clone() else {
+                self.message = "No previous command line".to_owned();
+                return;
+            };
+            for _ in 0..count {
+                if let Err(e) = self.execute(&command) {
+                    self.message = e.to_string();
+                    break;
+                }
+            }
+            return;
+        }
+        let register = match self.registers.get(name) {
+            Ok(register) => register,
+            Err(e) => {
+                self.message = e.to_string();
+                return;
+            }
+        };
+        let keys: Vec<char> = register.text.chars().collect();
+        self.pending.prepend(&keys.repeat(count), Origin::Typed);
+    }
+
+    /// Something a command needed wasn't there, like a motion's target. As in vim, keys typed
+    /// ahead are dropped, which is also what stops a macro being played.
+    pub(super) fn fail(&mut self) {
+        self.pending.clear();
+    }
+}
@@ -0,0 +1,431 @@
+//! `:map` and friends: keys that stand for other keys. A mapping is expanded in the pending
+//! keys, where the keys it puts there are taken as if typed, mapped again unless it's a
+//! `:noremap`.
+
+use thiserror::Error;
+
+use super::Editor;
+use super::commands::abbrev;
+use super::keys::{self, Origin};
+use super::mode::Mode;
+use super::normal;
+
+#[derive(Debug, Error)]
+pub enum MappingError {
+    #[error("No such mapping")]
+    NotFound,
+    #[error("No mapping found")]
+    NoneFound,
+    #[error("Argument required")]
+    ArgumentRequired,
+    #[error("Recursive mapping")]
+    Recursive,
+    #[error("Invalid mode: {0}")]
+    InvalidMode(String),
+}
+
+/// How many mappings can be expanded in a row before a command comes out of them, to stop
+/// mappings that map to each other.
+pub const MAX_DEPTH: usize = 1000;
+
+/// The modes a mapping applies in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modes(u8);
+
+impl Modes {
+    const NORMAL: Self = Self(1);
+    const VISUAL: Self = Self(2);
+    const OPERATOR: Self = Self(4);
+    const INSERT: Self = Self(8);
+    const COMMAND_LINE: Self = Self(16);
+    const ALL: Self = Self(31);
+
+    /// The modes vim's letters for them stand for: `n`, `v` or `x`, `o`, `i` and `c`.
+    fn from_letters(letters: &str) -> Self {
+        Self(
+            letters
+                .chars()
+                .map(|letter| match letter {
+                    'n' => Self::NORMAL.0,
+                    'v' | 'x' => Self::VISUAL.0,
+                    'o' => Self::OPERATOR.0,
+                    'i' => Self::INSERT.0,
+                    'c' => Self::COMMAND_LINE.0,
+                    _ => 0,
+                })
+                .fold(0, |modes, mode| modes | mode),
+        )
+    }
+
+    fn of(mode: Mode) -> Self {
+        match mode {
+            Mode::Normal => Self::NORMAL,
+            Mode::Visual(_) => Self::VISUAL,
+            Mode::OperatorPending(_) => Self::OPERATOR,
+            Mode::Insert | Mode::Replace => Self::INSERT,
+            Mode::CommandLine => Self::COMMAND_LINE,
+        }
+    }
+
+    fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// How `:map` shows the modes: blank for Normal, Visual and Operator-pending, `!` for
+    /// Insert and Command-line, otherwise their letters.
+    fn label(self) -> String {
+        match self.0 {
+            7 => " ".to_owned(),
+            24 => "!".to_owned(),
+            _ => [
+                (Self::NORMAL, 'n'),
+                (Self::VISUAL, 'x'),
+                (Self::OPERATOR, 'o'),
+                (Self::INSERT, 'i'),
+                (Self::COMMAND_LINE, 'c'),
+            ]
+            .iter()
+            .filter(|(mode, _)| self.intersects(*mode))
+            .map(|&(_, letter)| letter)
+            .collect(),
+        }
+    }
+}
+
+/// What a mapping command does, in which modes. `!` after `:map`, `:noremap` and `:unmap`
+/// makes them about Insert and Command-line mode instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Map { remap: bool },
+    Unmap,
+}
+
+/// Every mapping command: its name, how short it can be abbreviated, what it does and the
+/// modes it's for.
+const COMMANDS: &[(&str, usize, Kind, &str)] = &[
+    ("map", 3, Kind::Map { remap: true }, "nvo"),
+    ("nmap", 2, Kind::Map { remap: true }, "n"),
+    ("vmap", 2, Kind::Map { remap: true }, "v"),
+    ("xmap", 2, Kind::Map { remap: true }, "x"),
+    ("omap", 2, Kind::Map { remap: true }, "o"),
+    ("imap", 2, Kind::Map { remap: true }, "i"),
+    ("cmap", 2, Kind::Map { remap: true }, "c"),
+    ("noremap", 2, Kind::Map { remap: false }, "nvo"),
+    ("nnoremap", 2, Kind::Map { remap: false }, "n"),
+    ("vnoremap", 2, Kind::Map { remap: false }, "v"),
+    ("xnoremap", 2, Kind::Map { remap: false }, "x"),
+    ("onoremap", 3, Kind::Map { remap: false }, "o"),
+    ("inoremap", 3, Kind::Map { remap: false }, "i"),
+    ("cnoremap", 3, Kind::Map { remap: false }, "c"),
+    ("unmap", 3, Kind::Unmap, "nvo"),
+    ("nunmap", 3, Kind::Unmap, "n"),
+    ("vunmap", 2, Kind::Unmap, "v"),
+    ("xunmap", 2, Kind::Unmap, "x"),
+    ("ounmap", 2, Kind::Unmap, "o"),
+    ("iunmap", 2, Kind::Unmap, "i"),
+    ("cunmap", 2, Kind::Unmap, "c"),
+];
+
+fn find_command(name: &str) -> Option<(&'static str, Kind, &'static str)> {
+    COMMANDS
+        .iter()
+        .find(|&&(full, min, ..)| abbrev(name, full, min))
+        .map(|&(full, _, kind, modes)| (full, kind, modes))
+}
+
+pub fn is_command(name: &str) -> bool {
+    find_command(name).is_some()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub lhs: Vec<char>,
+    pub rhs: Vec<char>,
+    modes: Modes,
+    /// What it maps to is mapped again, unlike with `:noremap`.
+    remap: bool,
+    /// The keys it maps to don't show while pending.
+    silent: bool,
+}
+
+/// The mappings of the editor, or those of one buffer made with `<buffer>`.
+#[derive(Debug, Clone, Default)]
+pub struct Mappings(Vec<Mapping>);
+
+impl Mappings {
+    /// What's there before any `:map`: Ctrl-Q quits, asking first when something would be
+    /// lost, and Ctrl-S saves, in every mode.
+    pub fn defaults() -> Self {
+        let mut mappings = Self::default();
+        for (lhs, rhs) in [
+            ("<C-q>", "<Cmd>confirm quitall<CR>"),
+            ("<C-s>", "<Cmd>write<CR>"),
+        ] {
+            mappings.add(Mapping {
+                lhs: keys::parse_notation(lhs, &[]),
+                rhs: keys::parse_notation(rhs, &[]),
+                modes: Modes::ALL,
+                remap: false,
+                silent: true,
+            });
+        }
+        mappings
+    }
+
+    /// Adds `mapping`, which replaces what `lhs` was mapped to in its modes before.
+    fn add(&mut self, mapping: Mapping) {
+        for old in self.0.iter_mut().filter(|old| old.lhs == mapping.lhs) {
+            old.modes = old.modes.without(mapping.modes);
+        }
+        self.0.retain(|old| !old.modes.is_empty());
+        self.0.push(mapping);
+    }
+
+    /// Takes `lhs` out of `modes`, returning whether it was mapped in any of them.
+    fn remove(&mut self, lhs: &[char], modes: Modes) -> bool {
+        let mut found = false;
+        for old in self.0.iter_mut().filter(|old| old.lhs == lhs) {
+            found |= old.modes.intersects(modes);
+            old.modes = old.modes.without(modes);
+        }
+        self.0.retain(|old| !old.modes.is_empty());
+        found
+    }
+
+    /// The mapping in `modes` with the longest `lhs` that `keys` start with, and whether
+    /// `keys` could still become the start of a longer one.
+    fn lookup(&self, keys: &[char], modes: Modes) -> (Option<&Mapping>, bool) {
+        let in_modes = || {
+            self.0
+                .iter()
+                .filter(|mapping| mapping.modes.intersects(modes))
+        };
+        let full = in_modes()
+            .filter(|mapping| keys.starts_with(&mapping.lhs) && !mapping.lhs.is_empty())
+            .max_by_key(|mapping| mapping.lhs.len());
+        let longer = in_modes()
+            .any(|mapping| mapping.lhs.len() > keys.len() && mapping.lhs.starts_with(keys));
+        (full, longer)
+    }
+
+    /// The mappings in `modes` whose `lhs` starts with `prefix`.
+    fn list<'a>(&'a self, prefix: &'a [char], modes: Modes) -> impl Iterator<Item = &'a Mapping> {
+        self.0.iter().filter(move |mapping| {
+            mapping.modes.intersects(modes) && mapping.lhs.starts_with(prefix)
+        })
+    }
+}
+
+/// What looking for a mapping in the pending keys came to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expansion {
+    /// A mapping was expanded, which may have made keys to expand again.
+    Expanded,
+    /// The keys might be the start of a mapping, so more are needed before they mean anything.
+    Waiting,
+    /// The keys are to be taken as they are.
+    None,
+}
+
+impl Editor {
+    /// `:map` and the other commands in `COMMANDS`. Without keys they list mappings, with only
+    /// the left-hand side those starting with it. `<buffer>` and `<silent>` can come first.
+    pub(super) fn map_command(
+        &mut self,
+        name: &str,
+        bang: bool,
+        arg: &str,
+    ) -> Result<(), MappingError> {
+        let Some((_, kind, letters)) = find_command(name) else {
+            return Err(MappingError::NotFound);
+        };
+        let modes = Modes::from_letters(if bang && letters == "nvo" {
+            "ic"
+        } else {
+            letters
+        });
+        let (mut buffer, mut silent) = (false, false);
+        let mut rest = arg.trim_start();
+        loop {
+            if let Some(after) = rest.strip_prefix("<buffer>") {
+                buffer = true;
+                rest = after.trim_start();
+            } else if let Some(after) = rest.strip_prefix("<silent>") {
+                silent = true;
+                rest = after.trim_start();
+            } else {
+                break;
+            }
+        }
+        let (lhs, rhs) = match rest.split_once(char::is_whitespace) {
+            Some((lhs, rhs)) => (lhs, rhs.trim_start()),
+            None => (rest, ""),
+        };
+        let leader = keys::parse_notation(&self.map_leader, &[]);
+        let lhs = keys::parse_notation(lhs, &leader);
+        let mappings = if buffer {
+            &mut self.buffers.current_mut().mappings
+        } else {
+            &mut self.mappings
+        };
+        match kind {
+            Kind::Unmap if lhs.is_empty() => Err(MappingError::ArgumentRequired),
+            Kind::Unmap if mappings.remove(&lhs, modes) => Ok(()),
+            Kind::Unmap => Err(MappingError::NotFound),
+            Kind::Map { .. } if rhs.is_empty() => self.list_mappings(&lhs, modes, buffer),
+            Kind::Map { .. } if lhs.is_empty() => Err(MappingError::ArgumentRequired),
+            Kind::Map { remap } => {
+                mappings.add(Mapping {
+                    lhs,
+                    rhs: keys::parse_notation(rhs, &leader),
+                    modes,
+                    remap,
+                    silent,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Maps `lhs` to `rhs` in the modes named by `letters`, as in `:map` but for the config
+    /// file's `[keymaps]`.
+    pub(super) fn add_mapping(
+        &mut self,
+        letters: &str,
+        lhs: &str,
+        rhs: &str,
+        remap: bool,
+        silent: bool,
+    ) -> Result<(), MappingError> {
+        let modes = Modes::from_letters(letters);
+        if modes.is_empty() || !letters.chars().all(|letter| "nvxoic".contains(letter)) {
+            return Err(MappingError::InvalidMode(letters.to_owned()));
+        }
+        let leader = keys::parse_notation(&self.map_leader, &[]);
+        let lhs = keys::parse_notation(lhs, &leader);
+        if lhs.is_empty() {
+            return Err(MappingError::ArgumentRequired);
+        }
+        self.mappings.add(Mapping {
+            lhs,
+            rhs: keys::parse_notation(rhs, &leader),
+            modes,
+            remap,
+            silent,
+        });
+        Ok(())
+    }
+
+    /// Shows the mappings in `modes` starting with `prefix`, the buffer's first, `*` marking
+    /// those not mapped again and `@` those of the buffer.
+    fn list_mappings(
+        &mut self,
+        prefix: &[char],
+        modes: Modes,
+        buffer_only: bool,
+    ) -> Result<(), MappingError> {
+        let show = |mapping: &Mapping, local: bool| {
+            format!(
+                "{:<3}{:<12} {}{}{}",
+                mapping.modes.label(),
+                keys::notation(&mapping.lhs),
+                if mapping.remap { ' ' } else { '*' },
+                if local { '@' } else { ' ' },
+                keys::notation(&mapping.rhs)
+            )
+        };
+        let local = self
+            .doc()
+            .mappings
+            .list(prefix, modes)
+            .map(|m| show(m, true));
+        let mut lines: Vec<String> = local.collect();
+        if !buffer_only {
+            lines.extend(self.mappings.list(prefix, modes).map(|m| show(m, false)));
+        }
+        if lines.is_empty() {
+            return Err(MappingError::NoneFound);
+        }
+        self.pager = Some(lines);
+        Ok(())
+    }
+
+    /// Expands a mapping at the start of the pending keys, or in Normal, Visual and
+    /// Operator-pending mode at the start of the command after a count and register. The
+    /// buffer's mappings come before the editor's, and the longest match wins. Keys that could
+    /// still become a longer mapping wait for more, until `timed_out`.
+    pub(super) fn expand_mapping(&mut self, timed_out: bool) -> Expansion {
+        if self.mode == Mode::CommandLine && self.command_line.takes_literal() {
+            return Expansion::None;
+        }
+        let keys = self.pending.keys();
+        let at = match self.mode {
+            Mode::Normal | Mode::Visual(_) | Mode::OperatorPending(_) => {
+                normal::command_start(keys, self.mode)
+            }
+            _ => 0,
+        };
+        if at >= keys.len() || !self.pending.origin(at).remaps() {
+            return Expansion::None;
+        }
+        let keys = &keys[at..];
+        let modes = Modes::of(self.mode);
+        let (mut found, mut longer) = self.doc().mappings.lookup(keys, modes);
+        if found.is_none() && !longer {
+            (found, longer) = self.mappings.lookup(keys, modes);
+        }
+        if longer && !timed_out {
+            return Expansion::Waiting;
+        }
+        let Some(mapping) = found.cloned() else {
+            if longer {
+                self.pending.set_origin(at, Origin::Unmapped);
+            }
+            return Expansion::None;
+        };
+        let origin = Origin::Mapped {
+            remap: mapping.remap,
+            silent: mapping.silent,
+        };
+        self.pending
+            .expand(at, mapping.lhs.len(), &mapping.rhs, origin);
+        // As in vi, a mapping to keys starting with its own doesn't map those again.
+        if mapping.remap && mapping.rhs.starts_with(&mapping.lhs) {
+            for idx in at..at + mapping.lhs.len() {
+                self.pending.set_origin(
+                    idx,
+                    Origin::Mapped {
+                        remap: false,
+                        silent: mapping.silent,
+                    },
+                );
+            }
+        }
+        Expansion::Expanded
+    }
+
+    /// `<Cmd>` at the start of the pending keys: runs the command up to Enter, without leaving
+    /// the mode the editor is in.
+    pub(super) fn run_cmd_key(&mut self) {
+        let keys = self.pending.keys();
+        let Some(end) = keys.iter().position(|&key| key == keys::ENTER) else {
+            self.pending.clear();
+            self.message = "<Cmd> mapping must end with <CR>".to_owned();
+            return;
+        };
+        let line: String = keys[1..end].iter().collect();
+        self.pending.consume(end + 1);
+        if let Err(e) = self.execute(&line) {
+            self.message = e.to_string();
+        }
+        self.clamp_cursor();
+    }
+}
@@ -0,0 +1,267 @@
+//! Marks, the jump list and the change list. The positions themselves live in the buffers'
+//! [`Marks`](crate::buffer::marks::Marks), which keep them on their text through edits; the
+//! editor keeps what spans buffers.
+
+use std::path::PathBuf;
+
+use super::Editor;
+use super::mode::Mode;
+use super::motion::first_non_blank;
+use crate::buffer::Position;
+
+/// How many jumps Ctrl-O can go back through, vim's number.
+const JUMP_LIST_LEN: usize = 100;
+
+/// The order `:marks` lists them in.
+const LISTED: &str = "'abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ[]^.<>";
+
+/// Whether `m` followed by `name` sets a mark.
+pub fn is_settable(name: char) -> bool {
+    name.is_ascii_alphabetic() || "'`[]<>".contains(name)
+}
+
+/// The mark `'` or `` ` `` followed by `key` jumps to, if any. Both quotes are the previous
+/// context mark.
+pub fn mark_name(key: char) -> Option<char> {
+    match key {
+        '`' => Some('\''),
+        key if key.is_ascii_alphabetic() || "'[]^.<>".contains(key) => Some(key),
+        _ => None,
+    }
+}
+
+/// A place jumped from, in the buffer numbered `doc`. Its position is kept in that buffer's
+/// marks under `id`.
+#[derive(Debug, Clone, Copy)]
+struct Jump {
+    doc: usize,
+    id: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct JumpList {
+    jumps: Vec<Jump>,
+    /// The entry Ctrl-O and Ctrl-I went to last, `jumps.len()` after a new jump.
+    index: usize,
+    next_id: u64,
+}
+
+impl Editor {
+    /// `m{mark}`: sets the mark at the cursor. A file mark, `A` to `Z`, is moved here from
+    /// wherever it was.
+    pub(super) fn set_mark(&mut self, name: char) {
+        let name = mark_name(name).unwrap_or(name);
+        if name.is_ascii_uppercase() {
+            for doc in self.buffers.iter_mut() {
+                doc.buffer.marks_mut().remove(name);
+            }
+            self.file_marks.retain(|&(mark, ..)| mark != name);
+        }
+        let cursor = self.doc().cursor;
+        self.doc_mut().buffer.marks_mut().set(name, cursor);
+    }
+
+    /// `'<` and `'>` for the selection, while it's still there.
+    pub(super) fn set_visual_marks(&mut self) {
+        let Mode::Visual(kind) = self.mode else {
+            return;
+        };
+        let region = self.selection(kind);
+        let marks = self.doc_mut().buffer.marks_mut();
+        marks.set('<', region.start);
+        marks.set('>', region.end);
+    }
+
+    /// Notes the cursor as a place jumped from: the `''` mark and a new entry at the end of
+    /// the jump list, replacing an older one for the same line.
+    pub(super) fn push_jump(&mut self) {
+        let doc = self.buffers.current_mut();
+        let (cursor, id) = (doc.cursor, doc.id);
+        let marks = doc.buffer.marks_mut();
+        marks.set('\'', cursor);
+        let list = &mut self.jumps;
+        list.jumps.retain(|jump| {
+            let same_line =
+                jump.doc == id && marks.jump(jump.id).is_none_or(|pos| pos.row == cursor.row);
+            if same_line {
+                marks.remove_jump(jump.id);
+            }
+            !same_line
+        });
+        marks.set_jump(list.next_id, cursor);
+        list.jumps.push(Jump {
+            doc: id,
+            id: list.next_id,
+        });
+        list.next_id += 1;
+        if list.jumps.len() > JUMP_LIST_LEN {
+            let oldest = list.jumps.remove(0);
+            if let Some(idx) = self.buffers.index_of(oldest.doc) {
+                self.buffers
+                    .get_mut(idx)
+                    .buffer
+                    .marks_mut()
+                    .remove_jump(oldest.id);
+            }
+        }
+        self.jumps.index = self.jumps.jumps.len();
+    }
+
+    /// Ctrl-O with a negative `delta`, Ctrl-I with a positive one: that many entries through
+    /// the jump list, switching buffers if need be.
+    pub(super) fn step_jump(&mut self, delta: isize) {
+        if delta < 0 && self.jumps.index >= self.jumps.jumps.len() {
+            // Going back from a new position: keep it, so Ctrl-I can return to it.
+            self.push_jump();
+            self.jumps.index = self.jumps.jumps.len() - 1;
+        }
+        let Some(index) = self
+            .jumps
+            .index
+            .checked_add_signed(delta)
+            .filter(|&index| index < self.jumps.jumps.len())
+        else {
+            self.fail();
+            return;
+        };
+        let jump = self.jumps.jumps[index];
+        let target = self.buffers.index_of(jump.doc).and_then(|idx| {
+            let pos = self.buffers.get_mut(idx).buffer.marks().jump(jump.id)?;
+            Some((idx, pos))
+        });
+        let Some((idx, pos)) = target else {
+            // The buffer was deleted since.
+            self.jumps.jumps.remove(index);
+            self.fail();
+            return;
+        };
+        self.jumps.index = index;
+        if idx != self.buffers.current_index() {
+            self.switch_to(idx);
+        }
+        let doc = self.doc_mut();
+        doc.cursor = doc.buffer.clamp(pos);
+        doc.remember_col();
+    }
+
+    /// `g;` with a negative `delta`, `g,` with a positive one.
+    pub(super) fn step_change(&mut self, delta: isize) {
+        let doc = self.doc_mut();
+        match doc.buffer.marks_mut().step_change(delta) {
+            Ok(pos) => {
+                doc.cursor = doc.buffer.clamp(pos);
+                doc.remember_col();
+            }
+            Err(e) => {
+                self.message = e.to_string();
+                self.fail();
+            }
+        }
+    }
+
+    /// `'A` or `` `A `` for a file mark in another buffer, or in a file that isn't open any
+    /// more, which is opened again. Returns whether the mark was found.
+    pub(super) fn jump_to_file_mark(&mut self, name: char, line: bool) -> bool {
+        let open = self
+            .buffers
+            .iter()
+            .position(|doc| doc.buffer.marks().get(name).is_some());
+        let closed = self
+            .file_marks
+            .iter()
+            .find(|&&(mark, ..)| mark == name)
+            .map(|(_, path, _)| path.clone());
+        match (open, closed) {
+            (Some(idx), _) => {
+                self.push_jump();
+                self.switch_to(idx);
+            }
+            (None, Some(path)) => {
+                self.push_jump();
+                self.open(path);
+            }
+            (None, None) => return false,
+        }
+        let doc = self.doc_mut();
+        if let Some(pos) = doc.buffer.marks().get(name) {
+            let pos = doc.buffer.clamp(pos);
+            doc.cursor = if line {
+                Position::new(pos.row, first_non_blank(&doc.buffer, pos.row))
+            } else {
+                pos
+            };
+            doc.remember_col();
+        }
+        true
+    }
+
+    /// Keeps the file marks of the buffer at `idx`, which is being closed, to go back to
+    /// later.
+    pub(super) fn stash_file_marks(&mut self, idx: usize) {
+        let doc = self.buffers.get_mut(idx);
+        let Some(path) = doc.buffer.path() else {
+            return;
+        };
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        for (name, pos) in doc.buffer.marks().iter() {
+            if name.is_ascii_uppercase() {
+                self.file_marks.push((name, path.clone(), pos));
+            }
+        }
+    }
+
+    /// Gives the current buffer back the file marks it had when its file was last open.
+    pub(super) fn restore_file_marks(&mut self) {
+        let Some(path) = self.doc().buffer.path() else {
+            return;
+        };
+        let path: PathBuf = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let (restored, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.file_marks)
+            .into_iter()
+            .partition(|(_, marked, _)| *marked == path);
+        self.file_marks = kept;
+        for (name, _, pos) in restored {
+            self.doc_mut().buffer.marks_mut().set(name, pos);
+        }
+    }
+
+    /// `:marks`, all of them or those in `names`.
+    pub(super) fn list_marks(&mut self, names: &str) {
+        let mut lines = vec!["mark line  col file/text".to_owned()];
+        let current = self.buffers.current_index();
+        for name in LISTED.chars() {
+            if !names.is_empty() && !names.contains(name) {
+                continue;
+            }
+            let here = self.doc().buffer.marks().get(name);
+            let elsewhere = || {
+                if !name.is_ascii_uppercase() {
+                    return None;
+                }
+                let open = self.buffers.iter().enumerate().find_map(|(idx, doc)| {
+                    let pos = doc.buffer.marks().get(name)?;
+                    (idx != current).then(|| (pos, doc.name()))
+                });
+                open.or_else(|| {
+                    self.file_marks
+                        .iter()
+                        .find(|&&(mark, ..)| mark == name)
+                        .map(|(_, path, pos)| (*pos, path.display().to_string()))
+                })
+            };
+            let (pos, text) = match here {
+                Some(pos) => {
+                    let buffer = &self.doc().buffer;
+                    let text = buffer.line(pos.row).unwrap_or_default();
+                    (pos, text.trim_start().to_owned())
+                }
+                None => match elsewhere() {
+                    Some(found) => found,
+                    None => continue,
+                },
+            };
+            lines.push(format!(" {name} {:6} {:4} {text}", pos.row + 1, pos.col));
+        }
+        self.pager = Some(lines);
+    }
+}
@@ -0,0 +1,981 @@
+mod block;
+mod cmdline;
+mod commands;
+mod config;
+mod document;
+mod filetype;
+mod insert;
+mod keys;
+mod macros;
+mod mapping;
+mod marks;
+mod mode;
+mod modeline;
+mod motion;
+mod normal;
+mod operator;
+mod options;
+mod range;
+mod register;
+mod repeat;
+mod search;
+mod substitute;
+mod textobject;
+mod word;
+
+use crate::buffer::{Buffer, DiskState, Position};
+use crate::command::*;
+use crate::command::{ClearType, Command};
+use crate::diff;
+use crate::display::{self, ColorScheme, Span, Style, View};
+use crate::event::{Event, KeyPressState, ModifierKeyCode};
+use crate::fileio::WriteOptions;
+use crate::paths;
+use crate::swap::{ExistingSwap, SwapFile};
+use crate::terminal::Terminal;
+use crate::watch::FileWatcher;
+use cmdline::{CommandLine, Prompt};
+use document::{BufferList, Document};
+use insert::InsertSession;
+use keys::{Lookup, Origin, PendingKeys};
+use macros::Macros;
+use mapping::{Expansion, MappingError, Mappings};
+use marks::JumpList;
+use mode::{Mode, VisualKind};
+use motion::FindChar;
+use options::{BufferOptions, WindowOptions};
+use register::Registers;
+use repeat::{Recorder, Step};
+use search::{Match, Search, SearchOptions};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
+use substitute::{LastSubstitute, Run};
+
+#[derive(Default)]
+pub struct Editor {
+    wants_exit: bool,
+    terminal: Terminal,
+    buffers: BufferList,
+    message: String,
+    write_options: WriteOptions,
+    /// Where undo history is persisted, `None` to keep it in memory only.
+    undo_dir: Option<PathBuf>,
+    /// Where swap files go, `None` to not write any.
+    swap_dir: Option<PathBuf>,
+    mode: Mode,
+    /// Keys typed that don't make a complete command yet.
+    pending: PendingKeys,
+    /// How long an ambiguous key sequence waits for more keys.
+    key_timeout: Duration,
+    registers: Registers,
+    /// The register named with `"x` for the command being typed.
+    register: Option<char>,
+    /// The end of the visual selection the cursor isn't at.
+    visual_anchor: Position,
+    /// Chars typed over in Replace mode, `None` where the text was extended or a line broken,
+    /// so Backspace can put them back.
+    replaced: Vec<Option<char>>,
+    insert_session: InsertSession,
+    /// The change being typed and the last one, for `.`.
+    repeat: Recorder,
+    macros: Macros,
+    jumps: JumpList,
+    /// File marks of files that aren't open any more, with the file's canonical path.
+    file_marks: Vec<(char, PathBuf, Position)>,
+    /// Text being typed in Command-line mode.
+    command_line: CommandLine,
+    /// How many Ex commands are running, nested in `:normal`.
+    ex_depth: usize,
+    /// `:global` is running its command on the lines it marked.
+    in_global: bool,
+    /// The last `f`, `t`, `F` or `T`, which `;` and `,` repeat.
+    last_find: Option<FindChar>,
+    /// The last pattern searched for, which `n` and `N` look for again.
+    last_search: Option<Search>,
+    search_options: SearchOptions,
+    /// Matches of the last search are highlighted, until `:nohlsearch`.
+    highlight_search: bool,
+    /// The last `:s`, for `:&` and `&` to repeat.
+    last_substitute: Option<LastSubstitute>,
+    /// A `:s///c` waiting for an answer about a match.
+    substitute_prompt: Option<Run>,
+    /// How many rows of text fit on screen, as of the last render.
+    text_rows: usize,
+    /// Output too long for the message area (`:ls`, a diff), shown in place of the text until
+    /// a key is pressed.
+    pager: Option<Vec<String>>,
+    /// The global values of buffer-local options, which new buffers start with.
+    buffer_options: BufferOptions,
+    window_options: WindowOptions,
+    colors: ColorScheme,
+    /// Whether modelines are read, and in how many lines at either end of a file.
+    modeline: bool,
+    modelines: usize,
+    /// `:map` and the rest, except those made with `<buffer>`.
+    mappings: Mappings,
+    /// What `<Leader>` stands for in mappings made after it's set.
+    map_leader: String,
+    /// `:confirm quit` found these buffers with unsaved changes, with their change ticks.
+    /// Asking again while they're the same quits anyway.
+    quit_pending: Option<Vec<(usize, u64)>>,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        let mut editor = Self {
+            wants_exit: false,
+            terminal: Terminal::new().expect("Terminal initialization failed"),
+            buffers: BufferList::default(),
+            message: String::new(),
+            write_options: WriteOptions::default(),
+            undo_dir: paths::state_dir().map(|dir| dir.join("undo")),
+            swap_dir: paths::state_dir().map(|dir| dir.join("swap")),
+            mode: Mode::Normal,
+            pending: PendingKeys::default(),
+            key_timeout: keys::DEFAULT_TIMEOUT,
+            registers: Registers::default(),
+            register: None,
+            visual_anchor: Position::default(),
+            replaced: Vec::new(),
+            insert_session: InsertSession::default(),
+            repeat: Recorder::default(),
+            macros: Macros::default(),
+            jumps: JumpList::default(),
+            file_marks: Vec::new(),
+            command_line: CommandLine::default(),
+            ex_depth: 0,
+            in_global: false,
+            last_find: None,
+            last_search: None,
+            search_options: SearchOptions::default(),
+            highlight_search: true,
+            last_substitute: None,
+            substitute_prompt: None,
+            text_rows: 0,
+            pager: None,
+            buffer_options: BufferOptions::default(),
+            window_options: WindowOptions::default(),
+            colors: ColorScheme::default(),
+            modeline: true,
+            modelines: modeline::DEFAULT_LINES,
+            mappings: Mappings::defaults(),
+            map_leader: "\\".to_owned(),
+            quit_pending: None,
+        };
+        editor.load_config();
+        editor.buffers.current_mut().options = editor.buffer_options.clone();
+        editor
+    }
+
+    fn doc(&self) -> &Document {
+        self.buffers.current()
+    }
+
+    fn doc_mut(&mut self) -> &mut Document {
+        self.buffers.current_mut()
+    }
+
+    /// Loads `path` into a new buffer and switches to it, or just switches if it's already
+    /// open. Failures are shown in the status line rather than returned since the terminal is
+    /// already in raw mode by the time a file is opened.
+    pub fn open(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        if let Some(idx) = self.buffers.find_path(&path) {
+            self.switch_to(idx);
+            return;
+        }
+        let buffer = match Buffer::open(path) {
+            Ok(buffer) => buffer,
+            Err(e) => {
+                self.message = e.to_string();
+                return;
+            }
+        };
+        // The empty buffer the editor starts with isn't worth keeping around.
+        let replace = self
+            .doc()
+            .is_untouched()
+            .then(|| self.buffers.current_index());
+        let idx = self.buffers.push(buffer);
+        self.buffers.get_mut(idx).options = self.buffer_options.clone();
+        self.switch_to(idx);
+        if let Some(untouched) = replace {
+            self.buffers.remove(untouched);
+        }
+
+        let doc = self.buffers.current_mut();
+        if let Some(dir) = &self.undo_dir
+            && let Err(e) = doc.buffer.read_undo_file(dir)
+        {
+            self.message = e.to_string();
+        }
+        doc.watcher = doc.buffer.path().map(FileWatcher::new);
+        self.claim_swap_file();
+        self.restore_file_marks();
+        self.apply_file_settings();
+    }
+
+    /// Makes the document at `idx` current, bringing up anything it still needs answered.
+    fn switch_to(&mut self, idx: usize) {
+        self.buffers.switch_to(idx);
+        self.message = format!("\"{}\"", self.doc().name());
+        self.show_prompt();
+    }
+
+    fn show_prompt(&mut self) {
+        if let Some(prompt) = self.doc().prompt() {
+            self.message = prompt;
+        }
+    }
+
+    /// Deals with files having been changed by other programs. Unmodified buffers just follow
+    /// the file, otherwise the user gets to choose, once the buffer is current.
+    fn check_files_changed(&mut self) {
+        let current = self.buffers.current_index();
+        for (idx, doc) in self.buffers.iter_mut().enumerate() {
+            if !doc.watcher.as_ref().is_some_and(FileWatcher::changed) {
+                continue;
+            }
+            match doc.buffer.check_disk() {
+                DiskState::Unchanged => {}
+                DiskState::Deleted if idx == current => {
+                    self.message = "File was deleted on disk, write it to keep it".to_owned();
+                }
+                DiskState::Deleted => {}
+                DiskState::Changed if !doc.buffer.is_modified() => {
+                    self.message = match doc.buffer.reload(doc.cursor) {
+                        Ok(()) => format!("\"{}\" changed on disk, reloaded", doc.name()),
+                        Err(e) => e.to_string(),
+                    };
+                    doc.cursor = doc.buffer.clamp(doc.cursor);
+                }
+                DiskState::Changed => doc.reload_prompt = true,
+            }
+        }
+        self.show_prompt();
+    }
+
+    fn reload(&mut self) {
+        let doc = self.doc_mut();
+        let result = doc.buffer.reload(doc.cursor);
+        doc.cursor = doc.buffer.clamp(doc.cursor);
+        self.message = match result {
+            Ok(()) => "File changed on disk, reloaded".to_owned(),
+            Err(e) => e.to_string(),
+        };
+    }
+
+    fn answer_reload_prompt(&mut self, ch: char) {
+        match ch.to_ascii_lowercase() {
+            'r' => {
+                self.doc_mut().reload_prompt = false;
+                self.reload();
+            }
+            'k' => {
+                self.doc_mut().reload_prompt = false;
+                self.message = "Kept your changes, writing will overwrite the file".to_owned();
+            }
+            'd' => {
+                let buffer = &self.doc().buffer;
+                match buffer.read_disk_lines() {
+                    Ok(disk) => {
+                        let ours: Vec<_> = (0..buffer.line_count())
+                            .map(|row| buffer.line(row).unwrap_or_default().into_owned())
+                            .collect();
+                        let mut view = vec!["--- on disk".to_owned(), "+++ buffer".to_owned()];
+                        view.extend(diff::unified(&disk, &ours, 3));
+                        self.pager = Some(view);
+                    }
+                    Err(e) => self.message = e.to_string(),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn claim_swap_file(&mut self) {
+        let doc = self.buffers.current_mut();
+        if let Some(swap) = doc.swap.take() {
+            swap.remove();
+        }
+        let (Some(dir), Some(path)) = (&self.swap_dir, doc.buffer.path()) else {
+            return;
+        };
+        if doc.buffer.is_read_only() {
+            return;
+        }
+        match SwapFile::create(dir, path, &doc.buffer) {
+            Ok(Ok(swap)) => doc.swap = Some(swap),
+            // Nothing to recover and nobody else using it, just a leftover from a crash.
+            Ok(Err(existing)) if !existing.running && existing.text.is_none() => {
+                self.take_over_swap_file(existing);
+            }
+            Ok(Err(existing)) => {
+                doc.swap_prompt = Some(existing);
+                self.show_prompt();
+            }
+            Err(e) => self.message = e.to_string(),
+        }
+    }
+
+    fn take_over_swap_file(&mut self, existing: ExistingSwap) {
+        let doc = self.doc_mut();
+        match SwapFile::take_over(existing, &doc.buffer) {
+            Ok(swap) => doc.swap = Some(swap),
+            Err(e) => self.message = e.to_string(),
+        }
+    }
+
+    fn answer_swap_prompt(&mut self, ch: char) {
+        let doc = self.buffers.current_mut();
+        let Some(existing) = doc.swap_prompt.take() else {
+            return;
+        };
+        match ch.to_ascii_lowercase() {
+            'r' if existing.text.is_some() => {
+                let text = existing.text.as_deref().unwrap_or_default().to_owned();
+                doc.buffer.begin_change(Position::default());
+                doc.buffer
+                    .replace(Position::default(), doc.buffer.end(), &text);
+                doc.buffer.commit_change();
+                doc.cursor = Position::default();
+                self.message = "Recovered unsaved changes, write them to keep them".to_owned();
+                self.take_over_swap_file(existing);
+            }
+            'o' => {
+                doc.buffer.set_read_only(true);
+                self.message = "Opened read-only".to_owned();
+            }
+            'e' => {
+                self.message.clear();
+                self.take_over_swap_file(existing);
+            }
+            'd' if !existing.running => {
+                self.message = "Deleted the swap file".to_owned();
+                self.take_over_swap_file(existing);
+            }
+            'q' => self.wants_exit = true,
+            _ => doc.swap_prompt = Some(existing),
+        }
+    }
+
+    fn update_swap_files(&mut self) {
+        for doc in self.buffers.iter_mut() {
+            if let Some(swap) = &mut doc.swap
+                && let Err(e) = swap.update(&doc.buffer)
+            {
+                self.message = e.to_string();
+            }
+        }
+    }
+
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        self.clear_screen()?;
+        let result = self.repl();
+        self.buffers.close_all();
+        self.terminal.queue_cmd(CursorStyle::Default)?;
+        self.refresh_screen()?;
+        self.terminate().unwrap();
+        result.unwrap();
+        print!("\nExiting...Goodbye. \r\n");
+        Ok(())
+    }
+
+    fn terminate(&self) -> anyhow::Result<()> {
+        Ok(self.terminal.deinitialize()?)
+    }
+
+    fn clear_screen(&mut self) -> anyhow::Result<()> {
+        self.terminal.queue_cmd(Clear(ClearType::All))?;
+        Ok(())
+    }
+
+    fn refresh_screen(&mut self) -> anyhow::Result<()> {
+        self.terminal.flush()?;
+        Ok(())
+    }
+
+    fn repl(&mut self) -> anyhow::Result<()> {
+        let timeout = Duration::from_millis(100);
+        loop {
+            self.render()?;
+            self.refresh_screen()?;
+            std::thread::sleep(timeout);
+            let event = self.terminal.read()?;
+            let _ = self.event_handler(&event);
+            if self.pending.timed_out(self.key_timeout) {
+                self.process_keys(true);
+            }
+            self.check_files_changed();
+            self.update_swap_files();
+            if self.wants_exit {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn render(&mut self) -> anyhow::Result<()> {
+        let size = self.terminal.get_size()?;
+        let text_rows = size.y.saturating_sub(1) as usize;
+        self.text_rows = text_rows;
+        let gutter = self.gutter_width();
+        let text_cols = (size.x as usize).saturating_sub(gutter).max(1);
+        self.scroll_to_cursor(text_rows, text_cols);
+
+        let (matches, current_match) = self.visible_matches();
+        let doc = self.buffers.current();
+        let previews = self.preview_substitute(doc.scroll..doc.scroll + text_rows);
+        let doc = self.buffers.current();
+        let wrap = self.window_options.wrap;
+        let tabstop = doc.options.tabstop;
+        let mut row = doc.scroll;
+        let mut screen_row = 0;
+        let mut cursor_at = None;
+        while screen_row < text_rows {
+            self.terminal.queue_cmd(MoveTo::new(0, screen_row as u32))?;
+            self.terminal.queue_cmd(Clear(ClearType::CurrentLine))?;
+            if let Some(pager) = &self.pager {
+                if let Some(line) = pager.get(screen_row) {
+                    let visible = display::render_line(line, size.x as usize);
+                    self.terminal.write_str_to_queue(&visible)?;
+                }
+                screen_row += 1;
+                continue;
+            }
+            let line = match previews
+                .iter()
+                .find(|(preview_row, ..)| *preview_row == row)
+            {
+                Some((_, text, _)) => Some(Cow::Borrowed(text.as_str())),
+                None => doc.buffer.line(row),
+            };
+            let Some(line) = line else {
+                self.terminal.write_str_to_queue("~")?;
+                screen_row += 1;
+                continue;
+            };
+            let match_span = |m: &Match, style| {
+                let cols = match_cols(&doc.buffer, row, *m)?;
+                Some(Span { cols, style })
+            };
+            let mut spans: Vec<_> = matches
+                .iter()
+                .filter_map(|m| match_span(m, Style::Match))
+                .collect();
+            spans.extend(current_match.and_then(|m| match_span(&m, Style::CurrentMatch)));
+            // Shown instead of the line, what `:s` would make of it.
+            if let Some((_, _, cols)) = previews.iter().find(|(r, ..)| *r == row) {
+                spans = cols
+                    .iter()
+                    .map(|cols| Span {
+                        cols: cols.clone(),
+                        style: Style::Match,
+                    })
+                    .collect();
+            }
+            spans.extend(self.selection_cols(row));
+
+            let parts = if wrap {
+                screen_rows(&line, tabstop, text_cols)
+            } else {
+                1
+            };
+            if row == doc.cursor.row {
+                let col = display::screen_col(&line, doc.cursor.col, tabstop);
+                let (part, col) = if wrap {
+                    let part = (col / text_cols).min(parts - 1);
+                    (part, col - part * text_cols)
+                } else {
+                    (0, col.saturating_sub(doc.left_col))
+                };
+                cursor_at = Some(((gutter + col).min(size.x as usize - 1), screen_row + part));
+            }
+            for part in 0..parts.min(text_rows - screen_row) {
+                if part > 0 {
+                    self.terminal
+                        .queue_cmd(MoveTo::new(0, (screen_row + part) as u32))?;
+                    self.terminal.queue_cmd(Clear(ClearType::CurrentLine))?;
+                }
+                if gutter > 0 {
+                    let number = (part == 0).then_some(row + 1);
+                    let number = display::render_number(number, gutter, self.colors);
+                    self.terminal.write_str_to_queue(&number)?;
+                }
+                let start = if wrap { part * text_cols } else { doc.left_col };
+                let view = View {
+                    cols: start..start + text_cols,
+                    tabstop,
+                    colors: self.colors,
+                };
+                let visible = display::render_spans(&line, &view, &spans);
+                self.terminal.write_str_to_queue(&visible)?;
+            }
+            screen_row += parts;
+            row += 1;
+        }
+
+        let status = match self.mode {
+            Mode::CommandLine => {
+                format!(
+                    "{}{}",
+                    self.command_line.prompt.char(),
+                    self.command_line.text
+                )
+            }
+            _ => self.status_line(),
+        };
+        self.terminal.queue_cmd(MoveTo::new(0, text_rows as u32))?;
+        self.terminal.queue_cmd(Clear(ClearType::CurrentLine))?;
+        let status: String = status.chars().take(size.x as usize).collect();
+        self.terminal.write_str_to_queue(&status)?;
+
+        self.terminal.queue_cmd(match self.mode {
+            Mode::Insert | Mode::CommandLine => CursorStyle::Bar,
+            Mode::Replace | Mode::OperatorPending(_) => CursorStyle::Underline,
+            Mode::Normal | Mode::Visual(_) => CursorStyle::Block,
+        })?;
+        if self.mode == Mode::CommandLine {
+            let line = &self.command_line;
+            let col = 1 + display::screen_col(&line.text, line.cursor, display::TAB_WIDTH);
+            self.terminal
+                .queue_cmd(MoveTo::new(col as u32, text_rows as u32))?;
+            return Ok(());
+        }
+        let (col, row) = cursor_at.unwrap_or_default();
+        self.terminal
+            .queue_cmd(MoveTo::new(col as u32, row as u32))?;
+        Ok(())
+    }
+
+    /// How many cells line numbers take with `number`, counting the space after them.
+    fn gutter_width(&self) -> usize {
+        if !self.window_options.number {
+            return 0;
+        }
+        let count = self.doc().buffer.line_count();
+        count.to_string().len().max(3) + 1
+    }
+
+    /// The search matches starting on screen, and the one to stand out: while a search is
+    /// typed, the match it finds, otherwise the one at the cursor. Matches of the last search
+    /// show with `hlsearch`, until `:nohlsearch`.
+    fn visible_matches(&self) -> (Vec<Match>, Option<Match>) {
+        let doc = self.doc();
+        let options = self.search_options;
+        let typing = self.mode == Mode::CommandLine
+            && matches!(self.command_line.prompt, Prompt::Search { .. })
+            && options.incremental;
+        let pattern = match &self.last_search {
+            _ if typing => Some(self.command_line.text.as_str()).filter(|text| !text.is_empty()),
+            Some(search) if options.highlight && self.highlight_search => {
+                Some(search.pattern.as_str())
+            }
+            _ => None,
+        };
+        let Some(pattern) = pattern.and_then(|pattern| self.compile_search(pattern).ok()) else {
+            return (Vec::new(), self.substitute_asking());
+        };
+        let matches = if options.highlight {
+            pattern.matches(&doc.buffer, doc.scroll..doc.scroll + self.text_rows)
+        } else {
+            Vec::new()
+        };
+        let current = if typing {
+            self.command_line.preview
+        } else {
+            self.substitute_asking()
+                .or_else(|| matches.iter().find(|m| m.0 == doc.cursor).copied())
+        };
+        (matches, current)
+    }
+
+    /// The part of `row` that's selected in Visual mode.
+    fn selection_cols(&self, row: usize) -> Option<Span> {
+        let Mode::Visual(kind) = self.mode else {
+            return None;
+        };
+        let cursor = self.doc().cursor;
+        let (start, end) = (
+            cursor.min(self.visual_anchor),
+            cursor.max(self.visual_anchor),
+        );
+        if row < start.row || row > end.row {
+            return None;
+        }
+        let len = self.doc().buffer.line_len(row);
+        let cols = match kind {
+            VisualKind::Char => {
+                let from = if row == start.row { start.col } else { 0 };
+                let to = if row == end.row { end.col + 1 } else { len + 1 };
+                from..to
+            }
+            VisualKind::Line => 0..len.max(1),
+            VisualKind::Block => {
+                let (left, right) = self.selection(kind).block_cols();
+                let doc = self.doc();
+                let line = doc.buffer.line(row).unwrap_or_default();
+                let part = block::part(&line, left, right, doc.options.tabstop);
+                if part.is_empty() {
+                    return None;
+                }
+                part.start..part.end
+            }
+        };
+        Some(Span {
+            cols,
+            style: Style::Selection,
+        })
+    }
+
+    fn status_line(&self) -> String {
+        let doc = self.doc();
+        let buffer = &doc.buffer;
+        let modified = if buffer.is_modified() { " [+]" } else { "" };
+        let format = if buffer.has_mixed_line_endings() {
+            format!("mixed->{}", buffer.file_format())
+        } else {
+            buffer.file_format().to_string()
+        };
+        let bom = if buffer.has_bom() { ",bom" } else { "" };
+        let mut large = match (buffer.indexing_progress(), buffer.index_error()) {
+            (_, Some(e)) => format!(" [index failed: {e}]"),
+            (Some(percent), None) => format!(" [RO, indexing {percent}%]"),
+            (None, None) if buffer.is_read_only() => " [RO]".to_owned(),
+            (None, None) => String::new(),
+        };
+        match buffer.long_lines() {
+            0 => {}
+            1 => large.push_str(" [1 line cut short]"),
+            count => large.push_str(&format!(" [{count} lines cut short]")),
+        }
+        let others = match self.buffers.len() {
+            1 => String::new(),
+            count => format!(" ({} of {count})", self.buffers.current_index() + 1),
+        };
+        let pending = keys::show(&self.pending.shown());
+        let recording = match self.macros.recording() {
+            Some(name) => format!(" recording @{name}"),
+            None => String::new(),
+        };
+        format!(
+            "{}{recording} {}{modified}{others} [{}{bom}] [{format}]{large}  {},{}  {pending}  {}",
+            self.mode.label(),
+            doc.name(),
+            buffer.encoding(),
+            doc.cursor.row + 1,
+            doc.cursor.col + 1,
+            self.message
+        )
+    }
+
+    /// Scrolls so the cursor shows with `scrolloff` lines around it, and sideways with
+    /// `nowrap` so its column does.
+    fn scroll_to_cursor(&mut self, text_rows: usize, text_cols: usize) {
+        if text_rows == 0 {
+            return;
+        }
+        let WindowOptions {
+            wrap, scrolloff, ..
+        } = self.window_options;
+        let scrolloff = scrolloff.min((text_rows - 1) / 2);
+        let doc = self.doc_mut();
+        let tabstop = doc.options.tabstop;
+        let top = doc.cursor.row.saturating_sub(scrolloff);
+        let last = doc
+            .buffer
+            .line_count()
+            .saturating_sub(1)
+            .max(doc.cursor.row);
+        let bottom = (doc.cursor.row + scrolloff).min(last);
+        if top < doc.scroll {
+            doc.scroll = top;
+        } else if bottom >= doc.scroll + text_rows {
+            doc.scroll = bottom + 1 - text_rows;
+        }
+        if wrap {
+            let heights: Vec<_> = (doc.scroll..=bottom)
+                .map(|row| {
+                    let line = doc.buffer.line(row).unwrap_or_default();
+                    screen_rows(&line, tabstop, text_cols)
+                })
+                .collect();
+            let mut rows: usize = heights.iter().sum();
+            for height in heights {
+                if doc.scroll == doc.cursor.row || rows <= text_rows {
+                    break;
+                }
+                rows -= height;
+                doc.scroll += 1;
+            }
+            doc.left_col = 0;
+            return;
+        }
+        let line = doc.buffer.line(doc.cursor.row).unwrap_or_default();
+        let col = display::screen_col(&line, doc.cursor.col, tabstop);
+        if col < doc.left_col {
+            doc.left_col = col;
+        } else if col >= doc.left_col + text_cols {
+            doc.left_col = col + 1 - text_cols;
+        }
+    }
+
+    /// Writes the current buffer, to `path` instead of its own file if given.
+    fn save(&mut self, path: Option<&str>) {
+        self.fix_before_write();
+        let doc = self.buffers.current_mut();
+        let result = match path {
+            Some(path) => doc.buffer.save_as(path, &self.write_options),
+            None => doc.buffer.save(&self.write_options),
+        };
+        self.message = match result {
+            Ok(()) => format!("\"{}\" written", doc.name()),
+            Err(e) => e.to_string(),
+        };
+        if let Some(dir) = &self.undo_dir
+            && !doc.buffer.is_modified()
+            && let Err(e) = doc.buffer.write_undo_file(dir)
+        {
+            self.message = e.to_string();
+        }
+        if path.is_some() {
+            // Written under a new name: swap file and watcher follow the buffer there.
+            doc.watcher = doc.buffer.path().map(FileWatcher::new);
+            self.claim_swap_file();
+            return;
+        }
+        // Nothing left to recover once the file on disk is up to date.
+        if let Some(swap) = &mut doc.swap
+            && let Err(e) = swap.write(&doc.buffer)
+        {
+            self.message = e.to_string();
+        }
+    }
+
+    /// Does what the buffer's options say to on every write: takes off trailing blanks with
+    /// `.editorconfig`'s `trim_trailing_whitespace`, and ends the last line with
+    /// `fixendofline`.
+    fn fix_before_write(&mut self) {
+        let doc = self.buffers.current_mut();
+        if doc.buffer.is_read_only() {
+            return;
+        }
+        if doc.options.fixendofline {
+            doc.buffer.set_eol(true);
+        }
+        if !doc.options.trim_trailing_whitespace {
+            return;
+        }
+        for row in 0..doc.buffer.line_count() {
+            let (len, trimmed) = {
+                let line = doc.buffer.line(row).unwrap_or_default();
+                (line.chars().count(), line.trim_end().chars().count())
+            };
+            if trimmed < len {
+                doc.buffer.begin_change(doc.cursor);
+                doc.buffer
+                    .delete(Position::new(row, trimmed), Position::new(row, len));
+            }
+        }
+        self.clamp_cursor();
+    }
+
+    /// Ends the current undo step, unless an Ex command is running: what it changes is undone
+    /// in one go, even when it runs Normal mode commands. The same goes for a `:s///c` still
+    /// asking.
+    fn commit_change(&mut self) {
+        if self.ex_depth == 0 && self.substitute_prompt.is_none() {
+            self.doc_mut().buffer.commit_change();
+        }
+    }
+
+    fn undo(&mut self) {
+        let doc = self.doc_mut();
+        match doc.buffer.undo() {
+            Some(cursor) => doc.cursor = cursor,
+            None => self.message = "Already at oldest change".to_owned(),
+        }
+    }
+
+    fn redo(&mut self) {
+        let doc = self.doc_mut();
+        match doc.buffer.redo() {
+            Some(cursor) => doc.cursor = cursor,
+            None => self.message = "Already at newest change".to_owned(),
+        }
+    }
+
+    /// `:confirm quit`, which Ctrl-Q is mapped to: quits straight away if nothing would be
+    /// lost, otherwise only when asked twice with nothing changed in between.
+    fn request_quit(&mut self) {
+        let unsaved: Vec<(usize, u64)> = self
+            .buffers
+            .iter()
+            .filter(|doc| doc.buffer.is_modified())
+            .map(|doc| (doc.id, doc.buffer.change_tick()))
+            .collect();
+        if unsaved.is_empty() || self.quit_pending.as_ref() == Some(&unsaved) {
+            self.wants_exit = true;
+            return;
+        }
+        self.message = match unsaved.len() {
+            1 => "1 buffer has unsaved changes, quit again to lose them".to_owned(),
+            n => format!("{n} buffers have unsaved changes, quit again to lose them"),
+        };
+        self.quit_pending = Some(unsaved);
+    }
+
+    fn event_handler(&mut self, events: &[Event]) -> anyhow::Result<()> {
+        for event in events {
+            match event {
+                Event::Key {
+                    ch,
+                    modifiers,
+                    state,
+                } => {
+                    if matches!(state, KeyPressState::KeyDown) {
+                        self.handle_key(*ch, *modifiers);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, ch: char, modifiers: ModifierKeyCode) {
+        if self.pager.take().is_some() {
+            return;
+        }
+        if self.doc().swap_prompt.is_some() {
+            self.answer_swap_prompt(ch);
+            return;
+        }
+        if self.doc().reload_prompt {
+            self.answer_reload_prompt(ch);
+            return;
+        }
+        if self.substitute_prompt.is_some() {
+            self.answer_substitute_prompt(keys::from_event(ch, modifiers));
+            return;
+        }
+        let key = keys::from_event(ch, modifiers);
+        self.record_key(key);
+        self.pending.push(key);
+        self.process_keys(false);
+    }
+
+    /// Turns pending keys into commands for the current mode and runs them, for as long as
+    /// they make complete commands, expanding mappings first. With `timed_out`, an ambiguous
+    /// sequence is taken as the shorter command or mapping instead of waiting for more keys.
+    fn process_keys(&mut self, timed_out: bool) {
+        let mut depth = 0;
+        while let Some(&key) = self.pending.keys().first() {
+            match self.expand_mapping(timed_out) {
+                Expansion::Expanded if depth == mapping::MAX_DEPTH => {
+                    self.pending.clear();
+                    self.message = MappingError::Recursive.to_string();
+                    return;
+                }
+                Expansion::Expanded => {
+                    depth += 1;
+                    continue;
+                }
+                Expansion::Waiting => return,
+                Expansion::None => depth = 0,
+            }
+            // The char itself can be typed or pasted, and then it's just a key like any other.
+            if key == keys::CMD && matches!(self.pending.origin(0), Origin::Mapped { .. }) {
+                self.run_cmd_key();
+                continue;
+            }
+            match self.mode {
+                Mode::CommandLine => {
+                    let origin = self.pending.origin(0);
+                    self.pending.consume(1);
+                    self.record_step(Step::CommandLine(key));
+                    // What a mapping ran shows, as if typed, unless it's `<silent>`.
+                    let line = &self.command_line;
+                    let echo = (key == keys::ENTER
+                        && matches!(origin, Origin::Mapped { silent: false, .. }))
+                    .then(|| format!("{}{}", line.prompt.char(), line.text));
+                    self.command_line_key(key);
+                    if let Some(echo) = echo
+                        && self.message.is_empty()
+                    {
+                        self.message = echo;
+                    }
+                }
+                Mode::Insert | Mode::Replace => {
+                    let lookup = insert::parse(self.pending.keys());
+                    let Some(cmd) = self.resolve(lookup, timed_out) else {
+                        return;
+                    };
+                    self.record_step(Step::Insert(cmd));
+                    self.run_insert(cmd);
+                }
+                Mode::Normal | Mode::Visual(_) | Mode::OperatorPending(_) => {
+                    let lookup = normal::parse(
+                        self.pending.keys(),
+                        self.mode,
+                        self.macros.recording().is_some(),
+                    );
+                    let Some(cmd) = self.resolve(lookup, timed_out) else {
+                        return;
+                    };
+                    self.record_step(Step::Normal(cmd));
+                    self.set_visual_marks();
+                    self.run_normal(cmd);
+                }
+            }
+            self.finish_step();
+        }
+    }
+
+    /// The command pending keys make, taking its keys off the queue, or `None` to stop
+    /// processing: when more keys are needed, or when they made no sense and were dropped.
+    fn resolve<T>(&mut self, lookup: Lookup<T>, timed_out: bool) -> Option<T> {
+        match lookup {
+            Lookup::Complete(cmd, used) => {
+                self.pending.consume(used);
+                Some(cmd)
+            }
+            Lookup::Ambiguous(cmd, used) if timed_out => {
+                self.pending.consume(used);
+                Some(cmd)
+            }
+            Lookup::Ambiguous(..) | Lookup::Incomplete => None,
+            Lookup::Invalid => {
+                self.pending.clear();
+                self.register = None;
+                if let Mode::OperatorPending(_) = self.mode {
+                    self.mode = Mode::Normal;
+                    // Drops the operator from the change being typed.
+                    self.finish_step();
+                }
+                None
+            }
+        }
+    }
+}
+
+/// How many screen rows `line` takes with `wrap`, `text_cols` cells to a row.
+fn screen_rows(line: &str, tabstop: usize, text_cols: usize) -> usize {
+    display::width(line, tabstop).div_ceil(text_cols).max(1)
+}
+
+/// The part of `row` a match covers, if any. A match going on past the end of the
+/// line covers the cell after it.
+fn match_cols(buffer: &Buffer, row: usize, (start, end): Match) -> Option<Range<usize>> {
+    if row < start.row || row > end.row || start == end {
+        return None;
+    }
+    let from = if row == start.row { start.col } else { 0 };
+    let to = if row == end.row {
+        end.col
+    } else {
+        buffer.line_len(row) + 1
+    };
+    (from < to).then_some(from..to)
+}
@@ -0,0 +1,43 @@
+//! The editor's modes.
+
+use super::operator::PendingOperator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisualKind {
+    Char,
+    Line,
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Normal,
+    Insert,
+    Replace,
+    Visual(VisualKind),
+    /// An operator was typed and waits for the motion saying what to apply it to.
+    OperatorPending(PendingOperator),
+    CommandLine,
+}
+
+impl Mode {
+    /// Shown at the start of the status line.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Normal => "NORMAL",
+            Self::Insert => "INSERT",
+            Self::Replace => "REPLACE",
+            Self::Visual(VisualKind::Char) => "VISUAL",
+            Self::Visual(VisualKind::Line) => "V-LINE",
+            Self::Visual(VisualKind::Block) => "V-BLOCK",
+            Self::OperatorPending(_) => "O-PENDING",
+            Self::CommandLine => "COMMAND",
+        }
+    }
+
+    /// Whether the cursor may sit just past the end of a line, where text is appended.
+    pub fn allows_past_end(self) -> bool {
+        matches!(self, Self::Insert | Self::Replace)
+    }
+}
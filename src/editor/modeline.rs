@@ -0,0 +1,213 @@
+//! Modelines: settings a file carries in its first or last lines, vim's
+//! `vim: set ts=4 sw=4 et:` or Emacs's `-*- mode: rust; tab-width: 4 -*-`. They're applied
+//! as `:setlocal` would, but only for the few options that are about how the text is laid
+//! out, so that a file can't do anything else to the editor by being opened.
+
+use super::Editor;
+use super::options::{self, OptionError, Target};
+use crate::buffer::Buffer;
+
+/// How many lines at the start and at the end of a file can have a modeline, unless
+/// `modelines` says otherwise.
+pub const DEFAULT_LINES: usize = 5;
+
+/// The options a modeline can set. `filetype` only to a plain name.
+const ALLOWED: &[&str] = &[
+    "commentstring",
+    "expandtab",
+    "filetype",
+    "shiftwidth",
+    "tabstop",
+    "textwidth",
+];
+
+/// Emacs major modes by the names they go by in vim.
+const EMACS_MODES: &[(&str, &str)] = &[
+    ("c++", "cpp"),
+    ("js", "javascript"),
+    ("makefile", "make"),
+    ("shell-script", "sh"),
+];
+
+/// The settings of the modelines in the first and last `lines` lines of `buffer`, as `:set`
+/// takes them: `ts=4`, `noet`. Those of later lines come after those of earlier ones, so
+/// they win.
+pub fn settings(buffer: &Buffer, lines: usize) -> Vec<String> {
+    let count = buffer.line_count();
+    let head = 0..lines.min(count);
+    let tail = count.saturating_sub(lines).max(head.end)..count;
+    head.chain(tail)
+        .filter_map(|row| {
+            let line = buffer.line(row)?;
+            vim_settings(&line).or_else(|| emacs_settings(&line))
+        })
+        .flatten()
+        .collect()
+}
+
+/// The filetype the modelines in `settings` give, the last one if there are several.
+pub fn filetype(settings: &[String]) -> Option<&str> {
+    settings
+        .iter()
+        .rev()
+        .filter(|setting| is_allowed(setting))
+        .find_map(|setting| {
+            let (name, value) = setting.split_once(['=', ':'])?;
+            (options::full_name(name) == Some("filetype")).then_some(value)
+        })
+}
+
+/// Whether a modeline may apply `setting`.
+fn is_allowed(setting: &str) -> bool {
+    let (name, value) = match setting.split_once(['=', ':']) {
+        Some((name, value)) => (name, Some(value)),
+        None => (setting, None),
+    };
+    let name = name.strip_suffix(['!', '&']).unwrap_or(name);
+    let full = options::full_name(name)
+        .or_else(|| name.strip_prefix("no").and_then(options::full_name))
+        .or_else(|| name.strip_prefix("inv").and_then(options::full_name));
+    match full {
+        // It names files to look for in other editors, so nothing that could be a path.
+        Some("filetype") => value.is_some_and(|value| {
+            value
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-' | '.'))
+        }),
+        Some(full) => ALLOWED.contains(&full),
+        None => false,
+    }
+}
+
+impl Editor {
+    /// Applies the current buffer's modelines, unless `nomodeline`. `filetype` is left out,
+    /// since it went into working out the filetype already. Only the first thing wrong is
+    /// shown, but the rest still applies.
+    pub(super) fn apply_modelines(&mut self) {
+        if !self.modeline {
+            return;
+        }
+        let mut error = None;
+        for setting in settings(&self.doc().buffer, self.modelines) {
+            let name = setting.split(['=', ':']).next().unwrap_or_default();
+            if options::full_name(name) == Some("filetype") && is_allowed(&setting) {
+                continue;
+            }
+            let result = if is_allowed(&setting) {
+                self.set_item(&setting, Target::Local).map(|_| ())
+            } else {
+                Err(OptionError::NotAllowed(setting))
+            };
+            if let Err(e) = result {
+                error.get_or_insert(e);
+            }
+        }
+        if let Some(e) = error {
+            self.message = format!("Modeline: {e}");
+        }
+    }
+}
+
+/// The settings of a vim modeline: `vim:` (or `vi:`, `Vim:`, `ex:`) at the start of the line
+/// or after a blank, then either settings up to the end of the line, split by blanks or `:`,
+/// or `set` and settings up to the next `:`, which `\` makes part of a value.
+fn vim_settings(line: &str) -> Option<Vec<String>> {
+    let rest = line[vim_marker(line)?..].trim_start();
+    let Some(set) = rest
+        .strip_prefix("set ")
+        .or_else(|| rest.strip_prefix("se "))
+    else {
+        let settings = rest.split(|ch: char| ch.is_whitespace() || ch == ':');
+        return Some(
+            settings
+                .filter(|s| !s.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        );
+    };
+    let mut settings = Vec::new();
+    let mut setting = String::new();
+    let mut chars = set.chars();
+    loop {
+        match chars.next()? {
+            '\\' if chars
+                .clone()
+                .next()
+                .is_some_and(|ch| ch == ':' || ch.is_whitespace()) =>
+            {
+                setting.push(chars.next()?);
+            }
+            ':' => break,
+            ch if ch.is_whitespace() => settings.push(std::mem::take(&mut setting)),
+            ch => setting.push(ch),
+        }
+    }
+    settings.push(setting);
+    settings.retain(|setting| !setting.is_empty());
+    Some(settings)
+}
+
+/// Where the settings of a vim modeline in `line` start, after its marker. `vim` can have a
+/// version after it, as in `vim600:`, which is ignored.
+fn vim_marker(line: &str) -> Option<usize> {
+    line.char_indices()
+        .filter(|&(i, _)| i == 0 || line[..i].ends_with(char::is_whitespace))
+        .find_map(|(i, _)| {
+            let rest = &line[i..];
+            let after = ["vi:", "ex:"]
+                .iter()
+                .find_map(|marker| rest.strip_prefix(marker))
+                .or_else(|| {
+                    let version = rest
+                        .strip_prefix("vim")
+                        .or_else(|| rest.strip_prefix("Vim"))?
+                        .trim_start_matches(['<', '=', '>'])
+                        .trim_start_matches(|ch: char| ch.is_ascii_digit());
+                    version.strip_prefix(':')
+                })?;
+            Some(line.len() - after.len())
+        })
+}
+
+/// The settings of an Emacs modeline, `-*- mode -*-` or `-*- mode: name; var: value -*-`.
+/// The mode is the filetype, and a few variables stand for options.
+fn emacs_settings(line: &str) -> Option<Vec<String>> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (vars, _) = rest.split_once("-*-")?;
+    if !vars.contains(':') {
+        return Some(emacs_mode(vars).into_iter().collect());
+    }
+    let settings = vars.split(';').filter_map(|var| {
+        let (name, value) = var.split_once(':')?;
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "mode" => emacs_mode(value),
+            "tab-width" => Some(format!("tabstop={value}")),
+            "fill-column" => Some(format!("textwidth={value}")),
+            "indent-tabs-mode" if value == "nil" => Some("expandtab".to_owned()),
+            "indent-tabs-mode" => Some("noexpandtab".to_owned()),
+            name if ["-basic-offset", "-indent-offset", "-indent-level"]
+                .iter()
+                .any(|suffix| name.ends_with(suffix)) =>
+            {
+                Some(format!("shiftwidth={value}"))
+            }
+            _ => None,
+        }
+    });
+    Some(settings.collect())
+}
+
+/// An Emacs major mode as the `filetype` setting it stands for.
+fn emacs_mode(mode: &str) -> Option<String> {
+    let mode = mode.trim().to_ascii_lowercase();
+    let mode = mode.strip_suffix("-mode").unwrap_or(&mode);
+    if mode.is_empty() {
+        return None;
+    }
+    let filetype = EMACS_MODES
+        .iter()
+        .find(|&&(name, _)| name == mode)
+        .map_or(mode, |&(_, filetype)| filetype);
+    Some(format!("filetype={filetype}"))
+}
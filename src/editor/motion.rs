@@ -0,0 +1,422 @@
+//! Cursor motions. On their own they move the cursor; after an operator they say what text the
+//! operator applies to.
+
+use super::operator::Operator;
+use super::search::Pattern;
+use super::word::{self, Walker};
+use crate::buffer::{Buffer, Position};
+use crate::display;
+
+/// `f`, `t`, `F` and `T` with the char they look for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FindChar {
+    pub ch: char,
+    pub forward: bool,
+    /// `t` and `T`: stop just before the char.
+    pub till: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// `0`
+    LineStart,
+    /// `^`
+    FirstNonBlank,
+    /// `$`
+    LineEnd,
+    /// `|`
+    Column,
+    /// `+` and Enter
+    NextLine,
+    /// `-`
+    PreviousLine,
+    /// `_`
+    CurrentLine,
+    /// `gg`
+    FirstLine,
+    /// `G`
+    LastLine,
+    /// `w` and `W`
+    WordForward {
+        big: bool,
+    },
+    /// `b` and `B`
+    WordBackward {
+        big: bool,
+    },
+    /// `e` and `E`
+    WordEnd {
+        big: bool,
+    },
+    /// `ge` and `gE`
+    WordEndBackward {
+        big: bool,
+    },
+    Find(FindChar),
+    /// `;`, and `,` which goes the other way.
+    RepeatFind {
+        reverse: bool,
+    },
+    /// `%`
+    MatchPair,
+    /// `}`
+    ParagraphForward,
+    /// `{`
+    ParagraphBackward,
+    /// `H`
+    ScreenTop,
+    /// `M`
+    ScreenMiddle,
+    /// `L`
+    ScreenBottom,
+    /// `n`, and `N` which goes the other way. `/` and `?` set the pattern and then use it.
+    SearchNext {
+        reverse: bool,
+    },
+    /// `'{mark}`, to the mark's `line`, and `` `{mark} `` to the mark itself.
+    Mark {
+        name: char,
+        line: bool,
+    },
+}
+
+/// How much of the text between the cursor and a motion's target an operator takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionKind {
+    /// Up to but not including the target.
+    Exclusive,
+    /// Up to and including the target.
+    Inclusive,
+    /// Every line from the cursor's to the target's.
+    Linewise,
+}
+
+/// Where a motion goes, and how an operator takes the text up to there. Some motions are
+/// inclusive or linewise only in some cases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub pos: Position,
+    pub kind: MotionKind,
+}
+
+/// What a motion needs to know besides the text.
+pub struct Context<'a> {
+    pub buffer: &'a Buffer,
+    pub cursor: Position,
+    /// Column vertical motions try to keep to, `usize::MAX` after `$`.
+    pub want_col: usize,
+    /// How wide tabs are, which that column depends on.
+    pub tabstop: usize,
+    /// The operator the motion follows, if any. It may then go just past the end of a line,
+    /// and `cw` works like `ce`.
+    pub operator: Option<Operator>,
+    /// The count typed, `None` when there wasn't one, which for some motions differs from 1.
+    pub count: Option<usize>,
+    /// The first row on screen and how many rows there are, for `H`, `M` and `L`.
+    pub view: (usize, usize),
+    /// The last `f`, `t`, `F` or `T`, for `;` and `,`.
+    pub last_find: Option<FindChar>,
+    /// The last pattern searched for, and whether it was searched for forward.
+    pub last_search: Option<(&'a Pattern, bool)>,
+}
+
+impl Motion {
+    /// Whether the motion keeps the column the cursor had, instead of setting a new one.
+    pub fn is_vertical(self) -> bool {
+        matches!(self, Self::Up | Self::Down)
+    }
+
+    /// Whether the motion is a jump, one that leaves the previous position in the jump list
+    /// and in the `''` mark.
+    pub fn is_jump(self) -> bool {
+        matches!(
+            self,
+            Self::FirstLine
+                | Self::LastLine
+                | Self::MatchPair
+                | Self::ParagraphForward
+                | Self::ParagraphBackward
+                | Self::ScreenTop
+                | Self::ScreenMiddle
+                | Self::ScreenBottom
+                | Self::SearchNext { .. }
+                | Self::Mark { .. }
+        )
+    }
+
+    /// Where the motion takes the cursor, or `None` if it can't move, like `h` in the first
+    /// column.
+    pub fn target(self, cx: &Context) -> Option<Target> {
+        use MotionKind::*;
+
+        let Position { row, col } = cx.cursor;
+        let buffer = cx.buffer;
+        let len = buffer.line_len(row);
+        let last_row = buffer.line_count().saturating_sub(1);
+        let count = cx.count.unwrap_or(1);
+        let line_at = |row: usize| Position::new(row, first_non_blank(buffer, row));
+        let at_want_col = |row: usize| {
+            let line = buffer.line(row).unwrap_or_default();
+            Position::new(row, display::char_at(&line, cx.want_col, cx.tabstop))
+        };
+        let (pos, kind) = match self {
+            Self::Left if col > 0 => (Position::new(row, col.saturating_sub(count)), Exclusive),
+            Self::Right => {
+                let end = if cx.operator.is_some() {
+                    len
+                } else {
+                    len.saturating_sub(1)
+                };
+                if col >= end {
+                    return None;
+                }
+                (Position::new(row, (col + count).min(end)), Exclusive)
+            }
+            Self::Up if row > 0 => (at_want_col(row.saturating_sub(count)), Linewise),
+            Self::Down if row < last_row => (at_want_col((row + count).min(last_row)), Linewise),
+            Self::Left | Self::Up | Self::Down => return None,
+            Self::LineStart => (Position::new(row, 0), Exclusive),
+            Self::FirstNonBlank => (line_at(row), Exclusive),
+            Self::LineEnd => {
+                let row = row + count - 1;
+                if row > last_row {
+                    return None;
+                }
+                (
+                    Position::new(row, buffer.line_len(row).saturating_sub(1)),
+                    Inclusive,
+                )
+            }
+            Self::Column => (
+                Position::new(row, (count - 1).min(len.saturating_sub(1))),
+                Exclusive,
+            ),
+            Self::NextLine if row + count <= last_row => (line_at(row + count), Linewise),
+            Self::PreviousLine if row >= count => (line_at(row - count), Linewise),
+            Self::CurrentLine if row + count - 1 <= last_row => {
+                (line_at(row + count - 1), Linewise)
+            }
+            Self::NextLine | Self::PreviousLine | Self::CurrentLine => return None,
+            Self::FirstLine => (line_at((count - 1).min(last_row)), Linewise),
+            Self::LastLine => {
+                let row = cx.count.map_or(last_row, |count| (count - 1).min(last_row));
+                (line_at(row), Linewise)
+            }
+            Self::WordForward { big } => {
+                let blank = Walker::new(buffer, cx.cursor).class(false) == 0;
+                // `cw` on a word changes just the word, not the blanks after it.
+                let (pos, moved, kind) = if cx.operator == Some(Operator::Change) && !blank {
+                    let (pos, moved) = word::end_word(buffer, cx.cursor, count, big, true, false);
+                    (pos, moved, Inclusive)
+                } else {
+                    let eol = cx.operator.is_some();
+                    let (pos, moved) = word::fwd_word(buffer, cx.cursor, count, big, eol);
+                    (pos, moved, Exclusive)
+                };
+                if !moved && cx.operator.is_none() {
+                    return None;
+                }
+                (pos, kind)
+            }
+            Self::WordBackward { big } => {
+                match word::bck_word(buffer, cx.cursor, count, big, false) {
+                    (pos, true) => (pos, Exclusive),
+                    (_, false) => return None,
+                }
+            }
+            Self::WordEnd { big } => {
+                match word::end_word(buffer, cx.cursor, count, big, false, false) {
+                    (_, false) if cx.operator.is_none() => return None,
+                    (pos, _) => (pos, Inclusive),
+                }
+            }
+            Self::WordEndBackward { big } => {
+                match word::bckend_word(buffer, cx.cursor, count, big, false) {
+                    (pos, true) => (pos, Inclusive),
+                    (_, false) => return None,
+                }
+            }
+            Self::Find(find) => (
+                find_char(buffer, cx.cursor, find, count, false)?,
+                find.kind(),
+            ),
+            Self::RepeatFind { reverse } => {
+                let mut find = cx.last_find?;
+                find.forward ^= reverse;
+                // Right before the char, `;` after `t` would find the same one again.
+                (
+                    find_char(buffer, cx.cursor, find, count, find.till)?,
+                    find.kind(),
+                )
+            }
+            Self::MatchPair => match cx.count {
+                Some(percent) if percent <= 100 => {
+                    let rows = buffer.line_count();
+                    (
+                        line_at(((percent * rows).div_ceil(100)).saturating_sub(1)),
+                        Linewise,
+                    )
+                }
+                Some(_) => return None,
+                None => (match_pair(buffer, cx.cursor)?, Inclusive),
+            },
+            Self::ParagraphForward => return paragraph(buffer, row, count, true),
+            Self::ParagraphBackward => return paragraph(buffer, row, count, false),
+            Self::ScreenTop | Self::ScreenMiddle | Self::ScreenBottom => {
+                let (top, rows) = cx.view;
+                let bottom = (top + rows.max(1)).min(buffer.line_count()) - 1;
+                let row = match self {
+                    Self::ScreenTop => (top + count - 1).min(bottom),
+                    Self::ScreenBottom => bottom.saturating_sub(count - 1).max(top),
+                    _ => top + (bottom - top) / 2,
+                };
+                (line_at(row), Linewise)
+            }
+            Self::SearchNext { reverse } => {
+                let (pattern, forward) = cx.last_search?;
+                let forward = forward ^ reverse;
+                let mut pos = cx.cursor;
+                for _ in 0..count {
+                    (pos, _) = pattern.find(buffer, pos, forward)?;
+                }
+                (pos, Exclusive)
+            }
+            Self::Mark { name, line } => {
+                let pos = buffer.clamp(buffer.marks().get(name)?);
+                if line {
+                    (line_at(pos.row), Linewise)
+                } else {
+                    (pos, Exclusive)
+                }
+            }
+        };
+        Some(Target { pos, kind })
+    }
+}
+
+impl FindChar {
+    fn kind(self) -> MotionKind {
+        if self.forward {
+            MotionKind::Inclusive
+        } else {
+            MotionKind::Exclusive
+        }
+    }
+}
+
+/// The `count`th `find.ch` on the cursor's line, in the direction `find` goes. With `skip`, an
+/// occurrence right next to the cursor doesn't count.
+fn find_char(
+    buffer: &Buffer,
+    cursor: Position,
+    find: FindChar,
+    count: usize,
+    skip: bool,
+) -> Option<Position> {
+    let line: Vec<char> = buffer.line(cursor.row)?.chars().collect();
+    let skip = usize::from(skip);
+    let col = if find.forward {
+        let from = cursor.col + 1 + skip;
+        let found = (from..line.len())
+            .filter(|&col| line[col] == find.ch)
+            .nth(count - 1)?;
+        if find.till { found - 1 } else { found }
+    } else {
+        let found = (0..cursor.col.checked_sub(skip)?)
+            .rev()
+            .filter(|&col| line[col] == find.ch)
+            .nth(count - 1)?;
+        if find.till { found + 1 } else { found }
+    };
+    Some(Position::new(cursor.row, col))
+}
+
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// `%`: the bracket matching the one under the cursor, or the first one after it on the line.
+fn match_pair(buffer: &Buffer, cursor: Position) -> Option<Position> {
+    let line: Vec<char> = buffer.line(cursor.row)?.chars().collect();
+    let (col, ch) = line.iter().enumerate().skip(cursor.col).find(|(_, ch)| {
+        PAIRS
+            .iter()
+            .any(|&(open, close)| **ch == open || **ch == close)
+    })?;
+    let (open, close) = *PAIRS
+        .iter()
+        .find(|&&(open, close)| *ch == open || *ch == close)?;
+    let forward = *ch == open;
+    let mut w = Walker::new(buffer, Position::new(cursor.row, col));
+    let mut depth = 0usize;
+    loop {
+        match w.ch() {
+            Some(c) if c == open || c == close => {
+                if (c == open) == forward {
+                    depth += 1;
+                } else {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(w.pos);
+                    }
+                }
+            }
+            _ => {}
+        }
+        let step = if forward { w.inc() } else { w.dec() };
+        if step == word::Step::Stuck {
+            return None;
+        }
+    }
+}
+
+/// `{` and `}`: the `count`th empty line before or after the paragraph at `row`. Going past
+/// the end of the buffer stops at its last char instead, which an operator then includes, but
+/// only for the last of the paragraphs counted.
+fn paragraph(buffer: &Buffer, row: usize, count: usize, forward: bool) -> Option<Target> {
+    let last_row = buffer.line_count().saturating_sub(1);
+    let is_empty = |row: usize| buffer.line_len(row) == 0;
+    let mut row = row;
+    'paragraphs: for remaining in (0..count).rev() {
+        let mut in_text = false;
+        loop {
+            in_text |= !is_empty(row);
+            let next = if forward {
+                Some(row + 1).filter(|&next| next <= last_row)
+            } else {
+                row.checked_sub(1)
+            };
+            let Some(next) = next else {
+                if remaining > 0 {
+                    return None;
+                }
+                break 'paragraphs;
+            };
+            row = next;
+            if in_text && is_empty(row) {
+                break;
+            }
+        }
+    }
+    if forward && row == last_row && !is_empty(row) {
+        return Some(Target {
+            pos: Position::new(row, buffer.line_len(row) - 1),
+            kind: MotionKind::Inclusive,
+        });
+    }
+    Some(Target {
+        pos: Position::new(row, 0),
+        kind: MotionKind::Exclusive,
+    })
+}
+
+/// Column of the first char on `row` that isn't a space or tab, or the last char if they all
+/// are.
+pub fn first_non_blank(buffer: &Buffer, row: usize) -> usize {
+    let line = buffer.line(row).unwrap_or_default();
+    line.chars()
+        .position(|ch| ch != ' ' && ch != '\t')
+        .unwrap_or_else(|| line.chars().count().saturating_sub(1))
+}
@@ -0,0 +1,729 @@
+//! Commands in Normal, Visual and Operator-pending mode, and turning typed keys into them.
+
+use std::cmp::Ordering;
+
+use super::Editor;
+use super::block;
+use super::cmdline::Prompt;
+use super::keys::{self, Lookup, ctrl};
+use super::macros;
+use super::marks;
+use super::mode::{Mode, VisualKind};
+use super::motion::{Context, FindChar, Motion, first_non_blank};
+use super::operator::{Operator, PendingOperator, Region, RegionKind};
+use super::register;
+use super::textobject::TextObject;
+use crate::buffer::Position;
+
+/// Where `i`, `a` and friends start inserting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertAt {
+    /// `i`
+    Cursor,
+    /// `a`
+    AfterCursor,
+    /// `I`
+    LineStart,
+    /// `A`
+    LineEnd,
+    /// `o`
+    LineBelow,
+    /// `O`
+    LineAbove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalCmd {
+    Move(Motion),
+    /// In Normal mode, waits for a motion. In Visual mode, applies to the selection.
+    Operator(Operator),
+    /// The operator typed twice, `dd`: acts on the whole line.
+    OperatorLine(Operator),
+    /// Shorthands for an operator and a motion, like `x` for `dl` and `D` for `d$`.
+    OperatorMotion(Operator, Motion),
+    Insert(InsertAt),
+    Replace,
+    /// Starts Visual mode, switches to another kind of it or, for the current kind, leaves it.
+    Visual(VisualKind),
+    /// `o` in Visual mode: the cursor goes to the other end of the selection.
+    SwapSelectionEnds,
+    /// `O` in Visual mode: in a block the cursor goes to the other corner on its line.
+    SwapSelectionCorner,
+    /// `I` and `A` in Visual block mode: inserts on every line of the block, before or after
+    /// it.
+    BlockInsert {
+        append: bool,
+    },
+    /// `r` in Visual mode: every char selected becomes the one typed.
+    ReplaceSelection(char),
+    CommandLine,
+    /// A text object after an operator or in Visual mode.
+    Object(TextObject),
+    /// `/` and `?`
+    Search {
+        forward: bool,
+    },
+    /// `*` and `#`, and `g*` and `g#` without `whole`.
+    SearchWord {
+        forward: bool,
+        whole: bool,
+    },
+    /// `.`
+    Repeat,
+    /// `&`, and `g&` for every line.
+    RepeatSubstitute {
+        all: bool,
+    },
+    /// `q{register}`
+    Record(char),
+    /// `q` while recording.
+    StopRecording,
+    /// `@{register}`, `@@` for the last one played.
+    Play(char),
+    /// `m{mark}`
+    SetMark(char),
+    /// Ctrl-O and Ctrl-I, back and forward through the jump list.
+    JumpList(isize),
+    /// `g;` and `g,`, back and forward through the change list.
+    ChangeList(isize),
+    /// `~`: switches the case of the char under the cursor and moves on.
+    ToggleCaseChar,
+    Put {
+        before: bool,
+    },
+    Undo,
+    Redo,
+    /// `g-` and `g+`
+    UndoChronologically(isize),
+    /// `ZZ`
+    WriteQuit,
+    /// `ZQ`
+    Quit,
+    /// Escape: cancels whatever was going on.
+    Cancel,
+}
+
+/// A command with the count and register typed before it, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Normal {
+    pub count: Option<usize>,
+    /// `"x`
+    pub register: Option<char>,
+    pub cmd: NormalCmd,
+}
+
+/// The key after `f`, `t`, `F` or `T`.
+fn find_char(keys: &[char], forward: bool, till: bool) -> Lookup<Motion> {
+    match keys.get(1) {
+        None => Lookup::Incomplete,
+        Some(&ch) if ch.is_control() && ch != keys::TAB => Lookup::Invalid,
+        Some(&ch) => Lookup::Complete(Motion::Find(FindChar { ch, forward, till }), 2),
+    }
+}
+
+fn motion(keys: &[char]) -> Lookup<Motion> {
+    let motion = match keys[0] {
+        'h' | keys::BACKSPACE | keys::DEL => Motion::Left,
+        'l' | ' ' => Motion::Right,
+        'k' => Motion::Up,
+        'j' => Motion::Down,
+        '+' | keys::ENTER => Motion::NextLine,
+        '-' => Motion::PreviousLine,
+        '_' => Motion::CurrentLine,
+        '0' => Motion::LineStart,
+        '^' => Motion::FirstNonBlank,
+        '$' => Motion::LineEnd,
+        '|' => Motion::Column,
+        'G' => Motion::LastLine,
+        'w' => Motion::WordForward { big: false },
+        'W' => Motion::WordForward { big: true },
+        'b' => Motion::WordBackward { big: false },
+        'B' => Motion::WordBackward { big: true },
+        'e' => Motion::WordEnd { big: false },
+        'E' => Motion::WordEnd { big: true },
+        'f' => return find_char(keys, true, false),
+        't' => return find_char(keys, true, true),
+        'F' => return find_char(keys, false, false),
+        'T' => return find_char(keys, false, true),
+        ';' => Motion::RepeatFind { reverse: false },
+        ',' => Motion::RepeatFind { reverse: true },
+        '%' => Motion::MatchPair,
+        '}' => Motion::ParagraphForward,
+        '{' => Motion::ParagraphBackward,
+        'H' => Motion::ScreenTop,
+        'M' => Motion::ScreenMiddle,
+        'L' => Motion::ScreenBottom,
+        'n' => Motion::SearchNext { reverse: false },
+        'N' => Motion::SearchNext { reverse: true },
+        '\'' | '`' => {
+            let Some(&key) = keys.get(1) else {
+                return Lookup::Incomplete;
+            };
+            let Some(name) = marks::mark_name(key) else {
+                return Lookup::Invalid;
+            };
+            return Lookup::Complete(
+                Motion::Mark {
+                    name,
+                    line: keys[0] == '\'',
+                },
+                2,
+            );
+        }
+        'g' => {
+            let motion = match keys.get(1) {
+                None => return Lookup::Incomplete,
+                Some('g') => Motion::FirstLine,
+                Some('e') => Motion::WordEndBackward { big: false },
+                Some('E') => Motion::WordEndBackward { big: true },
+                Some(_) => return Lookup::Invalid,
+            };
+            return Lookup::Complete(motion, 2);
+        }
+        _ => return Lookup::Invalid,
+    };
+    Lookup::Complete(motion, 1)
+}
+
+/// The count at the start of `keys` and how many keys it takes. A leading `0` is the motion.
+fn parse_count(keys: &[char]) -> (Option<usize>, usize) {
+    let digits = match keys.first() {
+        Some('0') => 0,
+        _ => keys.iter().take_while(|key| key.is_ascii_digit()).count(),
+    };
+    let count = (digits > 0).then(|| {
+        keys[..digits].iter().fold(0usize, |count, key| {
+            count
+                .saturating_mul(10)
+                .saturating_add(key.to_digit(10).unwrap_or(0) as usize)
+        })
+    });
+    (count, digits)
+}
+
+/// Where the command itself starts in `keys`, after the count and register typed before it.
+pub fn command_start(keys: &[char], mode: Mode) -> usize {
+    let (_, mut used) = parse_count(keys);
+    if keys.get(used) == Some(&'"')
+        && keys.len() > used + 1
+        && !matches!(mode, Mode::OperatorPending(_))
+    {
+        used += 2 + parse_count(&keys[used + 2..]).1;
+    }
+    used
+}
+
+/// What `keys`, typed in `mode` and while `recording` a macro or not, ask for. A count and a
+/// register can come first, in either order: `2"a3dw` deletes six words into register `a`.
+pub fn parse(keys: &[char], mode: Mode, recording: bool) -> Lookup<Normal> {
+    let (mut count, mut used) = parse_count(keys);
+    let mut register = None;
+    if keys.get(used) == Some(&'"') && !matches!(mode, Mode::OperatorPending(_)) {
+        match keys.get(used + 1) {
+            None => return Lookup::Incomplete,
+            Some(&name) if register::is_name(name) => register = Some(name),
+            Some(_) => return Lookup::Invalid,
+        }
+        let (second, digits) = parse_count(&keys[used + 2..]);
+        count = match (count, second) {
+            (Some(count), Some(second)) => Some(count.saturating_mul(second)),
+            (count, second) => count.or(second),
+        };
+        used += 2 + digits;
+    }
+    let normal = |cmd| Normal {
+        count,
+        register,
+        cmd,
+    };
+    match command(&keys[used..], mode, recording) {
+        Lookup::Complete(cmd, len) => Lookup::Complete(normal(cmd), used + len),
+        Lookup::Ambiguous(cmd, len) => Lookup::Ambiguous(normal(cmd), used + len),
+        Lookup::Incomplete => Lookup::Incomplete,
+        Lookup::Invalid => Lookup::Invalid,
+    }
+}
+
+fn command(keys: &[char], mode: Mode, recording: bool) -> Lookup<NormalCmd> {
+    let Some(&first) = keys.first() else {
+        return Lookup::Incomplete;
+    };
+    if first == keys::ESC {
+        return Lookup::Complete(NormalCmd::Cancel, 1);
+    }
+    if let Mode::OperatorPending(PendingOperator { op, .. }) = mode {
+        // Typed again, `dd`, `>>`, `gUU` or `gUgU`, the operator takes lines.
+        if first == op.key() {
+            return Lookup::Complete(NormalCmd::OperatorLine(op), 1);
+        }
+        if op.is_g_prefixed() && first == 'g' && keys.get(1) == Some(&op.key()) {
+            return Lookup::Complete(NormalCmd::OperatorLine(op), 2);
+        }
+    }
+    if matches!(mode, Mode::OperatorPending(_) | Mode::Visual(_)) && matches!(first, 'i' | 'a') {
+        let Some(&key) = keys.get(1) else {
+            return Lookup::Incomplete;
+        };
+        return match TextObject::from_key(first == 'i', key) {
+            Some(object) => Lookup::Complete(NormalCmd::Object(object), 2),
+            None => Lookup::Invalid,
+        };
+    }
+    match motion(keys) {
+        Lookup::Complete(motion, len) => return Lookup::Complete(NormalCmd::Move(motion), len),
+        Lookup::Incomplete => return Lookup::Incomplete,
+        _ => {}
+    }
+    let search = match first {
+        '/' => Some(NormalCmd::Search { forward: true }),
+        '?' => Some(NormalCmd::Search { forward: false }),
+        _ => None,
+    };
+    if let Some(search) = search {
+        return Lookup::Complete(search, 1);
+    }
+    if let Mode::OperatorPending(_) = mode {
+        return Lookup::Invalid;
+    }
+    if let Some(op) = Operator::from_key(first) {
+        return Lookup::Complete(NormalCmd::Operator(op), 1);
+    }
+    if first == 'g'
+        && let Some(op) = keys.get(1).copied().and_then(Operator::from_g_key)
+    {
+        return Lookup::Complete(NormalCmd::Operator(op), 2);
+    }
+    let visual = matches!(mode, Mode::Visual(_));
+    let block = mode == Mode::Visual(VisualKind::Block);
+    let cmd = match first {
+        'v' => NormalCmd::Visual(VisualKind::Char),
+        'V' => NormalCmd::Visual(VisualKind::Line),
+        key if key == ctrl('v') => NormalCmd::Visual(VisualKind::Block),
+        ':' => NormalCmd::CommandLine,
+        'x' if visual => NormalCmd::Operator(Operator::Delete),
+        's' if visual => NormalCmd::Operator(Operator::Change),
+        '~' if visual => NormalCmd::Operator(Operator::ToggleCase),
+        'u' if visual => NormalCmd::Operator(Operator::Lowercase),
+        'U' if visual => NormalCmd::Operator(Operator::Uppercase),
+        'o' if visual => NormalCmd::SwapSelectionEnds,
+        'O' if visual => NormalCmd::SwapSelectionCorner,
+        'I' if block => NormalCmd::BlockInsert { append: false },
+        'A' if block => NormalCmd::BlockInsert { append: true },
+        'r' if visual => {
+            return match keys.get(1) {
+                None => Lookup::Incomplete,
+                Some(&ch) if ch.is_control() && ch != keys::TAB => Lookup::Invalid,
+                Some(&ch) => Lookup::Complete(NormalCmd::ReplaceSelection(ch), 2),
+            };
+        }
+        _ if visual => return Lookup::Invalid,
+        'i' => NormalCmd::Insert(InsertAt::Cursor),
+        'a' => NormalCmd::Insert(InsertAt::AfterCursor),
+        'I' => NormalCmd::Insert(InsertAt::LineStart),
+        'A' => NormalCmd::Insert(InsertAt::LineEnd),
+        'o' => NormalCmd::Insert(InsertAt::LineBelow),
+        'O' => NormalCmd::Insert(InsertAt::LineAbove),
+        'R' => NormalCmd::Replace,
+        'x' => NormalCmd::OperatorMotion(Operator::Delete, Motion::Right),
+        'X' => NormalCmd::OperatorMotion(Operator::Delete, Motion::Left),
+        's' => NormalCmd::OperatorMotion(Operator::Change, Motion::Right),
+        'D' => NormalCmd::OperatorMotion(Operator::Delete, Motion::LineEnd),
+        'C' => NormalCmd::OperatorMotion(Operator::Change, Motion::LineEnd),
+        'S' => NormalCmd::OperatorLine(Operator::Change),
+        'Y' => NormalCmd::OperatorLine(Operator::Yank),
+        '~' => NormalCmd::ToggleCaseChar,
+        '.' => NormalCmd::Repeat,
+        '&' => NormalCmd::RepeatSubstitute { all: false },
+        '*' => NormalCmd::SearchWord {
+            forward: true,
+            whole: true,
+        },
+        '#' => NormalCmd::SearchWord {
+            forward: false,
+            whole: true,
+        },
+        'p' => NormalCmd::Put { before: false },
+        'P' => NormalCmd::Put { before: true },
+        'u' => NormalCmd::Undo,
+        key if key == ctrl('r') => NormalCmd::Redo,
+        key if key == ctrl('o') => NormalCmd::JumpList(-1),
+        keys::TAB => NormalCmd::JumpList(1),
+        'q' if recording => NormalCmd::StopRecording,
+        'g' | 'Z' | 'q' | '@' | 'm' => {
+            let Some(&second) = keys.get(1) else {
+                return Lookup::Incomplete;
+            };
+            let cmd = match (first, second) {
+                ('q', name) if macros::is_recordable(name) => NormalCmd::Record(name),
+                ('@', name) if name == '@' || register::is_name(name) => NormalCmd::Play(name),
+                ('m', name) if marks::is_settable(name) => NormalCmd::SetMark(name),
+                ('g', ';') => NormalCmd::ChangeList(-1),
+                ('g', ',') => NormalCmd::ChangeList(1),
+                ('g', '*') => NormalCmd::SearchWord {
+                    forward: true,
+                    whole: false,
+                },
+                ('g', '#') => NormalCmd::SearchWord {
+                    forward: false,
+                    whole: false,
+                },
+                ('g', '&') => NormalCmd::RepeatSubstitute { all: true },
+                ('g', '-') => NormalCmd::UndoChronologically(-1),
+                ('g', '+') => NormalCmd::UndoChronologically(1),
+                ('Z', 'Z') => NormalCmd::WriteQuit,
+                ('Z', 'Q') => NormalCmd::Quit,
+                _ => return Lookup::Invalid,
+            };
+            return Lookup::Complete(cmd, 2);
+        }
+        _ => return Lookup::Invalid,
+    };
+    Lookup::Complete(cmd, 1)
+}
+
+impl Editor {
+    pub(super) fn run_normal(
+        &mut self,
+        Normal {
+            count,
+            register,
+            cmd,
+        }: Normal,
+    ) {
+        if register.is_some() {
+            self.register = register;
+        }
+        let cursor = self.doc().cursor;
+        self.doc_mut().buffer.begin_change(cursor);
+        let times = count.unwrap_or(1);
+        match cmd {
+            NormalCmd::Move(motion) => self.move_cursor(motion, count),
+            NormalCmd::Operator(op) => match self.mode {
+                Mode::Visual(kind) => {
+                    let region = self.selection(kind);
+                    self.mode = Mode::Normal;
+                    // A count shifts that many times, the other operators don't take one.
+                    let times = match op {
+                        Operator::ShiftRight | Operator::ShiftLeft => times,
+                        _ => 1,
+                    };
+                    for _ in 0..times {
+                        self.apply_operator(op, region);
+                    }
+                }
+                _ => self.mode = Mode::OperatorPending(PendingOperator { op, count }),
+            },
+            NormalCmd::OperatorLine(op) => {
+                let count = match self.mode {
+                    Mode::OperatorPending(pending) => pending.count_with(count),
+                    _ => count,
+                };
+                self.mode = Mode::Normal;
+                let row = self.doc().cursor.row;
+                let last = self.doc().buffer.line_count() - 1;
+                let region = Region {
+                    start: Position::new(row, 0),
+                    end: Position::new((row + count.unwrap_or(1) - 1).min(last), 0),
+                    kind: RegionKind::Line,
+                };
+                self.apply_operator(op, region);
+            }
+            NormalCmd::OperatorMotion(op, motion) => {
+                self.mode = Mode::OperatorPending(PendingOperator { op, count: None });
+                self.move_cursor(motion, count);
+            }
+            NormalCmd::Object(object) => self.select_object(object, count),
+            NormalCmd::Insert(at) => self.start_insert(at, times),
+            NormalCmd::Replace => {
+                self.enter_insert(Mode::Replace);
+                self.repeat_insert(times, false);
+            }
+            NormalCmd::Visual(kind) => match self.mode {
+                Mode::Visual(current) if current == kind => self.mode = Mode::Normal,
+                Mode::Visual(_) => self.mode = Mode::Visual(kind),
+                _ => {
+                    self.visual_anchor = self.doc().cursor;
+                    self.mode = Mode::Visual(kind);
+                }
+            },
+            NormalCmd::SwapSelectionEnds => {
+                let doc = self.buffers.current_mut();
+                std::mem::swap(&mut doc.cursor, &mut self.visual_anchor);
+                doc.remember_col();
+            }
+            NormalCmd::SwapSelectionCorner => self.swap_selection_corner(),
+            NormalCmd::BlockInsert { append } => self.block_insert(append),
+            NormalCmd::ReplaceSelection(ch) => {
+                if let Mode::Visual(kind) = self.mode {
+                    let region = self.selection(kind);
+                    self.mode = Mode::Normal;
+                    self.replace_chars(region, ch);
+                }
+            }
+            NormalCmd::CommandLine => self.enter_command_line(Prompt::Ex, count),
+            NormalCmd::Search { forward } => {
+                self.enter_command_line(Prompt::Search { forward }, count);
+            }
+            NormalCmd::SearchWord { forward, whole } => self.search_word(forward, whole, count),
+            NormalCmd::Repeat => self.repeat_change(count),
+            NormalCmd::RepeatSubstitute { all } => {
+                // `:s` again without its flags, or `g&` with them and the last search pattern.
+                let command = if all { "%s//~/&" } else { "s" };
+                if let Err(e) = self.execute(command) {
+                    self.message = e.to_string();
+                }
+            }
+            NormalCmd::Record(name) => self.start_recording(name),
+            NormalCmd::StopRecording => self.stop_recording(),
+            NormalCmd::Play(name) => self.play_macro(name, times),
+            NormalCmd::SetMark(name) => self.set_mark(name),
+            NormalCmd::JumpList(delta) => self.step_jump(delta * times as isize),
+            NormalCmd::ChangeList(delta) => self.step_change(delta * times as isize),
+            NormalCmd::ToggleCaseChar => {
+                let Position { row, col } = self.doc().cursor;
+                let len = self.doc().buffer.line_len(row);
+                if col < len {
+                    let end = Position::new(row, (col + times).min(len));
+                    let region = Region {
+                        start: Position::new(row, col),
+                        end,
+                        kind: RegionKind::Char,
+                    };
+                    self.apply_operator(Operator::ToggleCase, region);
+                    self.doc_mut().cursor = end;
+                }
+            }
+            NormalCmd::Put { before } => self.put(before, times),
+            NormalCmd::Undo => (0..times).for_each(|_| self.undo()),
+            NormalCmd::Redo => (0..times).for_each(|_| self.redo()),
+            NormalCmd::UndoChronologically(delta) => {
+                let doc = self.doc_mut();
+                match doc.buffer.undo_chronologically(delta * times as isize) {
+                    Some(cursor) => doc.cursor = cursor,
+                    None if delta < 0 => self.message = "Already at oldest change".to_owned(),
+                    None => self.message = "Already at newest change".to_owned(),
+                }
+            }
+            NormalCmd::WriteQuit => {
+                if self.doc().buffer.is_modified() {
+                    self.save(None);
+                }
+                if let Err(e) = self.quit(false) {
+                    self.message = e.to_string();
+                }
+            }
+            NormalCmd::Quit => self.wants_exit = true,
+            NormalCmd::Cancel => {
+                if self.mode == Mode::Normal {
+                    self.message.clear();
+                }
+                self.mode = Mode::Normal;
+            }
+        }
+        self.finish_normal();
+    }
+
+    /// Ends the undo step a normal command made, unless it left the editor typing text, and
+    /// keeps the cursor on the text. The register named goes too, unless the command is still
+    /// waiting for its motion.
+    pub(super) fn finish_normal(&mut self) {
+        if !self.mode.allows_past_end() {
+            self.commit_change();
+        }
+        if !matches!(self.mode, Mode::OperatorPending(_) | Mode::CommandLine) {
+            self.register = None;
+        }
+        self.clamp_cursor();
+    }
+
+    /// Moves the cursor, or in Operator-pending mode applies the operator to the text the
+    /// motion covers.
+    pub(super) fn move_cursor(&mut self, motion: Motion, count: Option<usize>) {
+        let pending = match self.mode {
+            Mode::OperatorPending(pending) => {
+                self.mode = Mode::Normal;
+                Some(pending)
+            }
+            _ => None,
+        };
+        let count = pending.map_or(count, |pending| pending.count_with(count));
+        if let Motion::Find(find) = motion {
+            self.last_find = Some(find);
+        }
+        if let Motion::Mark { name, line } = motion
+            && name.is_ascii_uppercase()
+            && pending.is_none()
+            && self.doc().buffer.marks().get(name).is_none()
+        {
+            if !self.jump_to_file_mark(name, line) {
+                self.message = "Mark not set".to_owned();
+                self.fail();
+            }
+            return;
+        }
+        let pattern = match (motion, &self.last_search) {
+            (Motion::SearchNext { .. }, Some(search)) => match self.compile_search(&search.pattern)
+            {
+                Ok(pattern) => Some((pattern, search.forward)),
+                Err(e) => {
+                    self.message = e.to_string();
+                    self.fail();
+                    return;
+                }
+            },
+            _ => None,
+        };
+        let doc = self.buffers.current();
+        let cx = Context {
+            buffer: &doc.buffer,
+            cursor: doc.cursor,
+            want_col: doc.want_col,
+            tabstop: doc.options.tabstop,
+            operator: pending.map(|pending| pending.op),
+            count,
+            view: (doc.scroll, self.text_rows),
+            last_find: self.last_find,
+            last_search: pattern
+                .as_ref()
+                .map(|(pattern, forward)| (pattern, *forward)),
+        };
+        let target = motion.target(&cx);
+        if let Motion::SearchNext { reverse } = motion {
+            self.highlight_search = true;
+            self.message = match (&self.last_search, &pattern, target) {
+                (None, ..) | (_, None, _) => "No previous search".to_owned(),
+                (Some(search), _, None) => format!("Pattern not found: {}", search.pattern),
+                (Some(search), Some((pattern, _)), Some(target)) => {
+                    let forward = search.forward ^ reverse;
+                    let wrapped = match (forward, target.pos.cmp(&doc.cursor)) {
+                        (true, Ordering::Greater) | (false, Ordering::Less) => "",
+                        (true, _) => "Search hit BOTTOM, continuing at TOP; ",
+                        (false, _) => "Search hit TOP, continuing at BOTTOM; ",
+                    };
+                    // Counting is left out after an operator, where the cursor doesn't go to
+                    // the match.
+                    match pending {
+                        Some(_) => wrapped.trim_end_matches("; ").to_owned(),
+                        None => format!("{wrapped}{}", pattern.count(&doc.buffer, target.pos)),
+                    }
+                }
+            };
+        }
+        let Some(target) = target else {
+            if let Motion::Mark { .. } = motion {
+                self.message = "Mark not set".to_owned();
+            }
+            self.fail();
+            return;
+        };
+        if let Some(pending) = pending {
+            let region = Region::from_motion(&doc.buffer, doc.cursor, target);
+            self.apply_operator(pending.op, region);
+            return;
+        }
+        if motion.is_jump() {
+            self.push_jump();
+        }
+        let doc = self.doc_mut();
+        doc.cursor = target.pos;
+        if motion == Motion::LineEnd {
+            doc.want_col = usize::MAX;
+        } else if !motion.is_vertical() {
+            doc.remember_col();
+        }
+    }
+
+    /// The visual selection as a region, ends included.
+    pub(super) fn selection(&self, kind: VisualKind) -> Region {
+        let cursor = self.doc().cursor;
+        let (start, end) = (
+            cursor.min(self.visual_anchor),
+            cursor.max(self.visual_anchor),
+        );
+        match kind {
+            VisualKind::Char => {
+                let len = self.doc().buffer.line_len(end.row);
+                // Selecting the end of a line takes its line break too.
+                let end = if end.col >= len && end.row + 1 < self.doc().buffer.line_count() {
+                    Position::new(end.row + 1, 0)
+                } else {
+                    Position::new(end.row, end.col + 1)
+                };
+                Region {
+                    start,
+                    end,
+                    kind: RegionKind::Char,
+                }
+            }
+            VisualKind::Line => Region {
+                start,
+                end,
+                kind: RegionKind::Line,
+            },
+            // The screen columns of both corners, whatever chars are there on the lines
+            // between. After `$` the block goes on to the end of each line.
+            VisualKind::Block => {
+                let doc = self.doc();
+                let cells = |pos: Position| {
+                    let line = doc.buffer.line(pos.row).unwrap_or_default();
+                    block::cells_at(&line, pos.col, doc.options.tabstop)
+                };
+                let (cursor, anchor) = (cells(cursor), cells(self.visual_anchor));
+                let right = if doc.want_col == usize::MAX {
+                    usize::MAX
+                } else {
+                    cursor.end.max(anchor.end) - 1
+                };
+                Region {
+                    start: Position::new(start.row, cursor.start.min(anchor.start)),
+                    end: Position::new(end.row, right),
+                    kind: RegionKind::Block,
+                }
+            }
+        }
+    }
+
+    /// `i`, `a` and friends. With a count, what's typed is typed that many times.
+    fn start_insert(&mut self, at: InsertAt, count: usize) {
+        // Before `o` and `O` open their line.
+        if self.doc().buffer.is_read_only() {
+            self.message = "Buffer is read-only".to_owned();
+            return;
+        }
+        let doc = self.doc_mut();
+        let Position { row, col } = doc.cursor;
+        let len = doc.buffer.line_len(row);
+        doc.cursor = match at {
+            InsertAt::Cursor => doc.cursor,
+            InsertAt::AfterCursor => Position::new(row, (col + 1).min(len)),
+            InsertAt::LineStart => Position::new(row, first_non_blank(&doc.buffer, row).min(len)),
+            InsertAt::LineEnd => Position::new(row, len),
+            InsertAt::LineBelow => {
+                doc.buffer.insert(Position::new(row, len), "\n");
+                Position::new(row + 1, 0)
+            }
+            InsertAt::LineAbove => {
+                doc.buffer.insert(Position::new(row, 0), "\n");
+                Position::new(row, 0)
+            }
+        };
+        self.enter_insert(Mode::Insert);
+        self.repeat_insert(
+            count,
+            matches!(at, InsertAt::LineBelow | InsertAt::LineAbove),
+        );
+    }
+
+    /// Keeps the cursor on a char outside of Insert and Replace mode, where it can't sit past
+    /// the end of the line.
+    pub(super) fn clamp_cursor(&mut self) {
+        let past_end = self.mode.allows_past_end();
+        let doc = self.doc_mut();
+        let mut cursor = doc.buffer.clamp(doc.cursor);
+        let len = doc.buffer.line_len(cursor.row);
+        if !past_end && cursor.col >= len {
+            cursor.col = len.saturating_sub(1);
+        }
+        doc.cursor = cursor;
+    }
+}
@@ -0,0 +1,624 @@
+//! Operators, which act on the text a motion or a visual selection covers, and putting text
+//! back from a register.
+
+use crate::buffer::{Buffer, Position};
+use crate::display;
+
+use super::Editor;
+use super::block;
+use super::document::Document;
+use super::mode::Mode;
+use super::motion::{MotionKind, Target, first_non_blank};
+use super::options::BufferOptions;
+use super::register::Register;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    /// `>`
+    ShiftRight,
+    /// `<`
+    ShiftLeft,
+    /// `=`
+    Reindent,
+    /// `g~`
+    ToggleCase,
+    /// `gu`
+    Lowercase,
+    /// `gU`
+    Uppercase,
+}
+
+impl Operator {
+    pub fn from_key(key: char) -> Option<Self> {
+        Some(match key {
+            'd' => Self::Delete,
+            'c' => Self::Change,
+            'y' => Self::Yank,
+            '>' => Self::ShiftRight,
+            '<' => Self::ShiftLeft,
+            '=' => Self::Reindent,
+            _ => return None,
+        })
+    }
+
+    /// The operators typed as `g` and a key.
+    pub fn from_g_key(key: char) -> Option<Self> {
+        Some(match key {
+            '~' => Self::ToggleCase,
+            'u' => Self::Lowercase,
+            'U' => Self::Uppercase,
+            _ => return None,
+        })
+    }
+
+    /// The operator's key, the one after `g` for those typed with it. Typing it again makes
+    /// the operator act on lines, as in `dd` or `gUU`.
+    pub fn key(self) -> char {
+        match self {
+            Self::Delete => 'd',
+            Self::Change => 'c',
+            Self::Yank => 'y',
+            Self::ShiftRight => '>',
+            Self::ShiftLeft => '<',
+            Self::Reindent => '=',
+            Self::ToggleCase => '~',
+            Self::Lowercase => 'u',
+            Self::Uppercase => 'U',
+        }
+    }
+
+    pub fn is_g_prefixed(self) -> bool {
+        matches!(self, Self::ToggleCase | Self::Lowercase | Self::Uppercase)
+    }
+}
+
+/// An operator waiting for its motion, with the count typed before it. A count typed before
+/// the motion multiplies this one, so `2d3w` deletes six words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingOperator {
+    pub op: Operator,
+    pub count: Option<usize>,
+}
+
+impl PendingOperator {
+    pub fn count_with(self, count: Option<usize>) -> Option<usize> {
+        match (self.count, count) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(1).saturating_mul(b.unwrap_or(1))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Char,
+    Line,
+    Block,
+}
+
+/// Text an operator applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    /// For `Char` regions `end` is exclusive. `Line` regions cover the rows of `start` and
+    /// `end` whatever the columns, and `Block` regions the screen columns between the two on
+    /// each of those rows, both inclusive, up to the end of each line when `end.col` is
+    /// `usize::MAX`.
+    pub start: Position,
+    pub end: Position,
+    pub kind: RegionKind,
+}
+
+impl Region {
+    /// What a motion from `cursor` to `target` covers.
+    ///
+    /// As in vim, an exclusive motion that ends at the start of a later line stops at the end
+    /// of the line before instead, and takes whole lines if it also started in the indent, so
+    /// `d}` at the start of a paragraph doesn't leave an empty line behind.
+    pub fn from_motion(buffer: &Buffer, cursor: Position, target: Target) -> Self {
+        let (start, end) = (cursor.min(target.pos), cursor.max(target.pos));
+        match target.kind {
+            MotionKind::Exclusive if end.col == 0 && end.row > start.row => {
+                let row = end.row - 1;
+                let indent = buffer
+                    .line(start.row)
+                    .unwrap_or_default()
+                    .chars()
+                    .take_while(|&ch| ch == ' ' || ch == '\t')
+                    .count();
+                if start.col <= indent {
+                    Self {
+                        start,
+                        end: Position::new(row, 0),
+                        kind: RegionKind::Line,
+                    }
+                } else {
+                    Self {
+                        start,
+                        end: Position::new(row, buffer.line_len(row)),
+                        kind: RegionKind::Char,
+                    }
+                }
+            }
+            MotionKind::Exclusive => Self {
+                start,
+                end,
+                kind: RegionKind::Char,
+            },
+            MotionKind::Inclusive => Self {
+                start,
+                end: Position::new(end.row, end.col + 1),
+                kind: RegionKind::Char,
+            },
+            MotionKind::Linewise => Self {
+                start,
+                end,
+                kind: RegionKind::Line,
+            },
+        }
+    }
+
+    /// Screen columns a block covers on each row, end exclusive.
+    pub fn block_cols(&self) -> (usize, usize) {
+        (self.start.col, self.end.col.saturating_add(1))
+    }
+
+    /// The first char of the region, where the cursor goes after most operators.
+    fn first_char(&self, doc: &Document) -> Position {
+        match self.kind {
+            RegionKind::Block => {
+                let line = doc.buffer.line(self.start.row).unwrap_or_default();
+                let col = display::char_at(&line, self.start.col, doc.options.tabstop);
+                Position::new(self.start.row, col)
+            }
+            _ => self.start,
+        }
+    }
+
+    pub fn text(&self, doc: &Document) -> String {
+        let buffer = &doc.buffer;
+        match self.kind {
+            RegionKind::Char => buffer.text_range(self.start, self.end),
+            RegionKind::Line => (self.start.row..=self.end.row)
+                .map(|row| buffer.line(row).unwrap_or_default().into_owned())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            RegionKind::Block => {
+                let (left, right) = self.block_cols();
+                (self.start.row..=self.end.row)
+                    .map(|row| {
+                        let line = buffer.line(row).unwrap_or_default();
+                        block::text(&line, left, right, doc.options.tabstop)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }
+    }
+}
+
+/// How wide the block in a register is: as wide as its widest line.
+fn block_width(text: &str, tabstop: usize) -> usize {
+    text.split('\n')
+        .map(|piece| display::width(piece, tabstop))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Deletes rows `first..=last` entirely, leaving one empty line if that was all of them.
+pub(super) fn delete_lines(buffer: &mut Buffer, first: usize, last: usize) {
+    if last + 1 < buffer.line_count() {
+        buffer.delete(Position::new(first, 0), Position::new(last + 1, 0));
+    } else if first > 0 {
+        let end_of_previous = Position::new(first - 1, buffer.line_len(first - 1));
+        buffer.delete(end_of_previous, buffer.end());
+    } else {
+        buffer.delete(Position::default(), buffer.end());
+    }
+}
+
+/// Width of the blanks at the start of `line`, and how many chars they are.
+fn indent_of(line: &str, tabstop: usize) -> (usize, usize) {
+    let blanks: String = line
+        .chars()
+        .take_while(|&ch| ch == ' ' || ch == '\t')
+        .collect();
+    (display::width(&blanks, tabstop), blanks.chars().count())
+}
+
+/// Gives `row` an indent `width` columns wide, made of tabs as far as they go unless
+/// `expandtab` is set.
+fn set_indent(buffer: &mut Buffer, row: usize, width: usize, options: &BufferOptions) {
+    let tabstop = options.tabstop.max(1);
+    let (_, chars) = indent_of(&buffer.line(row).unwrap_or_default(), tabstop);
+    let indent = if options.expandtab {
+        " ".repeat(width)
+    } else {
+        "\t".repeat(width / tabstop) + &" ".repeat(width % tabstop)
+    };
+    if buffer.text_range(Position::new(row, 0), Position::new(row, chars)) != indent {
+        buffer.replace(Position::new(row, 0), Position::new(row, chars), &indent);
+    }
+}
+
+/// The indent `=` gives `row`: one level more than the line above for each bracket left open
+/// there, and one less if the line starts by closing one.
+fn c_indent(buffer: &Buffer, row: usize, options: &BufferOptions) -> usize {
+    let is_close = |ch: char| matches!(ch, ')' | ']' | '}');
+    let line = buffer.line(row).unwrap_or_default();
+    let closes_first = line.trim_start().starts_with(is_close);
+    let Some(above) = (0..row)
+        .rev()
+        .find(|&row| !buffer.line(row).unwrap_or_default().trim().is_empty())
+    else {
+        return 0;
+    };
+    let above = buffer.line(above).unwrap_or_default();
+    let (width, _) = indent_of(&above, options.tabstop);
+    // Brackets the line above starts by closing were already counted when it was indented.
+    let mut chars = above.trim_start().trim_start_matches(is_close).chars();
+    let mut open = 0isize;
+    let mut in_string = false;
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '/' if chars.clone().next() == Some('/') => break,
+            '(' | '[' | '{' => open += 1,
+            ')' | ']' | '}' => open -= 1,
+            _ => {}
+        }
+    }
+    let open = open - isize::from(closes_first);
+    width.saturating_add_signed(open * options.shift_width() as isize)
+}
+
+/// What `op`, one of the case operators, makes of `text`.
+fn change_case(op: Operator, text: &str) -> String {
+    match op {
+        Operator::Lowercase => text.to_lowercase(),
+        Operator::Uppercase => text.to_uppercase(),
+        _ => text
+            .chars()
+            .flat_map(|ch| {
+                let swapped: Vec<char> = if ch.is_lowercase() {
+                    ch.to_uppercase().collect()
+                } else {
+                    ch.to_lowercase().collect()
+                };
+                swapped
+            })
+            .collect(),
+    }
+}
+
+impl Editor {
+    pub(super) fn apply_operator(&mut self, op: Operator, region: Region) {
+        if op != Operator::Yank && self.doc().buffer.is_read_only() {
+            self.message = "Buffer is read-only".to_owned();
+            return;
+        }
+        match op {
+            Operator::Delete | Operator::Change | Operator::Yank => self.cut(op, region),
+            Operator::ShiftRight | Operator::ShiftLeft | Operator::Reindent => {
+                let doc = self.buffers.current_mut();
+                let options = &doc.options;
+                let (first, last) = (region.start.row, region.end.row);
+                for row in first..=last {
+                    if doc.buffer.line_len(row) == 0 {
+                        continue;
+                    }
+                    let line = doc.buffer.line(row).unwrap_or_default();
+                    let (width, _) = indent_of(&line, options.tabstop);
+                    let width = match op {
+                        Operator::ShiftRight => width + options.shift_width(),
+                        Operator::ShiftLeft => width.saturating_sub(options.shift_width()),
+                        _ => c_indent(&doc.buffer, row, options),
+                    };
+                    set_indent(&mut doc.buffer, row, width, options);
+                }
+                doc.cursor = Position::new(first, first_non_blank(&doc.buffer, first));
+            }
+            Operator::ToggleCase | Operator::Lowercase | Operator::Uppercase => {
+                let doc = self.buffers.current_mut();
+                let (first, last) = (region.start.row, region.end.row);
+                let ranges: Vec<(Position, Position)> = match region.kind {
+                    RegionKind::Char => vec![(region.start, region.end)],
+                    RegionKind::Line => vec![(
+                        Position::new(first, 0),
+                        Position::new(last, doc.buffer.line_len(last)),
+                    )],
+                    RegionKind::Block => {
+                        let (left, right) = region.block_cols();
+                        (first..=last)
+                            .map(|row| {
+                                let line = doc.buffer.line(row).unwrap_or_default();
+                                let part = block::part(&line, left, right, doc.options.tabstop);
+                                (Position::new(row, part.start), Position::new(row, part.end))
+                            })
+                            .collect()
+                    }
+                };
+                for (start, end) in ranges {
+                    let (start, end) = (doc.buffer.clamp(start), doc.buffer.clamp(end));
+                    let text = doc.buffer.text_range(start, end);
+                    let changed = change_case(op, &text);
+                    if changed != text {
+                        doc.buffer.replace(start, end, &changed);
+                    }
+                }
+                doc.cursor = match region.kind {
+                    RegionKind::Line => Position::new(first, 0),
+                    _ => region.first_char(doc),
+                };
+            }
+        }
+    }
+
+    /// `d`, `c` and `y`, which put the text in a register as well.
+    fn cut(&mut self, op: Operator, region: Region) {
+        let text = region.text(self.doc());
+        let register = Register {
+            text,
+            kind: region.kind,
+        };
+        if !self.write_register(register, op == Operator::Yank) {
+            return;
+        }
+        let doc = self.buffers.current_mut();
+        let (first, last) = (region.start.row, region.end.row);
+        if op == Operator::Yank {
+            let end = match region.kind {
+                RegionKind::Block => {
+                    let line = doc.buffer.line(last).unwrap_or_default();
+                    Position::new(
+                        last,
+                        display::char_at(&line, region.end.col, doc.options.tabstop),
+                    )
+                }
+                _ => region.end,
+            };
+            let start = region.first_char(doc);
+            let marks = doc.buffer.marks_mut();
+            marks.set('[', start);
+            marks.set(']', end);
+        }
+        match (op, region.kind) {
+            (Operator::Yank, RegionKind::Line) => {
+                doc.cursor.row = first;
+            }
+            (Operator::Yank, _) => {
+                doc.cursor = region.first_char(doc);
+            }
+            (_, RegionKind::Char) => {
+                doc.buffer.delete(region.start, region.end);
+                doc.cursor = region.start;
+                if op == Operator::Change {
+                    self.enter_insert(Mode::Insert);
+                }
+            }
+            (Operator::Change, RegionKind::Line) => {
+                let end = Position::new(last, doc.buffer.line_len(last));
+                doc.buffer.delete(Position::new(first, 0), end);
+                doc.cursor = Position::new(first, 0);
+                self.enter_insert(Mode::Insert);
+            }
+            (_, RegionKind::Line) => {
+                delete_lines(&mut doc.buffer, first, last);
+                let row = first.min(doc.buffer.line_count() - 1);
+                doc.cursor = Position::new(row, first_non_blank(&doc.buffer, row));
+            }
+            (_, RegionKind::Block) => {
+                let (left, right) = region.block_cols();
+                // What's typed after `c` goes on the lines that had something in the block.
+                let mut rows = vec![first];
+                for row in first..=last {
+                    let line = doc.buffer.line(row).unwrap_or_default();
+                    let part = block::part(&line, left, right, doc.options.tabstop);
+                    if part.is_empty() {
+                        continue;
+                    }
+                    // Of a tab or wide char sticking out of the block, what's outside of it
+                    // stays as spaces.
+                    let spaces = " ".repeat(part.before + part.after);
+                    doc.buffer.replace(
+                        Position::new(row, part.start),
+                        Position::new(row, part.end),
+                        &spaces,
+                    );
+                    if row > first {
+                        rows.push(row);
+                    }
+                }
+                doc.cursor = region.first_char(doc);
+                if op == Operator::Change {
+                    self.insert_block(left, rows);
+                }
+            }
+        }
+    }
+
+    /// `:j`: joins rows `first..=last` into one. Unless `keep_spaces`, the joined lines lose
+    /// their indent and get a space in front, except before a `)` or after blanks already
+    /// there.
+    pub(super) fn join_lines(&mut self, first: usize, last: usize, keep_spaces: bool) {
+        if self.doc().buffer.is_read_only() {
+            self.message = "Buffer is read-only".to_owned();
+            return;
+        }
+        let doc = self.buffers.current_mut();
+        let mut col = doc.cursor.col;
+        for _ in first..last {
+            let line = doc.buffer.line(first).unwrap_or_default().into_owned();
+            let next = doc.buffer.line(first + 1).unwrap_or_default().into_owned();
+            let len = line.chars().count();
+            let (indent, separator) = if keep_spaces {
+                (0, "")
+            } else {
+                let (_, indent) = indent_of(&next, doc.options.tabstop);
+                let rest = next.trim_start_matches([' ', '\t']);
+                let bare = rest.is_empty()
+                    || rest.starts_with(')')
+                    || line.is_empty()
+                    || line.ends_with([' ', '\t']);
+                (indent, if bare { "" } else { " " })
+            };
+            doc.buffer.replace(
+                Position::new(first, len),
+                Position::new(first + 1, indent),
+                separator,
+            );
+            col = len;
+        }
+        doc.cursor = Position::new(first, col);
+    }
+
+    /// `p` and `P`: puts the register after or before the cursor, `count` times over.
+    pub(super) fn put(&mut self, before: bool, count: usize) {
+        let mut register = match self.read_register(self.register.unwrap_or('"')) {
+            Ok(register) => register,
+            Err(e) => {
+                self.message = e.to_string();
+                return;
+            }
+        };
+        let tabstop = self.doc().options.tabstop;
+        register.text = match register.kind {
+            RegionKind::Char => register.text.repeat(count),
+            RegionKind::Line => vec![register.text.as_str(); count].join("\n"),
+            // Each copy of a piece is as wide as the block, so the copies line up.
+            RegionKind::Block => {
+                let width = block_width(&register.text, tabstop);
+                register
+                    .text
+                    .split('\n')
+                    .map(|piece| {
+                        let padded =
+                            piece.to_owned() + &" ".repeat(width - display::width(piece, tabstop));
+                        padded.repeat(count - 1) + piece
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        };
+        let doc = self.buffers.current_mut();
+        let Position { row, col } = doc.cursor;
+        match register.kind {
+            RegionKind::Line => {
+                let row = if before {
+                    doc.buffer
+                        .insert(Position::new(row, 0), &format!("{}\n", register.text));
+                    row
+                } else {
+                    let end = Position::new(row, doc.buffer.line_len(row));
+                    doc.buffer.insert(end, &format!("\n{}", register.text));
+                    row + 1
+                };
+                doc.cursor = Position::new(row, first_non_blank(&doc.buffer, row));
+            }
+            RegionKind::Char => {
+                let at = if before || doc.buffer.line_len(row) == 0 {
+                    Position::new(row, col)
+                } else {
+                    Position::new(row, col + 1)
+                };
+                let end = doc.buffer.insert(at, &register.text);
+                doc.cursor = if register.text.contains('\n') {
+                    at
+                } else {
+                    Position::new(end.row, end.col.saturating_sub(1))
+                };
+            }
+            RegionKind::Block => {
+                let line = doc.buffer.line(row).unwrap_or_default();
+                let cells = block::cells_at(&line, col, tabstop);
+                let left = if before || line.is_empty() {
+                    cells.start
+                } else {
+                    cells.end
+                };
+                let width = block_width(&register.text, tabstop);
+                for (i, piece) in register.text.split('\n').enumerate() {
+                    let row = row + i;
+                    if row >= doc.buffer.line_count() {
+                        let end = doc.buffer.end();
+                        doc.buffer.insert(end, "\n");
+                    }
+                    let at = block::split_at(&mut doc.buffer, row, left, tabstop);
+                    // Text after the block stays lined up, nothing is added at the end of a
+                    // line.
+                    let padding = if at < doc.buffer.line_len(row) {
+                        " ".repeat(width - display::width(piece, tabstop))
+                    } else {
+                        String::new()
+                    };
+                    doc.buffer
+                        .insert(Position::new(row, at), &format!("{piece}{padding}"));
+                }
+                let line = doc.buffer.line(row).unwrap_or_default();
+                doc.cursor = Position::new(row, display::char_at(&line, left, tabstop));
+            }
+        }
+    }
+
+    /// `r` in Visual mode: every char selected becomes `ch`, line breaks excepted. In a block
+    /// it takes each screen cell, so a tab becomes as many `ch` as it was wide.
+    pub(super) fn replace_chars(&mut self, region: Region, ch: char) {
+        if self.doc().buffer.is_read_only() {
+            self.message = "Buffer is read-only".to_owned();
+            return;
+        }
+        let doc = self.buffers.current_mut();
+        let (first, last) = (region.start.row, region.end.row);
+        let replaced = |text: &str| -> String {
+            text.chars()
+                .map(|old| if old == '\n' { old } else { ch })
+                .collect()
+        };
+        match region.kind {
+            RegionKind::Char => {
+                let text = doc.buffer.text_range(region.start, region.end);
+                doc.buffer
+                    .replace(region.start, region.end, &replaced(&text));
+            }
+            RegionKind::Line => {
+                for row in first..=last {
+                    let line = doc.buffer.line(row).unwrap_or_default().into_owned();
+                    let end = Position::new(row, line.chars().count());
+                    doc.buffer
+                        .replace(Position::new(row, 0), end, &replaced(&line));
+                }
+            }
+            RegionKind::Block => {
+                let (left, right) = region.block_cols();
+                let tabstop = doc.options.tabstop;
+                let width = display::char_width(ch, 0, tabstop).max(1);
+                for row in first..=last {
+                    let line = doc.buffer.line(row).unwrap_or_default();
+                    let part = block::part(&line, left, right, tabstop);
+                    if part.is_empty() {
+                        continue;
+                    }
+                    let cells = display::width(&block::text(&line, left, right, tabstop), tabstop);
+                    let text = " ".repeat(part.before)
+                        + &ch.to_string().repeat(cells / width)
+                        + &" ".repeat(cells % width + part.after);
+                    doc.buffer.replace(
+                        Position::new(row, part.start),
+                        Position::new(row, part.end),
+                        &text,
+                    );
+                }
+            }
+        }
+        doc.cursor = match region.kind {
+            RegionKind::Line => Position::new(first, 0),
+            _ => region.first_char(doc),
+        };
+    }
+}
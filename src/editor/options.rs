@@ -0,0 +1,410 @@
+//! `:set`, for the settings that can be changed while editing.
+//!
+//! Options are global, local to the window or local to the buffer. A buffer-local option
+//! like `tabstop` also has a global value, which new buffers start with: `:set` changes
+//! both, `:setlocal` only the buffer's and `:setglobal` only the global one. The file's own
+//! properties, like `fileformat`, have no global value. There is only ever one window, so
+//! its options are the same whichever way they're set.
+
+use std::time::Duration;
+
+use thiserror::Error;
+
+use super::Editor;
+use super::keys;
+use super::modeline;
+use super::search::SearchOptions;
+use crate::buffer::{Encoding, FileFormat};
+use crate::display::{ColorScheme, TAB_WIDTH};
+use crate::fileio::WriteOptions;
+
+#[derive(Debug, Error)]
+pub enum OptionError {
+    #[error("Unknown option: {0}")]
+    Unknown(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("Number required after =: {0}")]
+    NumberRequired(String),
+    #[error("Not a global option: {0}")]
+    NotGlobal(String),
+    #[error("Not allowed in a modeline: {0}")]
+    NotAllowed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Number(usize),
+    String(String),
+}
+
+/// Where an option's value is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Global,
+    Window,
+    Buffer,
+}
+
+/// Which values of an option a command sets: `:set`, `:setlocal` or `:setglobal`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Both,
+    Local,
+    Global,
+}
+
+/// The widest `tabstop`, `shiftwidth` and `textwidth` can be, as in vim. Much wider and
+/// every tab would take more cells than the screen could ever show.
+const MAX_WIDTH: usize = 9999;
+
+/// Every option by its name, vim's short name for it and its scope.
+const OPTIONS: &[(&str, &str, Scope)] = &[
+    ("backup", "bk", Scope::Global),
+    ("bomb", "bomb", Scope::Buffer),
+    ("colorscheme", "colo", Scope::Global),
+    ("commentstring", "cms", Scope::Buffer),
+    ("endofline", "eol", Scope::Buffer),
+    ("expandtab", "et", Scope::Buffer),
+    ("fileencoding", "fenc", Scope::Buffer),
+    ("fileformat", "ff", Scope::Buffer),
+    ("filetype", "ft", Scope::Buffer),
+    ("fixendofline", "fixeol", Scope::Buffer),
+    ("hlsearch", "hls", Scope::Global),
+    ("ignorecase", "ic", Scope::Global),
+    ("incsearch", "is", Scope::Global),
+    ("mapleader", "mapleader", Scope::Global),
+    ("modeline", "ml", Scope::Global),
+    ("modelines", "mls", Scope::Global),
+    ("number", "nu", Scope::Window),
+    ("readonly", "ro", Scope::Buffer),
+    ("scrolloff", "so", Scope::Window),
+    ("shiftwidth", "sw", Scope::Buffer),
+    ("smartcase", "scs", Scope::Global),
+    ("tabstop", "ts", Scope::Buffer),
+    ("textwidth", "tw", Scope::Buffer),
+    ("timeoutlen", "tm", Scope::Global),
+    ("wrap", "wrap", Scope::Window),
+];
+
+pub fn full_name(name: &str) -> Option<&'static str> {
+    OPTIONS
+        .iter()
+        .find(|&&(full, short, _)| name == full || name == short)
+        .map(|&(full, ..)| full)
+}
+
+/// Options each buffer has its own value of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferOptions {
+    /// How far apart tab stops are.
+    pub tabstop: usize,
+    /// How far `>` and `<` shift and `=` indents, `tabstop` when 0.
+    pub shiftwidth: usize,
+    /// Indents and the Tab key make spaces rather than tabs.
+    pub expandtab: bool,
+    /// How a comment looks, `%s` standing for its text.
+    pub commentstring: String,
+    /// Typing past this screen column breaks the line, unless it's 0.
+    pub textwidth: usize,
+    /// The last line always ends in a line break when written, even if the file's didn't.
+    pub fixendofline: bool,
+    /// Trailing blanks are taken off every line when written. Only `.editorconfig` sets this.
+    pub trim_trailing_whitespace: bool,
+}
+
+impl Default for BufferOptions {
+    fn default() -> Self {
+        Self {
+            tabstop: TAB_WIDTH,
+            shiftwidth: TAB_WIDTH,
+            expandtab: false,
+            commentstring: "/* %s */".to_owned(),
+            textwidth: 0,
+            fixendofline: false,
+            trim_trailing_whitespace: false,
+        }
+    }
+}
+
+impl BufferOptions {
+    pub fn shift_width(&self) -> usize {
+        match self.shiftwidth {
+            0 => self.tabstop,
+            width => width,
+        }
+    }
+}
+
+/// Options of the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowOptions {
+    /// Line numbers are shown before each line.
+    pub number: bool,
+    /// Lines too long for the screen go on on the next row, rather than off the side.
+    pub wrap: bool,
+    /// How many lines to keep on screen above and below the cursor.
+    pub scrolloff: usize,
+}
+
+impl Default for WindowOptions {
+    fn default() -> Self {
+        Self {
+            number: false,
+            wrap: true,
+            scrolloff: 0,
+        }
+    }
+}
+
+/// What `name&` sets an option back to, which is also what tells the type of its values.
+pub fn default_value(name: &str) -> Value {
+    let search = SearchOptions::default();
+    let buffer = BufferOptions::default();
+    let window = WindowOptions::default();
+    match name {
+        "backup" => Value::Bool(WriteOptions::default().backup),
+        "bomb" | "readonly" => Value::Bool(false),
+        "colorscheme" => Value::String(ColorScheme::default().to_string()),
+        "commentstring" => Value::String(buffer.commentstring),
+        "endofline" => Value::Bool(true),
+        "expandtab" => Value::Bool(buffer.expandtab),
+        "fileencoding" => Value::String(Encoding::default().to_string()),
+        "fileformat" => Value::String(FileFormat::native().to_string()),
+        "filetype" => Value::String(String::new()),
+        "fixendofline" => Value::Bool(buffer.fixendofline),
+        "hlsearch" => Value::Bool(search.highlight),
+        "ignorecase" => Value::Bool(search.case.ignore_case),
+        "incsearch" => Value::Bool(search.incremental),
+        "mapleader" => Value::String("\\".to_owned()),
+        "modeline" => Value::Bool(true),
+        "modelines" => Value::Number(modeline::DEFAULT_LINES),
+        "number" => Value::Bool(window.number),
+        "scrolloff" => Value::Number(window.scrolloff),
+        "shiftwidth" => Value::Number(buffer.shiftwidth),
+        "smartcase" => Value::Bool(search.case.smart_case),
+        "tabstop" => Value::Number(buffer.tabstop),
+        "textwidth" => Value::Number(buffer.textwidth),
+        "wrap" => Value::Bool(window.wrap),
+        _ => Value::Number(keys::DEFAULT_TIMEOUT.as_millis() as usize),
+    }
+}
+
+impl Editor {
+    /// The value of option `name` here, or with `global` its global value, which the file's
+    /// own properties don't have.
+    fn option(&self, name: &str, global: bool) -> Option<Value> {
+        let doc = self.doc();
+        let (buffer, window) = (&doc.buffer, &self.window_options);
+        let options = if global {
+            &self.buffer_options
+        } else {
+            &doc.options
+        };
+        let value = match name {
+            "backup" => Value::Bool(self.write_options.backup),
+            "colorscheme" => Value::String(self.colors.to_string()),
+            "commentstring" => Value::String(options.commentstring.clone()),
+            "expandtab" => Value::Bool(options.expandtab),
+            "fixendofline" => Value::Bool(options.fixendofline),
+            "hlsearch" => Value::Bool(self.search_options.highlight),
+            "ignorecase" => Value::Bool(self.search_options.case.ignore_case),
+            "incsearch" => Value::Bool(self.search_options.incremental),
+            "mapleader" => Value::String(self.map_leader.clone()),
+            "modeline" => Value::Bool(self.modeline),
+            "modelines" => Value::Number(self.modelines),
+            "number" => Value::Bool(window.number),
+            "scrolloff" => Value::Number(window.scrolloff),
+            "shiftwidth" => Value::Number(options.shiftwidth),
+            "smartcase" => Value::Bool(self.search_options.case.smart_case),
+            "tabstop" => Value::Number(options.tabstop),
+            "textwidth" => Value::Number(options.textwidth),
+            "timeoutlen" => Value::Number(self.key_timeout.as_millis() as usize),
+            "wrap" => Value::Bool(window.wrap),
+            _ if global => return None,
+            "bomb" => Value::Bool(buffer.has_bom()),
+            "endofline" => Value::Bool(buffer.has_eol()),
+            "fileencoding" => Value::String(buffer.encoding().to_string()),
+            "fileformat" => Value::String(buffer.file_format().to_string()),
+            "filetype" => Value::String(doc.filetype.clone()),
+            _ => Value::Bool(buffer.is_read_only()),
+        };
+        Some(value)
+    }
+
+    /// Sets the values of option `name` that `target` says. `value` is of the option's type.
+    pub(super) fn set_option(
+        &mut self,
+        name: &str,
+        value: Value,
+        target: Target,
+    ) -> Result<(), OptionError> {
+        let has_global = self.option(name, true).is_some();
+        if target == Target::Global && !has_global {
+            return Err(OptionError::NotGlobal(name.to_owned()));
+        }
+        let doc = self.buffers.current_mut();
+        let buffer = &mut doc.buffer;
+        // Buffer-local options with a global value: the ones of the scopes set.
+        let mut locals = Vec::new();
+        if target != Target::Global {
+            locals.push(&mut doc.options);
+        }
+        if target != Target::Local {
+            locals.push(&mut self.buffer_options);
+        }
+        let window = &mut self.window_options;
+        match (name, value) {
+            ("backup", Value::Bool(on)) => self.write_options.backup = on,
+            ("bomb", Value::Bool(on)) => buffer.set_bom(on),
+            ("colorscheme", Value::String(value)) => {
+                self.colors = value.parse().map_err(OptionError::InvalidArgument)?;
+            }
+            ("commentstring", Value::String(value)) => {
+                locals
+                    .into_iter()
+                    .for_each(|o| o.commentstring = value.clone());
+            }
+            ("endofline", Value::Bool(on)) => buffer.set_eol(on),
+            ("expandtab", Value::Bool(on)) => locals.into_iter().for_each(|o| o.expandtab = on),
+            ("fileencoding", Value::String(value)) => {
+                let encoding: Encoding = value.parse().map_err(OptionError::InvalidArgument)?;
+                buffer.set_encoding(encoding);
+            }
+            ("fileformat", Value::String(value)) => {
+                let format: FileFormat = value.parse().map_err(OptionError::InvalidArgument)?;
+                buffer.set_file_format(format);
+            }
+            ("filetype", Value::String(name)) => self.set_filetype(&name),
+            ("fixendofline", Value::Bool(on)) => {
+                locals.into_iter().for_each(|o| o.fixendofline = on);
+            }
+            ("hlsearch", Value::Bool(on)) => {
+                self.search_options.highlight = on;
+                self.highlight_search = on;
+            }
+            ("ignorecase", Value::Bool(on)) => self.search_options.case.ignore_case = on,
+            ("incsearch", Value::Bool(on)) => self.search_options.incremental = on,
+            ("mapleader", Value::String(value)) => self.map_leader = value,
+            ("modeline", Value::Bool(on)) => self.modeline = on,
+            ("modelines", Value::Number(lines)) => self.modelines = lines,
+            ("number", Value::Bool(on)) => window.number = on,
+            ("readonly", Value::Bool(on)) => buffer.set_read_only(on),
+            ("scrolloff", Value::Number(lines)) => window.scrolloff = lines,
+            ("tabstop", Value::Number(width)) if !(1..=MAX_WIDTH).contains(&width) => {
+                return Err(OptionError::InvalidArgument(format!("tabstop={width}")));
+            }
+            ("shiftwidth" | "textwidth", Value::Number(width)) if width > MAX_WIDTH => {
+                return Err(OptionError::InvalidArgument(format!("{name}={width}")));
+            }
+            ("shiftwidth", Value::Number(width)) => {
+                locals.into_iter().for_each(|o| o.shiftwidth = width);
+            }
+            ("smartcase", Value::Bool(on)) => self.search_options.case.smart_case = on,
+            ("tabstop", Value::Number(width)) => {
+                locals.into_iter().for_each(|o| o.tabstop = width);
+                // Where `j` and `k` go depends on how wide tabs are.
+                self.buffers.current_mut().remember_col();
+            }
+            ("textwidth", Value::Number(width)) => {
+                locals.into_iter().for_each(|o| o.textwidth = width);
+            }
+            ("timeoutlen", Value::Number(ms)) => {
+                self.key_timeout = Duration::from_millis(ms as u64)
+            }
+            ("wrap", Value::Bool(on)) => window.wrap = on,
+            (name, _) => return Err(OptionError::InvalidArgument(name.to_owned())),
+        }
+        Ok(())
+    }
+
+    fn show_option(&self, name: &str, global: bool) -> Option<String> {
+        Some(match self.option(name, global)? {
+            Value::Bool(true) => format!("  {name}"),
+            Value::Bool(false) => format!("no{name}"),
+            Value::Number(number) => format!("  {name}={number}"),
+            Value::String(string) => format!("  {name}={string}"),
+        })
+    }
+
+    /// `:set`, `:setlocal` or `:setglobal` followed by any number of `name`, `noname`,
+    /// `invname`, `name!`, `name?`, `name&` and `name=value`. Values asked for are shown,
+    /// and without arguments all of them, or for `:setlocal` all the local ones.
+    pub(super) fn set_options(&mut self, arg: &str, target: Target) -> Result<(), OptionError> {
+        let global = target == Target::Global;
+        if arg.is_empty() || arg == "all" {
+            self.pager = Some(
+                OPTIONS
+                    .iter()
+                    .filter(|&&(.., scope)| target != Target::Local || scope != Scope::Global)
+                    .filter_map(|&(name, ..)| self.show_option(name, global))
+                    .collect(),
+            );
+            return Ok(());
+        }
+        let mut shown = Vec::new();
+        for item in arg.split_whitespace() {
+            shown.extend(self.set_item(item, target)?);
+        }
+        if !shown.is_empty() {
+            self.message = shown.join(" ");
+        }
+        Ok(())
+    }
+
+    /// One argument of `:set`, returning the value shown if it asks for one.
+    pub(super) fn set_item(
+        &mut self,
+        item: &str,
+        target: Target,
+    ) -> Result<Option<String>, OptionError> {
+        let global = target == Target::Global;
+        let (name, value) = match item.split_once(['=', ':']) {
+            Some((name, value)) => (name, Some(value)),
+            None => (item, None),
+        };
+        let (name, suffix) = match name.strip_suffix(['?', '!', '&']) {
+            Some(stripped) => (stripped, name.chars().last()),
+            None => (name, None),
+        };
+        let (full, negated, inverted) = if let Some(full) = full_name(name) {
+            (full, false, suffix == Some('!'))
+        } else if let Some(full) = name.strip_prefix("no").and_then(full_name) {
+            (full, true, false)
+        } else if let Some(full) = name.strip_prefix("inv").and_then(full_name) {
+            (full, false, true)
+        } else {
+            return Err(OptionError::Unknown(name.to_owned()));
+        };
+        let Some(current) = self.option(full, global) else {
+            return Err(OptionError::NotGlobal(full.to_owned()));
+        };
+        let new = match (current, value) {
+            _ if suffix == Some('?') => None,
+            _ if suffix == Some('&') => Some(default_value(full)),
+            (Value::Bool(_), Some(_)) => {
+                return Err(OptionError::InvalidArgument(item.to_owned()));
+            }
+            (Value::Bool(on), None) if inverted => Some(Value::Bool(!on)),
+            (Value::Bool(_), None) => Some(Value::Bool(!negated)),
+            (_, None) if negated || inverted => {
+                return Err(OptionError::InvalidArgument(item.to_owned()));
+            }
+            (_, None) => None,
+            (Value::Number(_), Some(value)) => Some(Value::Number(
+                value
+                    .parse()
+                    .map_err(|_| OptionError::NumberRequired(item.to_owned()))?,
+            )),
+            (Value::String(_), Some(value)) => Some(Value::String(value.to_owned())),
+        };
+        match new {
+            Some(value) => {
+                self.set_option(full, value, target)?;
+                Ok(None)
+            }
+            None => Ok(self.show_option(full, global)),
+        }
+    }
+}
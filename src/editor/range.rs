@@ -0,0 +1,206 @@
+//! The lines an Ex command acts on, as typed before it: `:3,5d`, `:%y`, `:'<,'>>`,
+//! `:/fn main/+1,$d`.
+
+use thiserror::Error;
+
+use super::marks::mark_name;
+use super::search::{CaseOptions, Pattern, PatternError};
+use crate::buffer::{Buffer, Position};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RangeError {
+    #[error("Invalid range")]
+    Invalid,
+    #[error("Mark not set")]
+    MarkNotSet,
+    #[error("Pattern not found: {0}")]
+    NotFound(String),
+    #[error("No previous regular expression")]
+    NoPrevious,
+    #[error(transparent)]
+    Pattern(#[from] PatternError),
+}
+
+/// What addresses are looked up in.
+pub struct Context<'a> {
+    pub buffer: &'a Buffer,
+    /// The cursor's row, which `.` is.
+    pub row: usize,
+    /// What `//` and `??` search for.
+    pub last_search: Option<&'a str>,
+    pub case: CaseOptions,
+}
+
+/// Lines by number as typed, 1 for the first. 0 is before the first line, which `:m` and
+/// `:t` take as where to put lines and other commands as the first line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    pub fn rows(self) -> (usize, usize) {
+        (self.start.saturating_sub(1), self.end.saturating_sub(1))
+    }
+}
+
+/// The range at the start of `text`, if there is one, and the command after it. Like vim, a
+/// range given backwards is turned around, and of more than two addresses the last two count.
+pub fn parse<'a>(text: &'a str, cx: &Context) -> Result<(Option<LineRange>, &'a str), RangeError> {
+    let text = text.trim_start();
+    let last = cx.buffer.line_count();
+    if let Some(rest) = text.strip_prefix('%') {
+        return Ok((
+            Some(LineRange {
+                start: 1,
+                end: last,
+            }),
+            rest,
+        ));
+    }
+    let mut current = cx.row + 1;
+    let mut range: Option<LineRange> = None;
+    let mut rest = text;
+    loop {
+        let (line, after) = address(rest, current, cx)?;
+        rest = after.trim_start();
+        let separator = rest.chars().next().filter(|&ch| ch == ',' || ch == ';');
+        // A missing address next to a separator is the current line: `:,5` or `:5,`.
+        let line = match (line, separator, range) {
+            (Some(line), ..) => line,
+            (None, Some(_), _) | (None, None, Some(_)) => current,
+            (None, None, None) => break,
+        };
+        range = Some(match range {
+            Some(range) => LineRange {
+                start: range.end,
+                end: line,
+            },
+            None => LineRange {
+                start: line,
+                end: line,
+            },
+        });
+        match separator {
+            Some(separator) => {
+                // After `;` the addresses that follow count from this one.
+                if separator == ';' {
+                    current = line;
+                }
+                rest = &rest[1..];
+            }
+            None => break,
+        }
+    }
+    let Some(range) = range else {
+        return Ok((None, rest));
+    };
+    if range.start > last || range.end > last {
+        return Err(RangeError::Invalid);
+    }
+    Ok((
+        Some(LineRange {
+            start: range.start.min(range.end),
+            end: range.start.max(range.end),
+        }),
+        rest,
+    ))
+}
+
+/// One address, with the `+N` and `-N` after it, relative to line `current`.
+pub fn address<'a>(
+    text: &'a str,
+    current: usize,
+    cx: &Context,
+) -> Result<(Option<usize>, &'a str), RangeError> {
+    let text = text.trim_start();
+    let mut chars = text.chars();
+    let (base, mut rest) = match chars.next() {
+        Some('.') => (Some(current), &text[1..]),
+        Some('$') => (Some(cx.buffer.line_count()), &text[1..]),
+        Some('0'..='9') => {
+            let (number, rest) = number(text);
+            (Some(number), rest)
+        }
+        Some('\'') => {
+            let key = chars.next().ok_or(RangeError::Invalid)?;
+            let name = mark_name(key).ok_or(RangeError::Invalid)?;
+            let pos = cx.buffer.marks().get(name).ok_or(RangeError::MarkNotSet)?;
+            (Some(pos.row + 1), &text[1 + key.len_utf8()..])
+        }
+        Some(delimiter @ ('/' | '?')) => {
+            let (pattern, rest) = split_pattern(&text[1..], delimiter);
+            let pattern = match (pattern.is_empty(), cx.last_search) {
+                (false, _) => pattern,
+                (true, Some(last)) => last.to_owned(),
+                (true, None) => return Err(RangeError::NoPrevious),
+            };
+            // The search starts on the line after the current one, or before it for `?`.
+            let row = current.saturating_sub(1).min(cx.buffer.line_count() - 1);
+            let forward = delimiter == '/';
+            let from = if forward {
+                Position::new(row, cx.buffer.line_len(row))
+            } else {
+                Position::new(row, 0)
+            };
+            let (found, _) = Pattern::new(&pattern, cx.case)?
+                .find(cx.buffer, from, forward)
+                .ok_or(RangeError::NotFound(pattern))?;
+            (Some(found.row + 1), rest)
+        }
+        _ => (None, text),
+    };
+    let mut line = base;
+    loop {
+        let sign = match rest.chars().next() {
+            Some('+') => 1,
+            Some('-') => -1,
+            _ => break,
+        };
+        let (offset, after) = if rest[1..].starts_with(|ch: char| ch.is_ascii_digit()) {
+            number(&rest[1..])
+        } else {
+            (1, &rest[1..])
+        };
+        let moved = line.unwrap_or(current) as isize + sign * offset as isize;
+        line = Some(usize::try_from(moved).map_err(|_| RangeError::Invalid)?);
+        rest = after;
+    }
+    Ok((line, rest))
+}
+
+/// The digits at the start of `text` as a number, and what comes after.
+fn number(text: &str) -> (usize, &str) {
+    let len = text
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(text.len());
+    let number = text[..len].bytes().fold(0usize, |number, digit| {
+        number
+            .saturating_mul(10)
+            .saturating_add((digit - b'0') as usize)
+    });
+    (number, &text[len..])
+}
+
+/// A pattern up to the `delimiter` that ends it, which a backslash escapes, and the text
+/// after. The closing delimiter can be left off at the end of the line.
+pub fn split_pattern(text: &str, delimiter: char) -> (String, &str) {
+    let mut pattern = String::new();
+    let mut chars = text.char_indices();
+    while let Some((idx, ch)) = chars.next() {
+        match ch {
+            ch if ch == delimiter => return (pattern, &text[idx + ch.len_utf8()..]),
+            '\\' => match chars.next() {
+                Some((_, next)) if next == delimiter => pattern.push(next),
+                Some((_, next)) => {
+                    pattern.push('\\');
+                    pattern.push(next);
+                }
+                None => pattern.push('\\'),
+            },
+            ch => pattern.push(ch),
+        }
+    }
+    (pattern, "")
+}
@@ -0,0 +1,269 @@
+//! Where deleted and yanked text goes: the unnamed register, `"0` to `"9` for the last yank
+//! and deletes, `"a` to `"z` named by the user, the read-only `"/`, `":` and `".`, and the
+//! system clipboard behind `"+` and `"*`.
+
+use thiserror::Error;
+
+use super::Editor;
+use super::operator::RegionKind;
+use crate::clipboard::{Clipboard, ClipboardError};
+
+/// Text in a register, and whether it was taken as characters, whole lines or a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    /// Lines are joined with `\n`, without a trailing one for linewise text.
+    pub text: String,
+    pub kind: RegionKind,
+}
+
+impl Register {
+    pub fn chars(text: &str) -> Self {
+        Self {
+            text: text.to_owned(),
+            kind: RegionKind::Char,
+        }
+    }
+
+    /// Adds `other` at the end, for an uppercase register name. Text joins up only when both
+    /// are characters, otherwise it goes on a line of its own.
+    fn append(&mut self, other: Register) {
+        self.kind = match (self.kind, other.kind) {
+            (RegionKind::Char, RegionKind::Char) => {
+                self.text.push_str(&other.text);
+                return;
+            }
+            (RegionKind::Line, _) | (_, RegionKind::Line) => RegionKind::Line,
+            _ => RegionKind::Block,
+        };
+        self.text.push('\n');
+        self.text.push_str(&other.text);
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RegisterError {
+    #[error("Invalid register name: \"{0}")]
+    ReadOnly(char),
+    #[error("Nothing in register \"{0}")]
+    Empty(char),
+    #[error(transparent)]
+    Clipboard(#[from] ClipboardError),
+}
+
+/// Whether `"` followed by `name` picks a register.
+pub fn is_name(name: char) -> bool {
+    name.is_ascii_alphanumeric() || "\"-_/:.+*".contains(name)
+}
+
+/// The order `:registers` lists them in.
+const LISTED: &str = "\"0123456789abcdefghijklmnopqrstuvwxyz-*+.:/";
+
+#[derive(Debug, Default)]
+pub struct Registers {
+    /// What `p` puts without a register named: a copy of the last one written.
+    unnamed: Option<Register>,
+    /// `"0` is the last yank, `"1` to `"9` the last deletes of a line or more, newest first.
+    numbered: [Option<Register>; 10],
+    /// `"-`: the last delete within a line.
+    small_delete: Option<Register>,
+    named: [Option<Register>; 26],
+    /// `".`: the text typed in the last Insert mode.
+    pub inserted: Option<String>,
+    /// `":`: the last Ex command.
+    pub command: Option<String>,
+    /// The last text put on the clipboard from here. When the clipboard still holds it, it
+    /// comes back as lines or a block the way it went out, rather than as characters.
+    copied: Option<Register>,
+    pub clipboard: Clipboard,
+}
+
+impl Registers {
+    /// The register `name`, except `"/` which lives with the search.
+    pub fn get(&self, name: char) -> Result<Register, RegisterError> {
+        let register = match name {
+            '"' => self.unnamed.clone(),
+            '0'..='9' => self.numbered[name as usize - '0' as usize].clone(),
+            'a'..='z' | 'A'..='Z' => {
+                self.named[name.to_ascii_lowercase() as usize - 'a' as usize].clone()
+            }
+            '-' => self.small_delete.clone(),
+            '.' => self.inserted.as_deref().map(Register::chars),
+            ':' => self.command.as_deref().map(Register::chars),
+            '+' | '*' => match self.clipboard.get()? {
+                Some(text) => Some(match &self.copied {
+                    Some(copied) if clipboard_text(copied) == text => copied.clone(),
+                    _ => match text.strip_suffix('\n') {
+                        Some(lines) => Register {
+                            text: lines.to_owned(),
+                            kind: RegionKind::Line,
+                        },
+                        None => Register::chars(&text),
+                    },
+                }),
+                // Nothing readable there, as with OSC 52: what was copied from here is the
+                // best guess.
+                None => self.copied.clone(),
+            },
+            _ => None,
+        };
+        register.ok_or(RegisterError::Empty(name))
+    }
+
+    /// Keeps text deleted or yanked (with `yank`), in register `name` if one was given. The
+    /// unnamed register gets it too, and so do the numbered ones when no other was named:
+    /// `"0` for a yank, `"1` for a delete of a line or more, `"-` for less. Deletes of lines
+    /// go to `"1` even when another register was named.
+    pub fn store(
+        &mut self,
+        name: Option<char>,
+        register: Register,
+        yank: bool,
+    ) -> Result<(), RegisterError> {
+        let name = name.filter(|&name| name != '"');
+        match name {
+            Some('_') => return Ok(()),
+            Some(name @ ('/' | ':' | '.')) => return Err(RegisterError::ReadOnly(name)),
+            _ => {}
+        }
+        let mut result = Ok(());
+        let lines = register.kind == RegionKind::Line || register.text.contains('\n');
+        if !yank && lines {
+            self.numbered[1..].rotate_right(1);
+            self.numbered[1] = Some(register.clone());
+        }
+        self.unnamed = Some(match name {
+            None if yank => {
+                self.numbered[0] = Some(register.clone());
+                register
+            }
+            None if !lines => {
+                self.small_delete = Some(register.clone());
+                register
+            }
+            None => register,
+            Some('+' | '*') => {
+                result = self.clipboard.set(&clipboard_text(&register));
+                self.copied = Some(register.clone());
+                register
+            }
+            Some(name) if name.is_ascii_uppercase() => {
+                let named = &mut self.named[name as usize - 'A' as usize];
+                let register = match named.take() {
+                    Some(mut old) => {
+                        old.append(register);
+                        old
+                    }
+                    None => register,
+                };
+                *named = Some(register.clone());
+                register
+            }
+            Some(name) if name.is_ascii_lowercase() => {
+                self.named[name as usize - 'a' as usize] = Some(register.clone());
+                register
+            }
+            // Numbered registers named explicitly are written like any other.
+            Some(name) if name.is_ascii_digit() => {
+                self.numbered[name as usize - '0' as usize] = Some(register.clone());
+                register
+            }
+            // `"-`
+            Some(_) => {
+                self.small_delete = Some(register.clone());
+                register
+            }
+        });
+        result.map_err(RegisterError::from)
+    }
+}
+
+/// How text from a register goes out to other programs: lines end with a line break each.
+fn clipboard_text(register: &Register) -> String {
+    match register.kind {
+        RegionKind::Line => format!("{}\n", register.text),
+        _ => register.text.clone(),
+    }
+}
+
+/// How `:registers` shows text: on one line, with control characters as `^X`.
+fn printable(text: &str) -> String {
+    let mut shown = String::new();
+    for ch in text.chars() {
+        match ch as u32 {
+            code @ 0..0x20 => {
+                shown.push('^');
+                shown.push(char::from(b'@' + code as u8));
+            }
+            0x7f => shown.push_str("^?"),
+            _ => shown.push(ch),
+        }
+    }
+    shown
+}
+
+impl Editor {
+    /// The contents of register `name`, which can also be `/` for the last search.
+    pub(super) fn read_register(&self, name: char) -> Result<Register, RegisterError> {
+        match name {
+            '/' => self
+                .last_search
+                .as_ref()
+                .map(|search| Register::chars(&search.pattern))
+                .ok_or(RegisterError::Empty('/')),
+            name => self.registers.get(name),
+        }
+    }
+
+    /// Keeps text deleted or yanked (with `yank`) in the register the command was given.
+    /// Fails only for registers that can't be written, the clipboard failing is just reported.
+    pub(super) fn write_register(&mut self, register: Register, yank: bool) -> bool {
+        match self.registers.store(self.register, register, yank) {
+            Ok(()) => true,
+            Err(e @ RegisterError::ReadOnly(_)) => {
+                self.message = e.to_string();
+                false
+            }
+            Err(e) => {
+                self.message = e.to_string();
+                true
+            }
+        }
+    }
+
+    /// `:registers`, of all registers or those in `names`.
+    pub(super) fn list_registers(&mut self, names: &str) {
+        let mut lines = vec!["Type Name Content".to_owned()];
+        for name in LISTED.chars() {
+            if !names.is_empty() && !names.contains(name) {
+                continue;
+            }
+            let register = if name == '/' {
+                self.last_search
+                    .as_ref()
+                    .map(|search| Register::chars(&search.pattern))
+            } else {
+                match self.registers.get(name) {
+                    Ok(register) => Some(register),
+                    Err(RegisterError::Empty(_)) => None,
+                    Err(e) => {
+                        self.message = e.to_string();
+                        None
+                    }
+                }
+            };
+            let Some(register) = register else {
+                continue;
+            };
+            let kind = match register.kind {
+                RegionKind::Char => 'c',
+                RegionKind::Line => 'l',
+                RegionKind::Block => 'b',
+            };
+            lines.push(format!(
+                "  {kind}  \"{name}   {}",
+                printable(&clipboard_text(&register))
+            ));
+        }
+        self.pager = Some(lines);
+    }
+}
@@ -0,0 +1,178 @@
+//! `.`: doing the last change again. What's kept are the commands the change was made of, as
+//! they were parsed, so the replay doesn't depend on what the keys would mean by then.
+
+use super::Editor;
+use super::insert::InsertCmd;
+use super::mode::{Mode, VisualKind};
+use super::normal::{Normal, NormalCmd};
+use crate::buffer::Position;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Normal(Normal),
+    Insert(InsertCmd),
+    /// A key typed on the command line, for a search used as a motion.
+    CommandLine(char),
+}
+
+/// How big the visual selection an operator was used on was. The replay selects as much again,
+/// starting at the cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VisualSize {
+    kind: VisualKind,
+    /// Rows after the first.
+    rows: usize,
+    /// Chars after the first for a selection on one line or a block, otherwise the column the
+    /// selection ends at.
+    cols: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Change {
+    steps: Vec<Step>,
+    visual: Option<VisualSize>,
+}
+
+impl Change {
+    /// Replaces the count the change was made with. After an operator the count typed before
+    /// the motion goes too, so `d3w` repeated with `2.` deletes two words.
+    fn set_count(&mut self, count: usize) {
+        let mut normal = self.steps.iter_mut().filter_map(|step| match step {
+            Step::Normal(normal) => Some(normal),
+            _ => None,
+        });
+        let Some(first) = normal.next() else {
+            return;
+        };
+        first.count = Some(count);
+        if let NormalCmd::Operator(_) = first.cmd
+            && self.visual.is_none()
+            && let Some(second) = normal.next()
+        {
+            second.count = None;
+        }
+    }
+
+    /// Moves a put from `"1` to `"8` on to the next register, so `"1p...` puts back one
+    /// delete after another.
+    fn next_numbered_register(&mut self) {
+        if let Some(Step::Normal(Normal {
+            register: Some(register @ '1'..='8'),
+            cmd: NormalCmd::Put { .. },
+            ..
+        })) = self.steps.first_mut()
+        {
+            *register = char::from(*register as u8 + 1);
+        }
+    }
+}
+
+/// The change being typed and the last one finished.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    current: Change,
+    /// The buffer's change tick when the current change started, to tell whether it changed
+    /// anything by the time it's done.
+    tick: u64,
+    last: Option<Change>,
+}
+
+impl Editor {
+    /// Notes a command about to be run as part of the change being typed.
+    pub(super) fn record_step(&mut self, step: Step) {
+        let recorder = &mut self.repeat;
+        if let Mode::Visual(kind) = self.mode {
+            // Of what's typed in Visual mode only the operator at the end matters, with how
+            // much was selected.
+            let cursor = self.buffers.current().cursor;
+            let (start, end) = (
+                cursor.min(self.visual_anchor),
+                cursor.max(self.visual_anchor),
+            );
+            let cols = match kind {
+                VisualKind::Block => cursor.col.abs_diff(self.visual_anchor.col),
+                _ if start.row == end.row => end.col - start.col,
+                _ => end.col,
+            };
+            recorder.current = Change {
+                steps: Vec::new(),
+                visual: Some(VisualSize {
+                    kind,
+                    rows: end.row - start.row,
+                    cols,
+                }),
+            };
+        }
+        if recorder.current.steps.is_empty() {
+            recorder.tick = self.buffers.current().buffer.change_tick();
+        }
+        recorder.current.steps.push(step);
+    }
+
+    /// Forgets what's been typed of a change so far. Commands `:normal` runs make changes of
+    /// their own rather than becoming part of the `:normal`.
+    pub(super) fn discard_change(&mut self) {
+        self.repeat.current = Change::default();
+    }
+
+    /// After a command has run: if that finished the change being typed, keeps it for `.` when
+    /// it changed the text.
+    pub(super) fn finish_step(&mut self) {
+        match self.mode {
+            Mode::OperatorPending(_) | Mode::Insert | Mode::Replace | Mode::CommandLine => return,
+            Mode::Visual(_) => {
+                self.repeat.current = Change::default();
+                return;
+            }
+            Mode::Normal => {}
+        }
+        let change = std::mem::take(&mut self.repeat.current);
+        let changed = self.buffers.current().buffer.change_tick() != self.repeat.tick;
+        // Undo changes the text too, but isn't something to repeat, and neither are Ex
+        // commands.
+        let repeatable = match change.steps.first() {
+            Some(Step::Normal(Normal { cmd, .. })) => !matches!(
+                cmd,
+                NormalCmd::Undo
+                    | NormalCmd::Redo
+                    | NormalCmd::UndoChronologically(_)
+                    | NormalCmd::Repeat
+                    | NormalCmd::CommandLine
+            ),
+            _ => false,
+        };
+        if changed && repeatable {
+            self.repeat.last = Some(change);
+        }
+    }
+
+    /// `.`, with a count that replaces the one the change was made with.
+    pub(super) fn repeat_change(&mut self, count: Option<usize>) {
+        let Some(mut change) = self.repeat.last.clone() else {
+            return;
+        };
+        if let Some(count) = count {
+            change.set_count(count);
+        }
+        change.next_numbered_register();
+        self.repeat.last = Some(change.clone());
+        if let Some(size) = change.visual {
+            let cursor = self.doc().cursor;
+            let end_row = (cursor.row + size.rows).min(self.doc().buffer.line_count() - 1);
+            let end_col = match size.kind {
+                VisualKind::Char if size.rows > 0 => size.cols,
+                _ => cursor.col + size.cols,
+            };
+            self.visual_anchor = cursor;
+            self.doc_mut().cursor = Position::new(end_row, end_col);
+            self.mode = Mode::Visual(size.kind);
+        }
+        for step in change.steps {
+            match step {
+                Step::Normal(normal) => self.run_normal(normal),
+                Step::Insert(cmd) => self.run_insert(cmd),
+                Step::CommandLine(key) => self.command_line_key(key),
+            }
+        }
+    }
+}
@@ -0,0 +1,587 @@
+//! Finding text in the buffer for `/`, `?`, `n`, `N`, `*` and `#`.
+//!
+//! Patterns are written as in vim and turned into the regex crate's syntax. After `\v` ("very
+//! magic") they're close to what Rust and PCRE take: `(a|b)+`, `\d{2,4}`, `(?:x)`, `(?i)` and
+//! lazy `*?` all work. Searching goes through the buffer a chunk of lines at a time, so a
+//! large file is never copied into one string.
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use regex::{Regex, RegexBuilder};
+use thiserror::Error;
+
+use crate::buffer::{Buffer, Position};
+
+/// How many lines are searched at once.
+const CHUNK_LINES: usize = 256;
+
+/// How many lines past a chunk are searched with it, for matches that start in the chunk and
+/// go on to the lines after.
+const OVERLAP_LINES: usize = 32;
+
+/// Counting matches for "match 3 of 17" stops past this many.
+const MAX_COUNT: usize = 9999;
+
+/// Counting matches stops after this long, so a huge file doesn't hold up each `n`.
+const COUNT_TIME: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PatternError {
+    #[error("Invalid pattern: {0}")]
+    Invalid(String),
+    #[error("Not supported in a pattern: {0}")]
+    Unsupported(String),
+}
+
+/// The last search typed, which `n` and `N` repeat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Search {
+    pub pattern: String,
+    /// Typed after `/` rather than `?`.
+    pub forward: bool,
+}
+
+/// The `ignorecase` and `smartcase` options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CaseOptions {
+    pub ignore_case: bool,
+    /// With `ignore_case`, a pattern with upper case letters in it still matches case.
+    pub smart_case: bool,
+}
+
+/// The options for searching and showing matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub case: CaseOptions,
+    /// `hlsearch`: all matches of the last search are highlighted.
+    pub highlight: bool,
+    /// `incsearch`: the match is shown while the pattern is typed.
+    pub incremental: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            case: CaseOptions::default(),
+            highlight: true,
+            incremental: true,
+        }
+    }
+}
+
+/// What each group in a pattern matched, the whole match first, `None` for a group that
+/// wasn't part of the match.
+pub type Groups = Vec<Option<String>>;
+
+/// Where a match starts, and where it ends, exclusive. The end can be on a later line.
+pub type Match = (Position, Position);
+
+/// How many matches there are and which of them is at the cursor, for "match 3 of 17".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchCount {
+    /// 1 for the first match, `None` when no match starts at the cursor or it wasn't reached.
+    pub index: Option<usize>,
+    pub total: usize,
+    /// Counting stopped early, and there are more than `total`.
+    pub incomplete: bool,
+}
+
+impl std::fmt::Display for MatchCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let more = if self.incomplete { ">" } else { "" };
+        match self.index {
+            Some(index) => write!(f, "match {index} of {more}{}", self.total),
+            None => write!(f, "{more}{} matches", self.total),
+        }
+    }
+}
+
+/// A compiled pattern.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str, case: CaseOptions) -> Result<Self, PatternError> {
+        let (translated, forced) = translate(pattern)?;
+        let ignore_case =
+            forced.unwrap_or(case.ignore_case && !(case.smart_case && has_upper_case(pattern)));
+        let regex = RegexBuilder::new(&translated)
+            .case_insensitive(ignore_case)
+            .multi_line(true)
+            .build()
+            .map_err(|e| match e {
+                regex::Error::Syntax(_) => PatternError::Invalid(pattern.to_owned()),
+                e => PatternError::Invalid(e.to_string()),
+            })?;
+        Ok(Self { regex })
+    }
+
+    /// The next match after (or before) `from`, wrapping around the end of the buffer.
+    pub fn find(&self, buffer: &Buffer, from: Position, forward: bool) -> Option<Match> {
+        let rows = buffer.line_count();
+        // The cursor's line comes up twice: the part after the cursor first, the part before
+        // it after wrapping around.
+        if forward {
+            self.scan(buffer, from.row..rows, true, |m| m.0 > from)
+                .or_else(|| self.scan(buffer, 0..from.row + 1, true, |m| m.0 <= from))
+        } else {
+            self.scan(buffer, 0..from.row + 1, false, |m| m.0 < from)
+                .or_else(|| self.scan(buffer, from.row..rows, false, |m| m.0 >= from))
+        }
+    }
+
+    /// The matches starting in `rows`, in order.
+    pub fn matches(&self, buffer: &Buffer, rows: Range<usize>) -> Vec<Match> {
+        let end = rows.end.min(buffer.line_count());
+        let mut found = Vec::new();
+        let mut start = rows.start;
+        while start < end {
+            let chunk_end = (start + CHUNK_LINES).min(end);
+            let chunk = Chunk::read(buffer, start, chunk_end + OVERLAP_LINES);
+            found.extend(chunk.matches(&self.regex, start..chunk_end));
+            start = chunk_end;
+        }
+        found
+    }
+
+    /// Counts the matches in the buffer and finds the one starting at `at`.
+    pub fn count(&self, buffer: &Buffer, at: Position) -> MatchCount {
+        let started = Instant::now();
+        let rows = buffer.line_count();
+        let mut count = MatchCount {
+            index: None,
+            total: 0,
+            incomplete: false,
+        };
+        let mut start = 0;
+        while start < rows {
+            if count.total > MAX_COUNT || started.elapsed() > COUNT_TIME {
+                count.incomplete = true;
+                break;
+            }
+            let end = (start + CHUNK_LINES).min(rows);
+            let chunk = Chunk::read(buffer, start, end + OVERLAP_LINES);
+            for (match_start, _) in chunk.matches(&self.regex, start..end) {
+                count.total += 1;
+                if match_start == at {
+                    count.index = Some(count.total);
+                }
+            }
+            start = end;
+        }
+        count
+    }
+
+    /// The first match starting at `from` or after it, on a row before `end_row`, without
+    /// wrapping around. It comes with what the groups in the pattern matched, for `:s` to put
+    /// in the replacement.
+    pub fn first_match(
+        &self,
+        buffer: &Buffer,
+        from: Position,
+        end_row: usize,
+    ) -> Option<(Match, Groups)> {
+        let end_row = end_row.min(buffer.line_count());
+        let mut start = from.row;
+        let mut col = from.col;
+        while start < end_row {
+            let chunk_end = (start + CHUNK_LINES).min(end_row);
+            let chunk = Chunk::read(buffer, start, chunk_end + OVERLAP_LINES);
+            // A match found in the lines after the chunk is looked for again with the next one,
+            // which reads further.
+            if let Some(captures) = self
+                .regex
+                .captures_at(&chunk.text, chunk.first_line_byte(col))
+                && let Some(whole) = captures.get(0)
+                && whole.start() < chunk.end_byte(start..chunk_end)
+            {
+                let groups = captures
+                    .iter()
+                    .map(|group| group.map(|group| group.as_str().to_owned()))
+                    .collect();
+                let found = (chunk.position(whole.start()), chunk.position(whole.end()));
+                return Some((found, groups));
+            }
+            start = chunk_end;
+            col = 0;
+        }
+        None
+    }
+
+    /// The first match starting in `rows` that `wanted` takes, or the last one going backward.
+    fn scan(
+        &self,
+        buffer: &Buffer,
+        rows: Range<usize>,
+        forward: bool,
+        wanted: impl Fn(&Match) -> bool,
+    ) -> Option<Match> {
+        let end = rows.end.min(buffer.line_count());
+        if forward {
+            let mut start = rows.start;
+            while start < end {
+                let chunk_end = (start + CHUNK_LINES).min(end);
+                let chunk = Chunk::read(buffer, start, chunk_end + OVERLAP_LINES);
+                if let Some(found) = chunk
+                    .matches(&self.regex, start..chunk_end)
+                    .find(|m| wanted(m))
+                {
+                    return Some(found);
+                }
+                start = chunk_end;
+            }
+        } else {
+            let mut chunk_end = end;
+            while chunk_end > rows.start {
+                let start = chunk_end.saturating_sub(CHUNK_LINES).max(rows.start);
+                let chunk = Chunk::read(buffer, start, chunk_end + OVERLAP_LINES);
+                if let Some(found) = chunk
+                    .matches(&self.regex, start..chunk_end)
+                    .filter(|m| wanted(m))
+                    .last()
+                {
+                    return Some(found);
+                }
+                chunk_end = start;
+            }
+        }
+        None
+    }
+}
+
+/// Lines of the buffer joined with `\n`, to search across line breaks.
+struct Chunk {
+    first_row: usize,
+    text: String,
+    /// Where each line starts in `text`.
+    starts: Vec<usize>,
+}
+
+impl Chunk {
+    /// Rows `first_row` up to `end_row`, or to the end of the buffer.
+    fn read(buffer: &Buffer, first_row: usize, end_row: usize) -> Self {
+        let mut text = String::new();
+        let mut starts = Vec::new();
+        for row in first_row..end_row.min(buffer.line_count()) {
+            let Some(line) = buffer.line(row) else {
+                break;
+            };
+            if row > first_row {
+                text.push('\n');
+            }
+            starts.push(text.len());
+            text.push_str(&line);
+        }
+        Self {
+            first_row,
+            text,
+            starts,
+        }
+    }
+
+    fn position(&self, byte: usize) -> Position {
+        let idx = self.starts.partition_point(|&start| start <= byte) - 1;
+        let col = self.text[self.starts[idx]..byte].chars().count();
+        Position::new(self.first_row + idx, col)
+    }
+
+    /// Where char `col` of the first line is in `text`, or the end of the line past its end.
+    fn first_line_byte(&self, col: usize) -> usize {
+        let line_end = self
+            .starts
+            .get(1)
+            .map_or(self.text.len(), |start| start - 1);
+        self.text[..line_end]
+            .char_indices()
+            .nth(col)
+            .map_or(line_end, |(idx, _)| idx)
+    }
+
+    /// Where the text of `rows` ends: matches starting before it start on one of them.
+    fn end_byte(&self, rows: Range<usize>) -> usize {
+        self.starts
+            .get(rows.end - self.first_row)
+            .copied()
+            .unwrap_or(self.text.len() + 1)
+    }
+
+    /// The matches starting on `rows`, which the chunk has to begin with.
+    fn matches<'a>(
+        &'a self,
+        regex: &'a Regex,
+        rows: Range<usize>,
+    ) -> impl Iterator<Item = Match> + 'a {
+        let end = self.end_byte(rows);
+        regex
+            .find_iter(&self.text)
+            .take_while(move |m| m.start() < end)
+            .map(|m| (self.position(m.start()), self.position(m.end())))
+    }
+}
+
+/// Which chars mean something without a backslash, as set by `\v`, `\m`, `\M` and `\V`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Magic {
+    /// `\v`: everything but letters, digits and `_`.
+    Very,
+    /// `\m`, the default: `^ $ . * [ ~`.
+    On,
+    /// `\M`: only `^` and `$`.
+    Off,
+    /// `\V`: nothing, and `^` and `$` only at the very start and end.
+    VeryOff,
+}
+
+/// `pattern` in the regex crate's syntax, and whether `\c` (`Some(true)`) or `\C` in it says
+/// to ignore case or not.
+fn translate(pattern: &str) -> Result<(String, Option<bool>), PatternError> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut out = String::new();
+    let mut magic = Magic::On;
+    let mut ignore_case = None;
+    // Right after the start, `\(` or `\|`, where `^` is an anchor.
+    let mut branch_start = true;
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        i += 1;
+        let at_start = std::mem::replace(&mut branch_start, false);
+        let escaped = ch == '\\';
+        let ch = if escaped {
+            match chars.get(i) {
+                Some(&next) => {
+                    i += 1;
+                    next
+                }
+                None => {
+                    out.push_str(r"\\");
+                    break;
+                }
+            }
+        } else {
+            ch
+        };
+        // Chars that take a backslash to be special in one mode and lose it in another.
+        let special = match ch {
+            '(' | ')' | '|' | '+' | '=' | '?' | '{' | '<' | '>' | '@' | '%' => {
+                escaped != (magic == Magic::Very)
+            }
+            '.' | '*' | '[' | '~' => escaped != matches!(magic, Magic::Very | Magic::On),
+            '^' | '$' => escaped == (magic == Magic::VeryOff),
+            _ => escaped,
+        };
+        if !special {
+            push_literal(&mut out, ch);
+            continue;
+        }
+        match ch {
+            'v' | 'm' | 'M' | 'V' | 'c' | 'C' => {
+                match ch {
+                    'v' => magic = Magic::Very,
+                    'm' => magic = Magic::On,
+                    'M' => magic = Magic::Off,
+                    'V' => magic = Magic::VeryOff,
+                    'c' => ignore_case = Some(true),
+                    _ => ignore_case = Some(false),
+                }
+                // `\c^foo` still starts with an anchor.
+                branch_start = at_start;
+            }
+            '^' if at_start || magic == Magic::Very => out.push('^'),
+            '$' if at_end(&chars[i..], magic) || magic == Magic::Very => out.push('$'),
+            '^' | '$' => push_literal(&mut out, ch),
+            '.' | '*' | '+' | '~' => out.push(ch),
+            '=' | '?' => out.push('?'),
+            '(' => {
+                out.push('(');
+                branch_start = true;
+            }
+            '%' if chars.get(i) == Some(&'(') => {
+                i += 1;
+                out.push_str("(?:");
+                branch_start = true;
+            }
+            ')' => out.push(')'),
+            '|' => {
+                out.push('|');
+                branch_start = true;
+            }
+            '<' => out.push_str(r"\b{start}"),
+            '>' => out.push_str(r"\b{end}"),
+            '{' => i += braces(&chars[i..], magic, &mut out),
+            '[' => match bracket(&chars[i..]) {
+                Some((class, used)) => {
+                    out.push_str(&class);
+                    i += used;
+                }
+                None => push_literal(&mut out, '['),
+            },
+            'n' => out.push_str(r"\n"),
+            't' => out.push_str(r"\t"),
+            'r' => out.push_str(r"\r"),
+            'e' => out.push_str(r"\x1b"),
+            'd' | 'D' | 'w' | 'W' | 's' | 'S' | 'b' | 'B' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            'a' => out.push_str("[A-Za-z]"),
+            'A' => out.push_str("[^A-Za-z]"),
+            'l' => out.push_str("[a-z]"),
+            'L' => out.push_str("[^a-z]"),
+            'u' => out.push_str("[A-Z]"),
+            'U' => out.push_str("[^A-Z]"),
+            'x' => out.push_str("[0-9A-Fa-f]"),
+            'X' => out.push_str("[^0-9A-Fa-f]"),
+            'o' => out.push_str("[0-7]"),
+            'O' => out.push_str("[^0-7]"),
+            'h' => out.push_str("[A-Za-z_]"),
+            'H' => out.push_str("[^A-Za-z_]"),
+            'i' | 'k' => out.push_str(r"\w"),
+            'I' | 'K' => out.push_str(r"[^\W\d]"),
+            '1'..='9' => return Err(PatternError::Unsupported(format!("\\{ch}"))),
+            'z' | '@' | '%' => {
+                let next: String = chars.get(i).into_iter().collect();
+                return Err(PatternError::Unsupported(format!("\\{ch}{next}")));
+            }
+            ch => push_literal(&mut out, ch),
+        }
+    }
+    Ok((out, ignore_case))
+}
+
+/// Whether a `$` followed by `rest` ends the pattern or a branch, which makes it an anchor.
+fn at_end(rest: &[char], magic: Magic) -> bool {
+    match rest {
+        [] => true,
+        ['\\', '|' | ')', ..] if magic != Magic::Very => true,
+        ['|' | ')', ..] if magic == Magic::Very => true,
+        _ => false,
+    }
+}
+
+fn push_literal(out: &mut String, ch: char) {
+    out.push_str(&regex::escape(ch.encode_utf8(&mut [0; 4])));
+}
+
+/// A count after `\{` (or `{` after `\v`), from the chars that follow it: `n`, `n,`, `,m`,
+/// `n,m`, and each of them with `-` first for as few as possible. Adds it to `out` and returns
+/// how many chars it took. Anything else is taken as text, as Rust's `\b{start}` after `\v`.
+fn braces(rest: &[char], magic: Magic, out: &mut String) -> usize {
+    let close = rest.iter().position(|&ch| ch == '}');
+    let Some(close) = close else {
+        push_literal(out, '{');
+        return 0;
+    };
+    // vim also takes `\}` as the end.
+    let body_end = match (magic, close.checked_sub(1).map(|idx| rest[idx])) {
+        (Magic::Very, _) | (_, None) => close,
+        (_, Some('\\')) => close - 1,
+        _ => close,
+    };
+    let body: String = rest[..body_end].iter().collect();
+    let (lazy, counts) = match body.strip_prefix('-') {
+        Some(counts) => (true, counts),
+        None => (false, body.as_str()),
+    };
+    if !counts.chars().all(|ch| ch.is_ascii_digit() || ch == ',') || counts.matches(',').count() > 1
+    {
+        out.push('{');
+        out.push_str(&regex::escape(&body));
+        out.push('}');
+        return close + 1;
+    }
+    let quantifier = match counts.split_once(',') {
+        None if counts.is_empty() => "*".to_owned(),
+        None => format!("{{{counts}}}"),
+        Some(("", "")) => "*".to_owned(),
+        Some(("", max)) => format!("{{0,{max}}}"),
+        Some((min, max)) => format!("{{{min},{max}}}"),
+    };
+    out.push_str(&quantifier);
+    if lazy {
+        out.push('?');
+    }
+    close + 1
+}
+
+/// A bracket expression from the chars after `[`, and how many chars it took, or `None` when
+/// there's no `]` to end it and the `[` is just a char.
+fn bracket(rest: &[char]) -> Option<(String, usize)> {
+    let mut class = String::from("[");
+    let mut i = 0;
+    if rest.first() == Some(&'^') {
+        class.push('^');
+        i += 1;
+    }
+    // A `]` first is part of the set.
+    if rest.get(i) == Some(&']') {
+        class.push_str(r"\]");
+        i += 1;
+    }
+    while i < rest.len() {
+        let ch = rest[i];
+        i += 1;
+        match ch {
+            ']' => {
+                class.push(']');
+                return Some((class, i));
+            }
+            '[' if rest.get(i) == Some(&':') => {
+                // A class like `[:alpha:]`, which the regex crate takes as it is.
+                let len = rest[i..].windows(2).position(|pair| pair == [':', ']'])?;
+                class.push('[');
+                class.extend(&rest[i..i + len + 2]);
+                i += len + 2;
+            }
+            '\\' => {
+                let next = *rest.get(i)?;
+                i += 1;
+                match next {
+                    'n' => class.push_str(r"\n"),
+                    't' => class.push_str(r"\t"),
+                    'e' => class.push_str(r"\x1b"),
+                    'd' | 'w' | 's' => {
+                        class.push('\\');
+                        class.push(next);
+                    }
+                    next => push_literal(&mut class, next),
+                }
+            }
+            // Set operations in the regex crate, plain chars in vim.
+            '[' | '&' | '~' => {
+                class.push('\\');
+                class.push(ch);
+            }
+            ch => class.push(ch),
+        }
+    }
+    None
+}
+
+/// Whether `pattern` has an upper case letter, not counting ones after a backslash like `\S`.
+fn has_upper_case(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            chars.next();
+        } else if ch.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+/// `text` as a pattern that matches it literally, for `*` and `#`.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for ch in text.chars() {
+        if matches!(ch, '\\' | '/' | '?' | '.' | '*' | '$' | '^' | '~' | '[') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
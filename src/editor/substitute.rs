@@ -0,0 +1,578 @@
+//! `:substitute`, and repeating it with `:&`, `:~`, `&` and `g&`.
+
+use std::ops::Range;
+
+use thiserror::Error;
+
+use super::Editor;
+use super::cmdline::Prompt;
+use super::commands::{self, CommandError};
+use super::keys::{self, ctrl};
+use super::mode::Mode;
+use super::motion::first_non_blank;
+use super::range::{LineRange, split_pattern};
+use super::search::{CaseOptions, Groups, Match, Pattern, PatternError, Search};
+use crate::buffer::{Buffer, Position};
+
+#[derive(Debug, Error)]
+pub enum SubstituteError {
+    #[error("Pattern not found: {0}")]
+    NotFound(String),
+    #[error("No previous substitute regular expression")]
+    NoPrevious,
+    #[error("Cannot ask for confirmation in :normal or :global")]
+    ConfirmNested,
+    #[error("Buffer is read-only")]
+    ReadOnly,
+    #[error(transparent)]
+    Pattern(#[from] PatternError),
+}
+
+/// The flags after `:s/pat/rep/`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags {
+    /// `g`: every match in a line rather than the first.
+    pub global: bool,
+    /// `c`: asks before each substitution.
+    pub confirm: bool,
+    /// `n`: only counts the matches.
+    pub count_only: bool,
+    /// `e`: finding nothing isn't an error.
+    pub quiet: bool,
+    /// `i` and `I`: ignores case or doesn't, whatever the options say.
+    pub ignore_case: Option<bool>,
+}
+
+/// Flags at the start of `text`, and what comes after them. `&` first keeps the `previous`
+/// ones.
+fn parse_flags(text: &str, previous: Flags) -> (Flags, &str) {
+    let (mut flags, text) = match text.strip_prefix('&') {
+        Some(rest) => (previous, rest),
+        None => (Flags::default(), text),
+    };
+    for (idx, ch) in text.char_indices() {
+        match ch {
+            'g' => flags.global = true,
+            'c' => flags.confirm = true,
+            'n' => flags.count_only = true,
+            'e' => flags.quiet = true,
+            'i' => flags.ignore_case = Some(true),
+            'I' => flags.ignore_case = Some(false),
+            _ => return (flags, &text[idx..]),
+        }
+    }
+    (flags, "")
+}
+
+/// A `\u` to `\E` in a replacement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Case {
+    UpperNext,
+    LowerNext,
+    Upper,
+    Lower,
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    /// `\1` to `\9`, and `&` or `\0` for the whole match.
+    Group(usize),
+    Case(Case),
+}
+
+/// The text `:s` puts in for each match, with what groups matched and case changes still to
+/// fill in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    parts: Vec<Part>,
+}
+
+impl Replacement {
+    /// Line breaks go in for `\r`, `\n` and a CR typed with Ctrl-V.
+    pub fn parse(text: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars();
+        while let Some(ch) = chars.next() {
+            let part = match ch {
+                '&' => Part::Group(0),
+                '\r' => Part::Text("\n".to_owned()),
+                '\\' => match chars.next() {
+                    Some(digit @ '0'..='9') => Part::Group(digit as usize - '0' as usize),
+                    Some('n' | 'r') => Part::Text("\n".to_owned()),
+                    Some('t') => Part::Text("\t".to_owned()),
+                    Some('u') => Part::Case(Case::UpperNext),
+                    Some('l') => Part::Case(Case::LowerNext),
+                    Some('U') => Part::Case(Case::Upper),
+                    Some('L') => Part::Case(Case::Lower),
+                    Some('E' | 'e') => Part::Case(Case::End),
+                    Some(ch) => Part::Text(ch.to_string()),
+                    None => Part::Text("\\".to_owned()),
+                },
+                ch => Part::Text(ch.to_string()),
+            };
+            match part {
+                Part::Text(text) => literal.push_str(&text),
+                part => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(part);
+                }
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Self { parts }
+    }
+
+    /// The text to put in for a match whose groups matched `groups`.
+    pub fn expand(&self, groups: &Groups) -> String {
+        let mut out = String::new();
+        let mut next = None;
+        let mut all = None;
+        for part in &self.parts {
+            let text = match part {
+                Part::Text(text) => text.as_str(),
+                Part::Group(idx) => groups.get(*idx).and_then(Option::as_deref).unwrap_or(""),
+                Part::Case(Case::End) => {
+                    all = None;
+                    continue;
+                }
+                Part::Case(case @ (Case::UpperNext | Case::LowerNext)) => {
+                    next = Some(*case);
+                    continue;
+                }
+                Part::Case(case) => {
+                    all = Some(*case);
+                    continue;
+                }
+            };
+            for ch in text.chars() {
+                match next.take().or(all) {
+                    Some(Case::Upper | Case::UpperNext) => out.extend(ch.to_uppercase()),
+                    Some(Case::Lower | Case::LowerNext) => out.extend(ch.to_lowercase()),
+                    _ => out.push(ch),
+                }
+            }
+        }
+        out
+    }
+}
+
+/// `text` with each `~` not after a backslash replaced by `previous`, the replacement of the
+/// last substitute.
+fn with_previous(text: &str, previous: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '~' => out.push_str(previous),
+            '\\' => {
+                out.push('\\');
+                out.extend(chars.next());
+            }
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+/// The last `:s`, which the ways of repeating it start from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastSubstitute {
+    pattern: String,
+    /// With any `~` in it already replaced.
+    replacement: String,
+    flags: Flags,
+}
+
+/// A substitute going through its range. With the `c` flag it stops at each match to ask.
+#[derive(Debug)]
+pub struct Run {
+    /// As typed, for the error when nothing matches.
+    source: String,
+    pattern: Pattern,
+    replacement: Replacement,
+    flags: Flags,
+    /// Where the next match is looked for.
+    from: Position,
+    /// An empty match here doesn't count, being right where the last match ended.
+    skip_empty_at: Option<Position>,
+    /// The last row of the range, which moves as lines are added and removed.
+    last_row: usize,
+    /// The match being asked about.
+    asking: Option<(Match, Groups)>,
+    /// Matches found, whether substituted or not.
+    found: usize,
+    /// Substitutions made, or with `n` matches counted.
+    count: usize,
+    /// How many lines they were on.
+    lines: usize,
+    /// The row the last one ended on.
+    last_line: Option<usize>,
+}
+
+impl Run {
+    /// The next match in the range, if there's any left.
+    fn next_match(&mut self, buffer: &Buffer) -> Option<(Match, Groups)> {
+        loop {
+            if self.from.row > self.last_row {
+                return None;
+            }
+            let ((start, end), groups) =
+                self.pattern
+                    .first_match(buffer, self.from, self.last_row + 1)?;
+            if start == end && self.skip_empty_at == Some(start) {
+                self.skip_empty_at = None;
+                self.from = if start.col < buffer.line_len(start.row) {
+                    Position::new(start.row, start.col + 1)
+                } else {
+                    Position::new(start.row + 1, 0)
+                };
+                continue;
+            }
+            self.found += 1;
+            return Some(((start, end), groups));
+        }
+    }
+
+    /// Substitutes a match, or with `n` only counts it, and moves on.
+    fn substitute(&mut self, buffer: &mut Buffer, (start, end): Match, groups: &Groups) {
+        let new_end = if self.flags.count_only {
+            end
+        } else {
+            let text = self.replacement.expand(groups);
+            let added = text.matches('\n').count() as isize - (end.row - start.row) as isize;
+            self.last_row = self.last_row.saturating_add_signed(added);
+            buffer.replace(start, end, &text)
+        };
+        self.count += 1;
+        if self.last_line != Some(start.row) {
+            self.lines += 1;
+        }
+        self.last_line = Some(new_end.row);
+        self.skip(start, end, new_end);
+    }
+
+    /// Moves on past a match from `start` to `end`, which now ends at `new_end`. Without `g`
+    /// that's to the next line, unless the match took in a line break: what came after it
+    /// is on this line now.
+    fn skip(&mut self, start: Position, end: Position, new_end: Position) {
+        if self.flags.global || end.row > start.row {
+            self.from = new_end;
+            self.skip_empty_at = Some(new_end);
+        } else {
+            self.from = Position::new(new_end.row + 1, 0);
+            self.skip_empty_at = None;
+        }
+    }
+
+    /// The lines of `rows` as they'd look after substituting, with the columns the
+    /// replacements take, for the ones that would change.
+    fn preview(
+        &self,
+        buffer: &Buffer,
+        rows: Range<usize>,
+    ) -> Vec<(usize, String, Vec<Range<usize>>)> {
+        let mut previews = Vec::new();
+        for row in rows.start.max(self.from.row)..rows.end.min(self.last_row + 1) {
+            let Some(line) = buffer.line(row) else {
+                break;
+            };
+            let chars: Vec<char> = line.chars().collect();
+            let mut text = String::new();
+            let mut cols = Vec::new();
+            let mut col = 0;
+            let mut from = Position::new(row, 0);
+            let mut skip_empty_at = None;
+            while let Some(((start, end), groups)) = self.pattern.first_match(buffer, from, row + 1)
+            {
+                if start == end && skip_empty_at == Some(start) {
+                    if start.col >= chars.len() {
+                        break;
+                    }
+                    from.col += 1;
+                    skip_empty_at = None;
+                    continue;
+                }
+                text.extend(&chars[col..start.col]);
+                // A line break put in shows as `^M`.
+                let replacement = self.replacement.expand(&groups).replace('\n', "\r");
+                let at = text.chars().count();
+                cols.push(at..at + replacement.chars().count());
+                text.push_str(&replacement);
+                col = end.col;
+                if end.row > row {
+                    // The match goes on to a later line: the rest of that line comes after.
+                    let rest = buffer.line(end.row).unwrap_or_default();
+                    text.extend(rest.chars().skip(end.col));
+                    col = chars.len();
+                    break;
+                }
+                if !self.flags.global {
+                    break;
+                }
+                from = end;
+                skip_empty_at = Some(end);
+            }
+            if !cols.is_empty() {
+                text.extend(&chars[col..]);
+                previews.push((row, text, cols));
+            }
+        }
+        previews
+    }
+}
+
+impl Editor {
+    /// `:s/pat/rep/flags count`, `:s flags count`, `:&&`, `:~` and so on: works out the
+    /// pattern, replacement and flags of a substitute from what was typed and the last one.
+    fn parse_substitute(
+        &self,
+        name: &str,
+        arg: &str,
+    ) -> Result<(LastSubstitute, Option<usize>), CommandError> {
+        let last = self.last_substitute.as_ref();
+        let last_search = || {
+            self.last_search
+                .as_ref()
+                .map(|search| search.pattern.clone())
+                .ok_or(SubstituteError::NoPrevious)
+        };
+        let previous_replacement = last.map_or("", |last| last.replacement.as_str());
+        let delimiter = arg
+            .chars()
+            .next()
+            .filter(|&ch| !ch.is_alphanumeric() && !matches!(ch, '\\' | '"' | '|' | ' ' | '&'));
+        let (pattern, replacement, rest) = match delimiter {
+            Some(delimiter) if name != "&" && name != "~" => {
+                let (pattern, rest) = split_pattern(&arg[delimiter.len_utf8()..], delimiter);
+                let (replacement, rest) = split_pattern(rest, delimiter);
+                let pattern = if pattern.is_empty() {
+                    last_search()?
+                } else {
+                    pattern
+                };
+                (
+                    pattern,
+                    with_previous(&replacement, previous_replacement),
+                    rest,
+                )
+            }
+            _ => {
+                let last = last.ok_or(SubstituteError::NoPrevious)?;
+                // `:~` takes the last search pattern instead.
+                let pattern = match name {
+                    "~" => last_search()?,
+                    _ => last.pattern.clone(),
+                };
+                (pattern, last.replacement.clone(), arg)
+            }
+        };
+        let (flags, rest) = parse_flags(rest, last.map_or_else(Flags::default, |last| last.flags));
+        let count = commands::count(rest.trim())?;
+        Ok((
+            LastSubstitute {
+                pattern,
+                replacement,
+                flags,
+            },
+            count,
+        ))
+    }
+
+    /// Starts going through rows `first` to `last` for a substitute.
+    fn substitute_run(
+        &self,
+        substitute: &LastSubstitute,
+        first: usize,
+        last: usize,
+    ) -> Result<Run, SubstituteError> {
+        let flags = substitute.flags;
+        let case = match flags.ignore_case {
+            Some(ignore_case) => CaseOptions {
+                ignore_case,
+                smart_case: false,
+            },
+            None => self.search_options.case,
+        };
+        Ok(Run {
+            source: substitute.pattern.clone(),
+            pattern: Pattern::new(&substitute.pattern, case)?,
+            replacement: Replacement::parse(&substitute.replacement),
+            flags,
+            from: Position::new(first, 0),
+            skip_empty_at: None,
+            last_row: last,
+            asking: None,
+            found: 0,
+            count: 0,
+            lines: 0,
+            last_line: None,
+        })
+    }
+
+    /// `:s` and the commands repeating it, named `name`. All the substitutions are one undo
+    /// step, even when asked about one at a time.
+    pub(super) fn substitute(
+        &mut self,
+        range: Option<LineRange>,
+        name: &str,
+        arg: &str,
+    ) -> Result<(), CommandError> {
+        let (substitute, count) = self.parse_substitute(name, arg)?;
+        let (first, last) = self.counted_rows(range, count);
+        let run = self.substitute_run(&substitute, first, last)?;
+        if run.flags.confirm && (self.ex_depth > 1 || self.in_global) {
+            return Err(SubstituteError::ConfirmNested.into());
+        }
+        // Counting matches with `n` changes nothing, so it's fine anywhere.
+        if !run.flags.count_only && self.doc().buffer.is_read_only() {
+            return Err(SubstituteError::ReadOnly.into());
+        }
+        let forward = self
+            .last_search
+            .as_ref()
+            .is_none_or(|search| search.forward);
+        self.last_search = Some(Search {
+            pattern: substitute.pattern.clone(),
+            forward,
+        });
+        self.highlight_search = true;
+        self.last_substitute = Some(substitute);
+        Ok(self.continue_substitute(run)?)
+    }
+
+    /// Substitutes until the end of the range, or until a match has to be asked about.
+    fn continue_substitute(&mut self, mut run: Run) -> Result<(), SubstituteError> {
+        loop {
+            let buffer = &mut self.buffers.current_mut().buffer;
+            let Some((found, groups)) = run.next_match(buffer) else {
+                break;
+            };
+            if run.flags.confirm {
+                self.message = format!(
+                    "replace with {} (y/n/a/q/l)?",
+                    run.replacement.expand(&groups)
+                );
+                self.doc_mut().cursor = found.0;
+                run.asking = Some((found, groups));
+                self.substitute_prompt = Some(run);
+                return Ok(());
+            }
+            run.substitute(buffer, found, &groups);
+        }
+        self.finish_substitute(run)
+    }
+
+    fn finish_substitute(&mut self, run: Run) -> Result<(), SubstituteError> {
+        if run.found == 0 {
+            // Under `:g` lines without a match are no surprise.
+            return if run.flags.quiet || self.in_global {
+                Ok(())
+            } else {
+                Err(SubstituteError::NotFound(run.source))
+            };
+        }
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        let lines = format!("on {} line{}", run.lines, plural(run.lines));
+        self.message = if run.flags.count_only {
+            let es = if run.count == 1 { "" } else { "es" };
+            format!("{} match{es} {lines}", run.count)
+        } else if run.count > 0 {
+            format!("{} substitution{} {lines}", run.count, plural(run.count))
+        } else {
+            String::new()
+        };
+        if let (Some(row), false) = (run.last_line, run.flags.count_only) {
+            let doc = self.doc_mut();
+            doc.cursor = Position::new(row, first_non_blank(&doc.buffer, row));
+            doc.remember_col();
+        }
+        Ok(())
+    }
+
+    /// A key typed while `:s///c` asks about a match: `y` substitutes it, `l` substitutes it
+    /// and stops, `n` skips it, `a` substitutes it and the rest without asking, `q` and
+    /// Escape stop.
+    pub(super) fn answer_substitute_prompt(&mut self, key: char) {
+        let Some(mut run) = self.substitute_prompt.take() else {
+            return;
+        };
+        let Some(((start, end), groups)) = run.asking.take() else {
+            return;
+        };
+        let buffer = &mut self.buffers.current_mut().buffer;
+        let done = match key {
+            'y' | 'l' | 'a' => {
+                run.substitute(buffer, (start, end), &groups);
+                run.flags.confirm = key != 'a';
+                key == 'l'
+            }
+            'n' => {
+                run.skip(start, end, end);
+                false
+            }
+            'q' | keys::ESC => true,
+            key if key == ctrl('c') => true,
+            _ => {
+                run.asking = Some(((start, end), groups));
+                self.substitute_prompt = Some(run);
+                return;
+            }
+        };
+        let result = if done {
+            self.finish_substitute(run)
+        } else {
+            self.continue_substitute(run)
+        };
+        if let Err(e) = result {
+            self.message = e.to_string();
+        }
+        if self.substitute_prompt.is_none() {
+            self.doc_mut().buffer.commit_change();
+            self.clamp_cursor();
+        }
+    }
+
+    /// The match `:s///c` is asking about.
+    pub(super) fn substitute_asking(&self) -> Option<Match> {
+        let run = self.substitute_prompt.as_ref()?;
+        run.asking.as_ref().map(|(found, _)| *found)
+    }
+
+    /// While `:s/pat/rep/` is typed, the lines of `rows` it would change as they'd look after
+    /// it, with the columns of what it put in.
+    pub(super) fn preview_substitute(
+        &self,
+        rows: Range<usize>,
+    ) -> Vec<(usize, String, Vec<Range<usize>>)> {
+        if self.mode != Mode::CommandLine || self.command_line.prompt != Prompt::Ex {
+            return Vec::new();
+        }
+        let Some((range, name, arg)) = self.typed_command(&self.command_line.text) else {
+            return Vec::new();
+        };
+        // Only once the replacement is being typed, not while the pattern still is.
+        let Some(delimiter) = arg.chars().next() else {
+            return Vec::new();
+        };
+        let (_, rest) = split_pattern(&arg[delimiter.len_utf8()..], delimiter);
+        let typing_replacement = !rest.is_empty() || arg.len() > 1 && arg.ends_with(delimiter);
+        if !commands::abbrev(name, "substitute", 1) || !typing_replacement {
+            return Vec::new();
+        }
+        let Ok((substitute, count)) = self.parse_substitute(name, arg) else {
+            return Vec::new();
+        };
+        let (first, last) = self.counted_rows(range, count);
+        match self.substitute_run(&substitute, first, last) {
+            Ok(run) if !run.flags.count_only => run.preview(&self.doc().buffer, rows),
+            _ => Vec::new(),
+        }
+    }
+}
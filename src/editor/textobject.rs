@@ -0,0 +1,632 @@
+//! Text objects, `iw`, `a(`, `it` and the rest: after an operator or in Visual mode they take
+//! the text around the cursor rather than moving it. Like the word motions they follow vim's
+//! rules, down to what happens with the blanks around a word or a sentence.
+
+use std::ops::RangeInclusive;
+
+use super::Editor;
+use super::mode::{Mode, VisualKind};
+use super::motion::{MotionKind, Target};
+use super::operator::Region;
+use super::word::{self, Step, Walker};
+use crate::buffer::{Buffer, Position};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Word { big: bool },
+    Sentence,
+    Paragraph,
+    Quote(char),
+    Block { open: char, close: char },
+    Tag,
+}
+
+/// A text object, `inner` for the `i` ones, which leave out blanks, brackets and tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextObject {
+    pub kind: ObjectKind,
+    pub inner: bool,
+}
+
+impl TextObject {
+    /// The object for the key typed after `i` or `a`.
+    pub fn from_key(inner: bool, key: char) -> Option<Self> {
+        let kind = match key {
+            'w' => ObjectKind::Word { big: false },
+            'W' => ObjectKind::Word { big: true },
+            's' => ObjectKind::Sentence,
+            'p' => ObjectKind::Paragraph,
+            '"' | '\'' | '`' => ObjectKind::Quote(key),
+            '(' | ')' | 'b' => ObjectKind::Block {
+                open: '(',
+                close: ')',
+            },
+            '{' | '}' | 'B' => ObjectKind::Block {
+                open: '{',
+                close: '}',
+            },
+            '[' | ']' => ObjectKind::Block {
+                open: '[',
+                close: ']',
+            },
+            '<' | '>' => ObjectKind::Block {
+                open: '<',
+                close: '>',
+            },
+            't' => ObjectKind::Tag,
+            _ => return None,
+        };
+        Some(Self { kind, inner })
+    }
+
+    /// Where the text the object covers around `cursor` starts, and a target that says where
+    /// it ends the way a motion's would. A count takes that many words, sentences or
+    /// paragraphs, or for brackets and tags goes that many levels out.
+    pub fn select(
+        self,
+        buffer: &Buffer,
+        cursor: Position,
+        count: usize,
+    ) -> Option<(Position, Target)> {
+        let include = !self.inner;
+        match self.kind {
+            ObjectKind::Word { big } => word(buffer, cursor, count, include, big),
+            ObjectKind::Sentence => sentence(buffer, cursor, count, include),
+            ObjectKind::Paragraph => paragraph(buffer, cursor, count, include),
+            ObjectKind::Quote(quote) => self::quote(buffer, cursor, quote, count, include),
+            ObjectKind::Block { open, close } => block(buffer, cursor, open, close, count, include),
+            ObjectKind::Tag => tag(buffer, cursor, count, include),
+        }
+    }
+
+    /// Whether selecting the object again in Visual mode goes further out, rather than on to
+    /// the next one.
+    fn nests(self) -> bool {
+        matches!(
+            self.kind,
+            ObjectKind::Quote(_) | ObjectKind::Block { .. } | ObjectKind::Tag
+        )
+    }
+}
+
+fn target(pos: Position, kind: MotionKind) -> Target {
+    Target { pos, kind }
+}
+
+/// `iw`, `aw`, `iW` and `aW`. Blanks count as a word of their own for `iw`; `aw` takes the
+/// blanks after the word, or the ones before it if there are none after.
+fn word(
+    buffer: &Buffer,
+    cursor: Position,
+    count: usize,
+    include: bool,
+    big: bool,
+) -> Option<(Position, Target)> {
+    let mut w = Walker::new(buffer, cursor);
+    w.back_in_line(big);
+    let mut start = w.pos;
+    let mut include_white = false;
+    let mut inclusive = true;
+    if (w.class(big) == 0) == include {
+        // On blanks for `aw`, or on a word for `iw`: up to the end of the word.
+        let (pos, moved) = word::end_word(buffer, w.pos, 1, big, true, true);
+        if !moved {
+            return None;
+        }
+        w.set(pos);
+    } else {
+        // Up to the start of the next word, less one char.
+        let (pos, _) = word::fwd_word(buffer, w.pos, 1, big, true);
+        w.set(pos);
+        if w.pos.col == 0 {
+            w.dec_line();
+        } else {
+            w.pos.col -= 1;
+        }
+        include_white = include;
+    }
+    for left in (1..count).rev() {
+        inclusive = true;
+        if w.inc_line() == Step::Stuck {
+            return None;
+        }
+        if include != (w.class(big) == 0) {
+            let (pos, moved) = word::fwd_word(buffer, w.pos, 1, big, true);
+            if !moved && left > 1 {
+                return None;
+            }
+            w.set(pos);
+            // Just past a line break, which isn't taken.
+            if w.pos.col == 0 {
+                inclusive = false;
+            } else {
+                w.pos.col -= 1;
+            }
+        } else {
+            let (pos, moved) = word::end_word(buffer, w.pos, 1, big, true, true);
+            if !moved {
+                return None;
+            }
+            w.set(pos);
+        }
+    }
+    if include_white && (w.class(big) != 0 || (w.pos.col == 0 && !inclusive)) {
+        // No blanks after the word to take, so take the ones before it, but not the indent.
+        let mut before = Walker::new(buffer, start);
+        if before.pos.col > 0 {
+            before.pos.col -= 1;
+            before.back_in_line(big);
+            if before.class(big) == 0 && before.pos.col > 0 {
+                start = before.pos;
+            }
+        }
+    }
+    let kind = if inclusive {
+        MotionKind::Inclusive
+    } else {
+        MotionKind::Exclusive
+    };
+    Some((start, target(w.pos, kind)))
+}
+
+/// Some lines as one run of chars, with a line break after each but the last.
+struct Flat {
+    chars: Vec<char>,
+    first_row: usize,
+    /// Index of the first char of each line.
+    starts: Vec<usize>,
+}
+
+impl Flat {
+    fn new(buffer: &Buffer, rows: RangeInclusive<usize>) -> Self {
+        let first_row = *rows.start();
+        let mut chars = Vec::new();
+        let mut starts = Vec::new();
+        for row in rows {
+            if !starts.is_empty() {
+                chars.push('\n');
+            }
+            starts.push(chars.len());
+            chars.extend(buffer.line(row).unwrap_or_default().chars());
+        }
+        Self {
+            chars,
+            first_row,
+            starts,
+        }
+    }
+
+    fn pos(&self, idx: usize) -> Position {
+        let line = self.starts.partition_point(|&start| start <= idx) - 1;
+        Position::new(self.first_row + line, idx - self.starts[line])
+    }
+
+    fn idx(&self, pos: Position) -> usize {
+        let line = pos.row - self.first_row;
+        let end = self
+            .starts
+            .get(line + 1)
+            .map_or(self.chars.len(), |next| next - 1);
+        (self.starts[line] + pos.col).min(end)
+    }
+}
+
+fn is_blank(ch: char) -> bool {
+    matches!(ch, ' ' | '\t' | '\n')
+}
+
+/// `is` and `as`. A sentence ends at a `.`, `!` or `?`, and any closing brackets and quotes
+/// after it, that's followed by a blank or the end of the paragraph.
+fn sentence(
+    buffer: &Buffer,
+    cursor: Position,
+    count: usize,
+    include: bool,
+) -> Option<(Position, Target)> {
+    if buffer.line_len(cursor.row) == 0 {
+        return None;
+    }
+    let is_text = |row: usize| buffer.line_len(row) > 0;
+    let first = (0..cursor.row)
+        .rev()
+        .find(|&row| !is_text(row))
+        .map_or(0, |row| row + 1);
+    let last = (cursor.row + 1..buffer.line_count())
+        .find(|&row| !is_text(row))
+        .map_or(buffer.line_count() - 1, |row| row - 1);
+    let flat = Flat::new(buffer, first..=last);
+    let chars = &flat.chars;
+
+    // Sentences and the blanks between them, one after the other.
+    let mut units = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        if is_blank(chars[i]) {
+            while i < chars.len() && is_blank(chars[i]) {
+                i += 1;
+            }
+        } else {
+            let mut ended = false;
+            while i < chars.len() && !ended {
+                let ch = chars[i];
+                i += 1;
+                if matches!(ch, '.' | '!' | '?') {
+                    while i < chars.len() && matches!(chars[i], ')' | ']' | '"' | '\'') {
+                        i += 1;
+                    }
+                    ended = i == chars.len() || is_blank(chars[i]);
+                }
+            }
+            while i > start && is_blank(chars[i - 1]) {
+                i -= 1;
+            }
+        }
+        units.push(start..i);
+    }
+
+    let at = flat.idx(cursor);
+    let k = units.iter().position(|unit| unit.contains(&at))?;
+    let on_blank = is_blank(chars[units[k].start]);
+    let to = if include {
+        k + count * 2 - 1
+    } else {
+        k + count - 1
+    };
+    let mut start = units[k].start;
+    let end = if to < units.len() {
+        units[to].end
+    } else if include && !on_blank && to == units.len() {
+        // No blanks after the last sentence: take the ones before the first instead.
+        if k > 0 {
+            start = units[k - 1].start;
+        }
+        units[to - 1].end
+    } else {
+        return None;
+    };
+    Some((
+        flat.pos(start),
+        target(flat.pos(end), MotionKind::Exclusive),
+    ))
+}
+
+/// `ip` and `ap`, which take whole lines. Lines with only blanks on them separate paragraphs,
+/// and count as a paragraph of their own for `ip`; `ap` takes the ones after the paragraph, or
+/// those before it if there are none after.
+fn paragraph(
+    buffer: &Buffer,
+    cursor: Position,
+    count: usize,
+    include: bool,
+) -> Option<(Position, Target)> {
+    let rows = buffer.line_count();
+    let white = |row: usize| buffer.line(row).unwrap_or_default().trim().is_empty();
+    let white_in_front = white(cursor.row);
+    let mut start = cursor.row;
+    while start > 0 && white(start - 1) == white_in_front {
+        start -= 1;
+    }
+    // The row after the ones taken so far.
+    let mut next = start;
+    while next < rows && white(next) {
+        next += 1;
+    }
+    let mut left = count;
+    if !include && white_in_front {
+        left -= 1;
+    }
+    while left > 0 {
+        left -= 1;
+        if next == rows {
+            return None;
+        }
+        let do_white = !include && white(next);
+        if include || !do_white {
+            next += 1;
+            while next < rows && !white(next) {
+                next += 1;
+            }
+        }
+        if left == 0 && white_in_front && include {
+            break;
+        }
+        if include || do_white {
+            while next < rows && white(next) {
+                next += 1;
+            }
+        }
+    }
+    if include && !white_in_front && !white(next - 1) {
+        while start > 0 && white(start - 1) {
+            start -= 1;
+        }
+    }
+    Some((
+        Position::new(start, 0),
+        target(Position::new(next - 1, 0), MotionKind::Linewise),
+    ))
+}
+
+/// `i"`, `a'` and the like, which only look at the cursor's line. A quote right after an odd
+/// number of backslashes doesn't count.
+fn quote(
+    buffer: &Buffer,
+    cursor: Position,
+    quote: char,
+    count: usize,
+    include: bool,
+) -> Option<(Position, Target)> {
+    let line: Vec<char> = buffer.line(cursor.row)?.chars().collect();
+    let is_quote = |i: usize| {
+        line[i] == quote && line[..i].iter().rev().take_while(|&&ch| ch == '\\').count() % 2 == 0
+    };
+    let next_quote = |from: usize| (from..line.len()).find(|&i| is_quote(i));
+    let col = cursor.col;
+    let (mut open, mut close) = if col < line.len() && is_quote(col) {
+        // Whether a quote opens or closes depends on how many come before it.
+        if (0..col).filter(|&i| is_quote(i)).count() % 2 == 0 {
+            (col, next_quote(col + 1)?)
+        } else {
+            ((0..col).rev().find(|&i| is_quote(i))?, col)
+        }
+    } else {
+        match (0..col.min(line.len())).rev().find(|&i| is_quote(i)) {
+            Some(open) => (open, next_quote(col)?),
+            // Nothing quoted around the cursor: the next quoted text on the line.
+            None => {
+                let open = next_quote(col)?;
+                (open, next_quote(open + 1)?)
+            }
+        }
+    };
+    let at = |col: usize| Position::new(cursor.row, col);
+    if !include && count < 2 {
+        return Some((at(open + 1), target(at(close), MotionKind::Exclusive)));
+    }
+    if include {
+        if line
+            .get(close + 1)
+            .is_some_and(|&ch| ch == ' ' || ch == '\t')
+        {
+            while line
+                .get(close + 1)
+                .is_some_and(|&ch| ch == ' ' || ch == '\t')
+            {
+                close += 1;
+            }
+        } else {
+            while open > 0 && matches!(line[open - 1], ' ' | '\t') {
+                open -= 1;
+            }
+        }
+    }
+    Some((at(open), target(at(close), MotionKind::Inclusive)))
+}
+
+/// `i(`, `a{` and the like: the `count`th pair of brackets around the cursor, or the one the
+/// cursor is on. When the inside starts a line after the opening bracket and ends on a line
+/// before the closing one, it's those lines in between.
+fn block(
+    buffer: &Buffer,
+    cursor: Position,
+    open: char,
+    close: char,
+    count: usize,
+    include: bool,
+) -> Option<(Position, Target)> {
+    let mut w = Walker::new(buffer, cursor);
+    let mut levels = count;
+    if w.ch() == Some(close) {
+        // On the closing bracket: back to its opening one.
+        let mut depth = 0usize;
+        loop {
+            match w.ch() {
+                Some(ch) if ch == close => depth += 1,
+                Some(ch) if ch == open => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            if w.dec() == Step::Stuck {
+                return None;
+            }
+        }
+        levels -= 1;
+    } else if w.ch() == Some(open) {
+        levels -= 1;
+    }
+    for _ in 0..levels {
+        // Back to the unmatched opening bracket.
+        let mut depth = 0usize;
+        loop {
+            if w.dec() == Step::Stuck {
+                return None;
+            }
+            match w.ch() {
+                Some(ch) if ch == close => depth += 1,
+                Some(ch) if ch == open && depth == 0 => break,
+                Some(ch) if ch == open => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+    let start = w.pos;
+    let mut depth = 0usize;
+    loop {
+        match w.ch() {
+            Some(ch) if ch == open => depth += 1,
+            Some(ch) if ch == close => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            _ => {}
+        }
+        if w.inc() == Step::Stuck {
+            return None;
+        }
+    }
+    if include {
+        return Some((start, target(w.pos, MotionKind::Inclusive)));
+    }
+
+    let mut inside = Walker::new(buffer, start);
+    inside.inc_line();
+    let start = inside.pos;
+    let mut start_of_line = w.pos.col == 0;
+    w.dec_line();
+    while w.in_indent(true) {
+        start_of_line = true;
+        if w.dec_line() != Step::Char {
+            break;
+        }
+    }
+    if start_of_line {
+        w.inc_line();
+        Some((start, target(w.pos, MotionKind::Exclusive)))
+    } else if start <= w.pos {
+        Some((start, target(w.pos, MotionKind::Inclusive)))
+    } else {
+        // Nothing between the brackets.
+        Some((start, target(start, MotionKind::Exclusive)))
+    }
+}
+
+/// `it` and `at`: the text between the `count`th pair of matching tags around the cursor,
+/// with `at` the tags too.
+fn tag(
+    buffer: &Buffer,
+    cursor: Position,
+    count: usize,
+    include: bool,
+) -> Option<(Position, Target)> {
+    let flat = Flat::new(buffer, 0..=buffer.line_count() - 1);
+    let chars = &flat.chars;
+    let at = flat.idx(cursor);
+
+    // Each pair as where the opening tag starts and ends and where the closing one does.
+    let mut pairs = Vec::new();
+    let mut open_tags: Vec<(String, usize, usize)> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '<' {
+            i += 1;
+            continue;
+        }
+        let Some(len) = chars[i..].iter().position(|&ch| ch == '>') else {
+            break;
+        };
+        let (start, end) = (i, i + len + 1);
+        let inside = &chars[start + 1..end - 1];
+        i = end;
+        let closing = inside.first() == Some(&'/');
+        let name: String = inside[usize::from(closing)..]
+            .iter()
+            .take_while(|ch| !ch.is_whitespace() && **ch != '/')
+            .collect();
+        if name.is_empty() || name.starts_with(['!', '?']) || inside.last() == Some(&'/') {
+            continue;
+        }
+        if !closing {
+            open_tags.push((name, start, end));
+        } else if let Some(k) = open_tags.iter().rposition(|(open, ..)| *open == name) {
+            // Tags left open inside this pair never get closed.
+            let (_, open_start, open_end) = open_tags[k];
+            open_tags.truncate(k);
+            pairs.push((open_start, open_end, start, end));
+        }
+    }
+    let mut around: Vec<_> = pairs
+        .into_iter()
+        .filter(|&(open_start, _, _, close_end)| open_start <= at && at < close_end)
+        .collect();
+    around.sort_by_key(|&(open_start, ..)| std::cmp::Reverse(open_start));
+    let (open_start, open_end, close_start, close_end) = *around.get(count - 1)?;
+    let (start, end) = if include {
+        (open_start, close_end)
+    } else {
+        (open_end, close_start)
+    };
+    Some((
+        flat.pos(start),
+        target(flat.pos(end), MotionKind::Exclusive),
+    ))
+}
+
+impl Editor {
+    /// A text object typed after an operator, which it then applies to, or in Visual mode,
+    /// where it becomes the selection or extends it.
+    pub(super) fn select_object(&mut self, object: TextObject, count: Option<usize>) {
+        match self.mode {
+            Mode::OperatorPending(pending) => {
+                self.mode = Mode::Normal;
+                let count = pending.count_with(count).unwrap_or(1);
+                let doc = self.doc();
+                let Some((start, target)) = object.select(&doc.buffer, doc.cursor, count) else {
+                    self.fail();
+                    return;
+                };
+                let region = Region::from_motion(&doc.buffer, start, target);
+                self.apply_operator(pending.op, region);
+            }
+            Mode::Visual(kind) => {
+                let count = count.unwrap_or(1);
+                let doc = self.doc();
+                let buffer = &doc.buffer;
+                let (cursor, anchor) = (doc.cursor, self.visual_anchor);
+                let selected = (cursor.min(anchor), cursor.max(anchor));
+                let found = if cursor == anchor {
+                    object.select(buffer, cursor, count)
+                } else if object.nests() {
+                    // Already selected: the next one out that's bigger.
+                    (count..)
+                        .map_while(|count| object.select(buffer, cursor, count))
+                        .find(|&(start, target)| {
+                            let end = visual_end(buffer, start, target);
+                            start <= selected.0 && end >= selected.1 && (start, end) != selected
+                        })
+                } else {
+                    // Extends the selection by the objects after it.
+                    let mut next = Walker::new(buffer, selected.1);
+                    next.inc_line();
+                    object
+                        .select(buffer, next.pos, count)
+                        .map(|(_, target)| (selected.0, target))
+                };
+                let Some((start, target)) = found else {
+                    self.fail();
+                    return;
+                };
+                let end = visual_end(buffer, start, target);
+                self.visual_anchor = start;
+                self.mode = match target.kind {
+                    MotionKind::Linewise => Mode::Visual(VisualKind::Line),
+                    _ if kind == VisualKind::Line => Mode::Visual(VisualKind::Char),
+                    _ => Mode::Visual(kind),
+                };
+                let doc = self.doc_mut();
+                doc.cursor = end;
+                doc.remember_col();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The last position a text object covers, where the cursor goes in Visual mode.
+fn visual_end(buffer: &Buffer, start: Position, target: Target) -> Position {
+    match target.kind {
+        MotionKind::Exclusive if target.pos <= start => start,
+        MotionKind::Exclusive => {
+            let mut w = Walker::new(buffer, target.pos);
+            w.dec();
+            w.pos
+        }
+        MotionKind::Inclusive | MotionKind::Linewise => target.pos,
+    }
+}
@@ -0,0 +1,333 @@
+//! Walking the buffer a char at a time, and the word motions built on that. These follow vim's
+//! `search.c` closely, since the exact rules are what makes `w`, `e`, `b` and `ge` (and their
+//! use after an operator) feel right.
+
+use crate::buffer::{Buffer, Position};
+
+/// What a step of [`Walker::inc`] or [`Walker::dec`] did, like the return values of vim's
+/// `inc()` and `dec()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Step {
+    /// Moved within the line.
+    Char,
+    /// Moved onto the end of the line, just past its last char.
+    LineEnd,
+    /// Moved to another line.
+    Line,
+    /// Couldn't move, at the start or end of the buffer.
+    Stuck,
+}
+
+impl Step {
+    /// Moved onto the end of a line or to another one.
+    fn crossed(self) -> bool {
+        matches!(self, Self::LineEnd | Self::Line)
+    }
+}
+
+/// A position that moves through the buffer char by char, stopping at the end of each line
+/// (where the line break would be).
+pub struct Walker<'a> {
+    buffer: &'a Buffer,
+    pub pos: Position,
+    chars: Vec<char>,
+}
+
+impl<'a> Walker<'a> {
+    pub fn new(buffer: &'a Buffer, pos: Position) -> Self {
+        let mut walker = Self {
+            buffer,
+            pos,
+            chars: Vec::new(),
+        };
+        walker.load_line();
+        walker
+    }
+
+    fn load_line(&mut self) {
+        self.chars = self
+            .buffer
+            .line(self.pos.row)
+            .unwrap_or_default()
+            .chars()
+            .collect();
+    }
+
+    /// The char at the position, `None` at the end of the line.
+    pub fn ch(&self) -> Option<char> {
+        self.chars.get(self.pos.col).copied()
+    }
+
+    /// Moves to `pos`.
+    pub fn set(&mut self, pos: Position) {
+        let row = self.pos.row;
+        self.pos = pos;
+        if pos.row != row {
+            self.load_line();
+        }
+    }
+
+    pub fn line_is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// Forward one char, onto the end of the line after the last char and from there to the
+    /// start of the next line.
+    pub fn inc(&mut self) -> Step {
+        if self.pos.col < self.chars.len() {
+            self.pos.col += 1;
+            return if self.pos.col < self.chars.len() {
+                Step::Char
+            } else {
+                Step::LineEnd
+            };
+        }
+        if self.pos.row + 1 < self.buffer.line_count() {
+            self.pos = Position::new(self.pos.row + 1, 0);
+            self.load_line();
+            return Step::Line;
+        }
+        Step::Stuck
+    }
+
+    /// Back one char. From the start of a line it goes to the end of the previous one.
+    pub fn dec(&mut self) -> Step {
+        if self.pos.col > 0 {
+            self.pos.col = self.pos.col.min(self.chars.len()) - 1;
+            return Step::Char;
+        }
+        if self.pos.row > 0 {
+            self.pos.row -= 1;
+            self.load_line();
+            self.pos.col = self.chars.len();
+            return Step::Line;
+        }
+        Step::Stuck
+    }
+
+    /// Like [`Walker::inc`], but skips the end of a non-empty line.
+    pub fn inc_line(&mut self) -> Step {
+        let step = self.inc();
+        if step == Step::LineEnd && self.pos.col > 0 {
+            return self.inc();
+        }
+        step
+    }
+
+    /// Like [`Walker::dec`], but skips the end of a non-empty line.
+    pub fn dec_line(&mut self) -> Step {
+        let step = self.dec();
+        if step == Step::Line && self.pos.col > 0 {
+            self.pos.col -= 1;
+        }
+        step
+    }
+
+    /// Whether only blanks come before the position on its line, with `and_here` the char at
+    /// it too.
+    pub fn in_indent(&self, and_here: bool) -> bool {
+        let upto = self.pos.col + usize::from(and_here);
+        self.chars
+            .iter()
+            .take_while(|&&ch| ch == ' ' || ch == '\t')
+            .count()
+            >= upto
+    }
+
+    /// Back to the first char of the run of chars of the same class as the one at the
+    /// position, without leaving the line.
+    pub fn back_in_line(&mut self, big: bool) {
+        let class = self.class(big);
+        while self.pos.col > 0 {
+            self.pos.col -= 1;
+            if self.class(big) != class {
+                self.pos.col += 1;
+                break;
+            }
+        }
+    }
+
+    /// Class of the char at the position: 0 for blanks and line ends, 1 for punctuation, 2 for
+    /// word chars. For WORDs everything that isn't blank is 1.
+    pub fn class(&self, big: bool) -> u8 {
+        let class = self.ch().map_or(0, char_class);
+        if big && class != 0 { 1 } else { class }
+    }
+
+    /// Moves while the class stays `class`. True if it hit the start or end of the buffer.
+    fn skip_class(&mut self, class: u8, big: bool, forward: bool) -> bool {
+        while self.class(big) == class {
+            let step = if forward { self.inc() } else { self.dec() };
+            if step == Step::Stuck {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Word class of a char, as in vim with the default `iskeyword`: 0 blank, 1 punctuation,
+/// 2 letters, digits and `_`.
+pub fn char_class(ch: char) -> u8 {
+    if ch == ' ' || ch == '\t' || (!ch.is_ascii() && ch.is_whitespace()) {
+        0
+    } else if ch.is_alphanumeric() || ch == '_' {
+        2
+    } else {
+        1
+    }
+}
+
+/// Where a word motion ended, and whether it got the whole way. After an operator a motion
+/// that stopped short still counts, it just covers less.
+pub type Moved = (Position, bool);
+
+/// `w` and `W`. With `eol` (after an operator) the last word stops at the end of its line
+/// instead of going on to the next line.
+pub fn fwd_word(buffer: &Buffer, pos: Position, count: usize, big: bool, eol: bool) -> Moved {
+    let mut w = Walker::new(buffer, pos);
+    let last_row = buffer.line_count().saturating_sub(1);
+    for remaining in (0..count).rev() {
+        let class = w.class(big);
+        let last_line = w.pos.row == last_row;
+        let step = w.inc();
+        if step == Step::Stuck || (step.crossed() && last_line) {
+            return (w.pos, false);
+        }
+        if step.crossed() && eol && remaining == 0 {
+            return (w.pos, true);
+        }
+        // Past the end of the current word, if in one.
+        if class != 0 {
+            while w.class(big) == class {
+                let step = w.inc();
+                if step == Step::Stuck || (step.crossed() && eol && remaining == 0) {
+                    return (w.pos, true);
+                }
+            }
+        }
+        // On to the next non-blank. An empty line counts as a word.
+        while w.class(big) == 0 {
+            if w.pos.col == 0 && w.line_is_empty() {
+                break;
+            }
+            let step = w.inc();
+            if step == Step::Stuck || (step.crossed() && eol && remaining == 0) {
+                return (w.pos, true);
+            }
+        }
+    }
+    (w.pos, true)
+}
+
+/// `b` and `B`, and with `stop` the start of the word the cursor is in rather than the one
+/// before (for `iw`).
+pub fn bck_word(buffer: &Buffer, pos: Position, count: usize, big: bool, stop: bool) -> Moved {
+    let mut w = Walker::new(buffer, pos);
+    let mut stop = stop;
+    'words: for _ in 0..count {
+        let class = w.class(big);
+        if w.dec() == Step::Stuck {
+            return (w.pos, false);
+        }
+        if !stop || class == w.class(big) || class == 0 {
+            // Skip blanks before the word, stopping at an empty line.
+            while w.class(big) == 0 {
+                if w.pos.col == 0 && w.line_is_empty() {
+                    stop = false;
+                    continue 'words;
+                }
+                if w.dec() == Step::Stuck {
+                    return (w.pos, true);
+                }
+            }
+            if w.skip_class(w.class(big), big, false) {
+                return (w.pos, true);
+            }
+        }
+        // One too far.
+        w.inc();
+        stop = false;
+    }
+    (w.pos, true)
+}
+
+/// `e` and `E`. With `stop`, a cursor already at the end of a word stays there (for `cw`), and
+/// with `empty` an empty line counts as a word end.
+pub fn end_word(
+    buffer: &Buffer,
+    pos: Position,
+    count: usize,
+    big: bool,
+    stop: bool,
+    empty: bool,
+) -> Moved {
+    let mut w = Walker::new(buffer, pos);
+    let mut stop = stop;
+    'words: for _ in 0..count {
+        let class = w.class(big);
+        if w.inc() == Step::Stuck {
+            return (w.pos, false);
+        }
+        if w.class(big) == class && class != 0 {
+            // In the middle of a word, just go to its end.
+            if w.skip_class(class, big, true) {
+                return (w.pos, false);
+            }
+        } else if !stop || class == 0 {
+            // At the end of a word: on to the end of the next one.
+            while w.class(big) == 0 {
+                if w.pos.col == 0 && w.line_is_empty() && empty {
+                    stop = false;
+                    continue 'words;
+                }
+                if w.inc() == Step::Stuck {
+                    return (w.pos, false);
+                }
+            }
+            if w.skip_class(w.class(big), big, true) {
+                return (w.pos, false);
+            }
+        }
+        // One too far.
+        w.dec();
+        stop = false;
+    }
+    (w.pos, true)
+}
+
+/// `ge` and `gE`. With `eol`, stops at the start of a line instead of going to the previous
+/// one.
+pub fn bckend_word(buffer: &Buffer, pos: Position, count: usize, big: bool, eol: bool) -> Moved {
+    let mut w = Walker::new(buffer, pos);
+    for _ in 0..count {
+        let class = w.class(big);
+        let step = w.dec();
+        if step == Step::Stuck {
+            return (w.pos, false);
+        }
+        if eol && step == Step::Line {
+            return (w.pos, true);
+        }
+        // Back to before the start of this word.
+        if class != 0 {
+            while w.class(big) == class {
+                let step = w.dec();
+                if step == Step::Stuck || (eol && step == Step::Line) {
+                    return (w.pos, true);
+                }
+            }
+        }
+        // Back to the end of the previous word.
+        while w.class(big) == 0 {
+            if w.pos.col == 0 && w.line_is_empty() {
+                break;
+            }
+            let step = w.dec();
+            if step == Step::Stuck || (eol && step == Step::Line) {
+                return (w.pos, true);
+            }
+        }
+    }
+    (w.pos, true)
+}
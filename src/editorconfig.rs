@@ -0,0 +1,288 @@
+//! `.editorconfig` files: the properties they give a file, from every `.editorconfig` in the
+//! directories above it up to one with `root = true`. Closer files win, and within a file
+//! later sections do.
+//!
+//! See <https://spec.editorconfig.org> for the format.
+
+use std::fs;
+use std::path::{self, Path};
+
+use regex::Regex;
+
+use crate::buffer::{Encoding, FileFormat};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tab,
+    Space,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentSize {
+    Width(usize),
+    /// As wide as a tab.
+    Tab,
+}
+
+/// The properties that apply to a file. Those left out, or set to `unset`, are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Properties {
+    pub indent_style: Option<IndentStyle>,
+    pub indent_size: Option<IndentSize>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<FileFormat>,
+    /// The encoding and whether it's written with a byte order mark.
+    pub charset: Option<(Encoding, bool)>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+}
+
+impl Properties {
+    /// Sets property `key`. Unknown properties and values are ignored, as the spec says.
+    fn set(&mut self, key: &str, value: &str) {
+        let value = value.to_ascii_lowercase();
+        let unset = value == "unset";
+        let flag = match value.as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        };
+        match key {
+            "indent_style" => {
+                self.indent_style = match value.as_str() {
+                    "tab" => Some(IndentStyle::Tab),
+                    "space" => Some(IndentStyle::Space),
+                    _ if unset => None,
+                    _ => return,
+                };
+            }
+            "indent_size" => {
+                self.indent_size = match value.parse() {
+                    Ok(width) => Some(IndentSize::Width(width)),
+                    Err(_) if value == "tab" => Some(IndentSize::Tab),
+                    Err(_) if unset => None,
+                    Err(_) => return,
+                };
+            }
+            "tab_width" => {
+                self.tab_width = match value.parse() {
+                    Ok(width) => Some(width),
+                    Err(_) if unset => None,
+                    Err(_) => return,
+                };
+            }
+            "end_of_line" => {
+                self.end_of_line = match value.as_str() {
+                    "lf" => Some(FileFormat::Unix),
+                    "crlf" => Some(FileFormat::Dos),
+                    "cr" => Some(FileFormat::Mac),
+                    _ if unset => None,
+                    _ => return,
+                };
+            }
+            "charset" => {
+                self.charset = match value.as_str() {
+                    "utf-8" => Some((Encoding::Utf8, false)),
+                    "utf-8-bom" => Some((Encoding::Utf8, true)),
+                    "utf-16be" => Some((Encoding::Utf16Be, true)),
+                    "utf-16le" => Some((Encoding::Utf16Le, true)),
+                    "latin1" => Some((Encoding::Latin1, false)),
+                    _ if unset => None,
+                    _ => return,
+                };
+            }
+            "trim_trailing_whitespace" if flag.is_some() || unset => {
+                self.trim_trailing_whitespace = flag;
+            }
+            "insert_final_newline" if flag.is_some() || unset => self.insert_final_newline = flag,
+            _ => {}
+        }
+    }
+}
+
+/// One `.editorconfig` file.
+#[derive(Debug, Default)]
+struct File {
+    root: bool,
+    /// Each section's glob and its properties, in order.
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+fn parse(text: &str) -> File {
+    let mut file = File::default();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(glob) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            file.sections.push((glob.to_owned(), Vec::new()));
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim().to_ascii_lowercase(), value.trim().to_owned());
+        match file.sections.last_mut() {
+            Some((_, properties)) => properties.push((key, value)),
+            // Before the first section, only `root` means anything.
+            None if key == "root" => file.root = value.eq_ignore_ascii_case("true"),
+            None => {}
+        }
+    }
+    file
+}
+
+/// The properties `.editorconfig` files give `path`.
+pub fn properties(path: &Path) -> Properties {
+    let mut properties = Properties::default();
+    let Ok(path) = path::absolute(path) else {
+        return properties;
+    };
+    let mut files = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let Ok(text) = fs::read_to_string(dir.join(".editorconfig")) else {
+            continue;
+        };
+        let file = parse(&text);
+        let root = file.root;
+        files.push((dir, file));
+        if root {
+            break;
+        }
+    }
+    for (dir, file) in files.iter().rev() {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let relative = relative
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        for (glob, section) in &file.sections {
+            if glob_regex(glob).is_some_and(|regex| regex.is_match(&relative)) {
+                for (key, value) in section {
+                    properties.set(key, value);
+                }
+            }
+        }
+    }
+    properties
+}
+
+/// What a section's glob matches, as a regex over paths relative to the `.editorconfig`,
+/// with `/` between their parts. A glob without a `/` matches file names in any directory.
+fn glob_regex(glob: &str) -> Option<Regex> {
+    let pattern = match glob.strip_prefix('/') {
+        Some(anchored) => translate(anchored),
+        None if glob.contains('/') => translate(glob),
+        None => format!("(?:.*/)?{}", translate(glob)),
+    };
+    Regex::new(&format!("^{pattern}$")).ok()
+}
+
+/// `glob` as regex syntax: `*`, `**`, `?`, `[seq]`, `[!seq]`, `{a,b}` and `{1..10}`.
+fn translate(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                out.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                i += 1;
+                out.push_str(".*");
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            '[' if chars[i..].contains(&']') => {
+                let len = chars[i..]
+                    .iter()
+                    .position(|&ch| ch == ']')
+                    .unwrap_or_default();
+                let mut seq = &chars[i + 1..i + len];
+                out.push('[');
+                if let Some(rest) = seq.strip_prefix(&['!']) {
+                    out.push('^');
+                    seq = rest;
+                }
+                for &ch in seq {
+                    if matches!(ch, '[' | '\\') {
+                        out.push('\\');
+                    }
+                    out.push(ch);
+                }
+                out.push(']');
+                i += len;
+            }
+            '{' if closing_brace(&chars[i..]).is_some() => {
+                let len = closing_brace(&chars[i..]).unwrap_or_default();
+                let inner: String = chars[i + 1..i + len].iter().collect();
+                out.push_str(&braces(&inner));
+                i += len;
+            }
+            ch => out.push_str(&regex::escape(&ch.to_string())),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Where the `}` closing the `{` that `chars` starts with is.
+fn closing_brace(chars: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, &ch) in chars.iter().enumerate() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' if depth == 1 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The regex for what's between `{` and `}`: a range of numbers, alternatives, or with
+/// neither the braces themselves.
+fn braces(inner: &str) -> String {
+    if let Some((from, to)) = inner.split_once("..")
+        && let (Ok(from), Ok(to)) = (from.parse::<i64>(), to.parse::<i64>())
+    {
+        // Too many to list, any number will do.
+        if from.abs_diff(to) > 1000 {
+            return "[+-]?[0-9]+".to_owned();
+        }
+        let (from, to) = (from.min(to), from.max(to));
+        let numbers: Vec<String> = (from..=to).map(|n| n.to_string()).collect();
+        return format!("(?:{})", numbers.join("|"));
+    }
+    let mut alternatives = Vec::new();
+    let (mut depth, mut start, mut escaped) = (0, 0, false);
+    for (i, ch) in inner.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(translate(&inner[start..i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if alternatives.is_empty() {
+        return format!(r"\{{{}\}}", translate(inner));
+    }
+    alternatives.push(translate(&inner[start..]));
+    format!("(?:{})", alternatives.join("|"))
+}
@@ -1,3 +1,19 @@
+/// F1 to F12 have no char of their own, so they're given ones from Unicode's Private Use Area.
+const FUNCTION_KEYS: u32 = 0xf700;
+
+/// The char for function key F`n`.
+pub fn function_key(n: u32) -> Option<char> {
+    (1..=12)
+        .contains(&n)
+        .then(|| char::from_u32(FUNCTION_KEYS + n))?
+}
+
+/// Which function key `ch` is, if it is one.
+pub fn function_key_number(ch: char) -> Option<u32> {
+    let n = (ch as u32).checked_sub(FUNCTION_KEYS)?;
+    (1..=12).contains(&n).then_some(n)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum KeyPressState {
     KeyUp,
@@ -0,0 +1,170 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Symlinks nested deeper than this are treated as a loop, same limit as Linux's `MAXSYMLINKS`.
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions {
+    /// Copy the previous contents to `<file>~` before replacing it.
+    pub backup: bool,
+}
+
+/// Writes `contents` to `path` without ever leaving a half-written file behind.
+///
+/// The data goes to a temporary file next to the target, is flushed to disk and only then renamed
+/// over the original, so a full disk or a crash mid-write leaves the old contents intact.
+/// If `path` is a symlink the file it points to is replaced and the link itself is kept.
+pub fn atomic_write(path: &Path, contents: &[u8], options: &WriteOptions) -> io::Result<()> {
+    let target = resolve_symlinks(path)?;
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let original = fs::metadata(&target).ok();
+
+    let (tmp_path, mut tmp) = create_temp_file(&dir, &target)?;
+    let result = (|| {
+        tmp.write_all(contents)?;
+        #[cfg(unix)]
+        if let Some(meta) = &original {
+            copy_permissions(&tmp, meta)?;
+        }
+        tmp.sync_all()?;
+        drop(tmp);
+
+        if options.backup && original.is_some() {
+            fs::copy(&target, backup_path(&target))?;
+        }
+        replace(&tmp_path, &target, original.as_ref())
+    })();
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    sync_dir(&dir);
+    Ok(())
+}
+
+/// Cheap fingerprint of a file on disk, compared to notice it being changed by something else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+}
+
+impl FileStamp {
+    /// `None` if the file doesn't exist (or can't be looked at).
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        Some(Self {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        })
+    }
+}
+
+/// 64-bit FNV-1a of a file's contents. Stable across builds, unlike `DefaultHasher`, so it can
+/// be written to disk and compared later.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push("~");
+    PathBuf::from(name)
+}
+
+/// Follows `path` through any chain of symlinks to the file that actually holds the data.
+/// Unlike [`fs::canonicalize`], the final target doesn't have to exist yet.
+pub fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    let mut current = path.to_path_buf();
+    for _ in 0..MAX_SYMLINK_DEPTH {
+        match fs::symlink_metadata(&current) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                let link = fs::read_link(&current)?;
+                current = match current.parent() {
+                    Some(parent) if link.is_relative() => parent.join(link),
+                    _ => link,
+                };
+            }
+            _ => return Ok(current),
+        }
+    }
+    Err(io::Error::other(format!(
+        "too many levels of symbolic links: {}",
+        path.display()
+    )))
+}
+
+fn create_temp_file(dir: &Path, target: &Path) -> io::Result<(PathBuf, File)> {
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let pid = std::process::id();
+    for attempt in 0u32.. {
+        let tmp_path = dir.join(format!(".{name}.{pid}.{attempt}.editui-tmp"));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+        {
+            Ok(file) => return Ok((tmp_path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+#[cfg(unix)]
+fn copy_permissions(file: &File, original: &fs::Metadata) -> io::Result<()> {
+    use std::os::unix::fs::{MetadataExt, fchown};
+
+    // Only root (or the owner, for the group) may hand the file over, so a failed chown just
+    // means the file now belongs to whoever saved it, same as vim.
+    let _ = fchown(file, Some(original.uid()), Some(original.gid()));
+    file.set_permissions(original.permissions())
+}
+
+#[cfg(unix)]
+fn replace(tmp: &Path, target: &Path, _original: Option<&fs::Metadata>) -> io::Result<()> {
+    fs::rename(tmp, target)
+}
+
+/// Windows won't rename over a read-only file, and its read-only attribute is all there is of
+/// the original's permissions. The original loses it for the rename and the new file gets it
+/// back afterwards.
+#[cfg(not(unix))]
+#[allow(clippy::permissions_set_readonly_false)]
+fn replace(tmp: &Path, target: &Path, original: Option<&fs::Metadata>) -> io::Result<()> {
+    let Some(original) = original.filter(|meta| meta.permissions().readonly()) else {
+        return fs::rename(tmp, target);
+    };
+    let mut writable = original.permissions();
+    writable.set_readonly(false);
+    fs::set_permissions(target, writable)?;
+    if let Err(e) = fs::rename(tmp, target) {
+        let _ = fs::set_permissions(target, original.permissions());
+        return Err(e);
+    }
+    fs::set_permissions(target, original.permissions())
+}
+
+/// Makes the rename itself durable. Windows has no equivalent for directories, and on unix a
+/// failure here isn't worth reporting since the data is already safely on disk.
+fn sync_dir(_dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(_dir) {
+        let _ = dir.sync_all();
+    }
+}
@@ -1,5 +1,14 @@
+pub mod buffer;
+pub mod clipboard;
 pub(crate) mod command;
+pub mod diff;
+pub mod display;
 pub mod editor;
+pub mod editorconfig;
 pub mod event;
+pub mod fileio;
+pub mod paths;
+pub mod swap;
 mod sys;
 pub mod terminal;
+pub mod watch;
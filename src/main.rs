@@ -8,6 +8,9 @@ static GLOBAL: MiMalloc = MiMalloc;
 
 fn main() -> std::process::ExitCode {
     let mut editor = Editor::new();
+    if let Some(path) = std::env::args_os().nth(1) {
+        editor.open(path);
+    }
     match editor.run() {
         Ok(_) => ExitCode::SUCCESS,
         Err(_) => ExitCode::FAILURE,
@@ -0,0 +1,59 @@
+//! Where editui keeps its own files.
+
+use std::{env, path::PathBuf};
+
+/// Per-user data that should survive restarts but isn't config: undo history, swap files.
+/// `%LOCALAPPDATA%\editui` on Windows, `$XDG_STATE_HOME/editui` (or `~/.local/state/editui`)
+/// elsewhere.
+pub fn state_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("editui"));
+    }
+    match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("editui")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state/editui")),
+    }
+}
+
+/// The config file read at startup: `%APPDATA%\editui\config.toml` on Windows,
+/// `$XDG_CONFIG_HOME/editui/config.toml` (or `~/.config/editui/config.toml`) elsewhere.
+pub fn config_file() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("editui"))
+    } else {
+        match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("editui")),
+            _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/editui")),
+        }
+    };
+    dir.map(|dir| dir.join("config.toml"))
+}
+
+/// Turns `path` into a single file name that can live in one of the state directories, the same
+/// way vim does for its undo and swap files: separators, and anything else Windows doesn't
+/// allow in a file name, become `%`. A file that doesn't exist yet still gets its absolute
+/// path, so it has the same name from any directory.
+pub fn flatten(path: &std::path::Path) -> String {
+    let path = path
+        .canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    // On Windows `canonicalize` gives `\\?\C:\dir` and `\\?\UNC\server\share`. Without the
+    // prefix, a file has the same swap and undo files before it exists as after, as `absolute`
+    // doesn't add it.
+    let path = path.to_string_lossy();
+    let path = match path.strip_prefix(r"\\?\") {
+        Some(rest) => match rest.strip_prefix(r"UNC\") {
+            Some(share) => format!(r"\\{share}"),
+            None => rest.to_owned(),
+        },
+        None => path.into_owned(),
+    };
+    path.chars()
+        .map(|ch| match ch {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '%',
+            ch if ch.is_control() => '%',
+            ch => ch,
+        })
+        .collect()
+}
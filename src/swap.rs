@@ -0,0 +1,161 @@
+//! Swap files: a copy of unsaved text written every few seconds, so a crash or a dropped SSH
+//! session loses at most that much. The swap file also acts as a lock; finding one that belongs
+//! to a running editui means the file is already being edited elsewhere.
+//!
+//! ```text
+//! editui-swap 1
+//! pid <pid>
+//! modified <0|1>
+//! <buffer text, if modified>
+//! ```
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::{
+    buffer::Buffer,
+    fileio::{self, WriteOptions},
+    paths, sys,
+};
+
+const MAGIC: &str = "editui-swap 1";
+
+/// How long unsaved changes can go without being written to the swap file.
+pub const SWAP_INTERVAL: Duration = Duration::from_secs(4);
+
+#[derive(Debug, Error)]
+pub enum SwapError {
+    #[error("Could not write swap file {0}: [{1}]")]
+    Write(PathBuf, io::Error),
+    #[error("Could not read swap file {0}: [{1}]")]
+    Read(PathBuf, io::Error),
+}
+
+/// A swap file left by another editui, found when opening a file.
+#[derive(Debug)]
+pub struct ExistingSwap {
+    pub path: PathBuf,
+    pub pid: u32,
+    /// The editui that wrote it is still running, so the file is open twice.
+    pub running: bool,
+    /// Unsaved text, if there was any. `None` for swap files that can't be parsed, which are
+    /// still worth asking about since they lock the file.
+    pub text: Option<String>,
+}
+
+/// The swap file this editui owns for its buffer.
+#[derive(Debug)]
+pub struct SwapFile {
+    path: PathBuf,
+    /// Buffer change tick last written out.
+    tick: u64,
+    written_at: Instant,
+}
+
+pub fn swap_path(swap_dir: &Path, file: &Path) -> PathBuf {
+    let mut name = paths::flatten(file);
+    name.push_str(".swp");
+    swap_dir.join(name)
+}
+
+impl SwapFile {
+    /// Claims the swap file for `file`. If there already is one it's returned instead, and the
+    /// caller decides whether to [`SwapFile::take_over`] it.
+    pub fn create(
+        swap_dir: &Path,
+        file: &Path,
+        buffer: &Buffer,
+    ) -> Result<Result<Self, ExistingSwap>, SwapError> {
+        let path = swap_path(swap_dir, file);
+        fs::create_dir_all(swap_dir).map_err(|e| SwapError::Write(path.clone(), e))?;
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut lock) => {
+                lock.write_all(&contents(buffer))
+                    .map_err(|e| SwapError::Write(path.clone(), e))?;
+                Ok(Ok(Self {
+                    path,
+                    tick: buffer.change_tick(),
+                    written_at: Instant::now(),
+                }))
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(Err(read_existing(path)?)),
+            Err(e) => Err(SwapError::Write(path, e)),
+        }
+    }
+
+    /// Replaces someone else's swap file with ours.
+    pub fn take_over(existing: ExistingSwap, buffer: &Buffer) -> Result<Self, SwapError> {
+        let mut swap = Self {
+            path: existing.path,
+            tick: buffer.change_tick(),
+            written_at: Instant::now(),
+        };
+        swap.write(buffer)?;
+        Ok(swap)
+    }
+
+    /// Writes the buffer out if it changed and the last write is more than [`SWAP_INTERVAL`] ago.
+    pub fn update(&mut self, buffer: &Buffer) -> Result<(), SwapError> {
+        if buffer.change_tick() != self.tick && self.written_at.elapsed() >= SWAP_INTERVAL {
+            self.write(buffer)?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, buffer: &Buffer) -> Result<(), SwapError> {
+        fileio::atomic_write(&self.path, &contents(buffer), &WriteOptions::default())
+            .map_err(|e| SwapError::Write(self.path.clone(), e))?;
+        self.tick = buffer.change_tick();
+        self.written_at = Instant::now();
+        Ok(())
+    }
+
+    /// Deletes the swap file, on a clean exit or when the buffer is closed.
+    pub fn remove(self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn contents(buffer: &Buffer) -> Vec<u8> {
+    let modified = buffer.is_modified();
+    let mut out = format!(
+        "{MAGIC}\npid {}\nmodified {}\n",
+        std::process::id(),
+        modified as u8
+    );
+    if modified {
+        out.push_str(&buffer.text());
+    }
+    out.into_bytes()
+}
+
+fn read_existing(path: PathBuf) -> Result<ExistingSwap, SwapError> {
+    let bytes = fs::read(&path).map_err(|e| SwapError::Read(path.clone(), e))?;
+    let text = String::from_utf8_lossy(&bytes);
+    let mut parts = text.splitn(4, '\n');
+    let (magic, pid, modified) = (parts.next(), parts.next(), parts.next());
+    let pid = pid
+        .and_then(|line| line.strip_prefix("pid "))
+        .and_then(|pid| pid.parse().ok());
+    let Some(pid) = pid.filter(|_| magic == Some(MAGIC)) else {
+        return Ok(ExistingSwap {
+            path,
+            pid: 0,
+            running: false,
+            text: None,
+        });
+    };
+    let text = (modified == Some("modified 1")).then(|| parts.next().unwrap_or("").to_owned());
+    Ok(ExistingSwap {
+        path,
+        pid,
+        running: pid != std::process::id() && sys::process_is_running(pid),
+        text,
+    })
+}
@@ -19,10 +19,14 @@ use windows_sys::{
                 ENABLE_VIRTUAL_TERMINAL_PROCESSING, ENABLE_WINDOW_INPUT, ENABLE_WRAP_AT_EOL_OUTPUT,
                 GetConsoleCP, GetConsoleMode, GetConsoleOutputCP, GetConsoleScreenBufferInfo,
                 GetNumberOfConsoleInputEvents, GetStdHandle, INPUT_RECORD, ReadConsoleA,
-                ReadConsoleInputA, SetConsoleMode,
+                ReadConsoleInputW, SetConsoleMode,
             },
+            DataExchange,
             Diagnostics::Debug::OutputDebugStringA,
             IO::CancelIoEx,
+            Memory,
+            Ole::CF_UNICODETEXT,
+            Threading,
         },
     },
     core::BOOL,
@@ -31,7 +35,7 @@ use windows_sys::{
 
 use crate::{
     command::{self, Command},
-    event::{Event, KeyPressState, ModifierKeyCode},
+    event::{Event, KeyPressState, ModifierKeyCode, function_key},
 };
 #[derive(Debug, Error)]
 pub enum ConsoleError {
@@ -51,7 +55,18 @@ pub enum ConsoleError {
     ReadConsoleInput(u32),
 }
 
+#[derive(Debug, Error)]
+pub enum ClipboardError {
+    #[error("Could not open the clipboard, error code: [{0}]")]
+    Open(u32),
+    #[error("Could not write to the clipboard, error code: [{0}]")]
+    Write(u32),
+}
+
 const INVALID_CONSOLE_MODE: u32 = u32::MAX;
+/// Virtual key codes of F1 and F12.
+const VK_F1: u16 = 0x70;
+const VK_F12: u16 = 0x7b;
 pub struct ConsoleState {
     stdin: Foundation::HANDLE,
     stdout: Foundation::HANDLE,
@@ -274,13 +289,14 @@ pub fn read() -> Result<Vec<Event>, ConsoleError> {
         .map_err(|_| ConsoleError::QueryNumberOfConsoleEvents(get_last_error_code()))?;
         while unread_events > 0 && total_events_read <= LEN {
             let mut events_read = 0;
-            check_nonzero_success(ReadConsoleInputA(
+            check_nonzero_success(ReadConsoleInputW(
                 GLOBAL_CONSOLE_STATE.stdin,
                 buf.as_mut_ptr(),
                 LEN,
                 &mut events_read,
             ))
             .map_err(|_| ConsoleError::ReadConsoleInput(get_last_error_code()))?;
+            buf.set_len(events_read as usize);
             total_events_read += events_read;
             // write_stdout(&format!(
             //     "unread_events: {}, total_events_read: {}",
@@ -290,16 +306,28 @@ pub fn read() -> Result<Vec<Event>, ConsoleError> {
         }
 
         for input in buf {
-            write_stdout(&format!("input: {:?}", input.EventType))?;
             match input.EventType as u32 {
                 Console::KEY_EVENT => {
                     let event = input.Event.KeyEvent;
-                    let ch = event.uChar.UnicodeChar;
+                    let mut ch = event.uChar.UnicodeChar;
+                    // F1 to F12 come without a char.
+                    let code = event.wVirtualKeyCode;
+                    if ch == 0
+                        && (VK_F1..=VK_F12).contains(&code)
+                        && let Some(key) = function_key(u32::from(code - VK_F1) + 1)
+                    {
+                        ch = key as u16;
+                    }
                     //bKeyDown : If the key is pressed, this member is TRUE. Otherwise, this member is FALSE (the key is released).
                     if ch != 0 {
-                        if let Some(ch) = char::from_u32(ch as u32) {
+                        if let Some(mut ch) = char::from_u32(ch as u32) {
                             let ctrl = (event.dwControlKeyState & 0x0008 != 0)
                                 || (event.dwControlKeyState & 0x0004 != 0);
+                            // Ctrl+letter arrives as the C0 control code (Ctrl+S is 0x13),
+                            // report it as the letter so callers can match on 's' + ctrl.
+                            if ctrl && ('\x01'..='\x1a').contains(&ch) {
+                                ch = (ch as u8 - 1 + b'a') as char;
+                            }
 
                             let alt = (event.dwControlKeyState & 0x0002 != 0)
                                 || (event.dwControlKeyState & 0x0001 != 0);
@@ -366,6 +394,146 @@ pub fn disable_raw_mode() -> Result<(), ConsoleError> {
     Ok(())
 }
 
+/// Whether a process with this id is still running. Used to tell a swap file held by a live
+/// editui from one left behind by a crash.
+pub fn process_is_running(pid: u32) -> bool {
+    unsafe {
+        let handle = Threading::OpenProcess(Threading::PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            // The process exists, it just belongs to someone else.
+            return get_last_error_code() == Foundation::ERROR_ACCESS_DENIED;
+        }
+        let mut exit_code = 0;
+        let queried = Threading::GetExitCodeProcess(handle, &mut exit_code);
+        Foundation::CloseHandle(handle);
+        queried != 0 && exit_code == Foundation::STILL_ACTIVE as u32
+    }
+}
+
+/// Change notifications for one directory, used to notice files being rewritten or replaced
+/// under an open buffer.
+pub struct DirectoryWatch {
+    handle: Foundation::HANDLE,
+}
+
+// The handle is only waited on and closed, both of which are fine from any thread.
+unsafe impl Send for DirectoryWatch {}
+
+impl DirectoryWatch {
+    /// Starts watching `dir` for files being created, renamed, written or resized. Fails for
+    /// directories that don't support notifications, such as some network shares.
+    pub fn new(dir: &std::path::Path) -> Option<Self> {
+        use std::os::windows::ffi::OsStrExt;
+
+        let wide: Vec<u16> = dir.as_os_str().encode_wide().chain([0]).collect();
+        let handle = unsafe {
+            FileSystem::FindFirstChangeNotificationW(
+                wide.as_ptr(),
+                0,
+                FileSystem::FILE_NOTIFY_CHANGE_FILE_NAME
+                    | FileSystem::FILE_NOTIFY_CHANGE_LAST_WRITE
+                    | FileSystem::FILE_NOTIFY_CHANGE_SIZE,
+            )
+        };
+        if ptr::eq(handle, Foundation::INVALID_HANDLE_VALUE) {
+            return None;
+        }
+        Some(Self { handle })
+    }
+
+    /// Blocks until something in the directory changes or `timeout` passes. Returns whether
+    /// there was a change.
+    pub fn wait(&self, timeout: std::time::Duration) -> bool {
+        unsafe {
+            if Threading::WaitForSingleObject(self.handle, timeout.as_millis() as u32)
+                != Foundation::WAIT_OBJECT_0
+            {
+                return false;
+            }
+            FileSystem::FindNextChangeNotification(self.handle);
+        }
+        true
+    }
+}
+
+impl Drop for DirectoryWatch {
+    fn drop(&mut self) {
+        unsafe {
+            FileSystem::FindCloseChangeNotification(self.handle);
+        }
+    }
+}
+
+/// Opens the clipboard for this thread. Another program may briefly have it open, so this tries
+/// a few times before giving up.
+fn open_clipboard() -> Result<(), ClipboardError> {
+    for _ in 0..5 {
+        if unsafe { DataExchange::OpenClipboard(null_mut()) } != 0 {
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    Err(ClipboardError::Open(get_last_error_code()))
+}
+
+/// The text on the clipboard with `\r\n` turned into `\n`, `None` if it holds something else.
+pub fn get_clipboard() -> Result<Option<String>, ClipboardError> {
+    open_clipboard()?;
+    let text = unsafe {
+        let handle = DataExchange::GetClipboardData(CF_UNICODETEXT as u32);
+        let data = if handle.is_null() {
+            null_mut()
+        } else {
+            Memory::GlobalLock(handle) as *const u16
+        };
+        if data.is_null() {
+            None
+        } else {
+            let len = (0..).take_while(|&i| *data.add(i) != 0).count();
+            let text = String::from_utf16_lossy(std::slice::from_raw_parts(data, len));
+            Memory::GlobalUnlock(handle);
+            Some(text.replace("\r\n", "\n"))
+        }
+    };
+    unsafe { DataExchange::CloseClipboard() };
+    Ok(text)
+}
+
+/// Puts `text` on the clipboard, with Windows line breaks.
+pub fn set_clipboard(text: &str) -> Result<(), ClipboardError> {
+    let wide: Vec<u16> = text
+        .replace('\n', "\r\n")
+        .encode_utf16()
+        .chain([0])
+        .collect();
+    open_clipboard()?;
+    let result = unsafe {
+        DataExchange::EmptyClipboard();
+        let memory = Memory::GlobalAlloc(Memory::GMEM_MOVEABLE, wide.len() * 2);
+        let data = if memory.is_null() {
+            null_mut()
+        } else {
+            Memory::GlobalLock(memory) as *mut u16
+        };
+        if data.is_null() {
+            Err(ClipboardError::Write(get_last_error_code()))
+        } else {
+            ptr::copy_nonoverlapping(wide.as_ptr(), data, wide.len());
+            Memory::GlobalUnlock(memory);
+            // The clipboard owns the memory once it's taken it, and only then.
+            if DataExchange::SetClipboardData(CF_UNICODETEXT as u32, memory).is_null() {
+                let code = get_last_error_code();
+                Foundation::GlobalFree(memory);
+                Err(ClipboardError::Write(code))
+            } else {
+                Ok(())
+            }
+        }
+    };
+    unsafe { DataExchange::CloseClipboard() };
+    result
+}
+
 fn get_last_error_code() -> u32 {
     unsafe { Foundation::GetLastError() }
 }
@@ -0,0 +1,94 @@
+//! Notices open files being changed by other programs.
+//!
+//! A thread per file waits on change notifications for the file's directory, falling back to
+//! polling where those aren't available, and compares [`FileStamp`]s. Watching the directory
+//! rather than the file means it keeps working when the file is replaced instead of rewritten,
+//! which is what `git checkout` and most editors' safe saves do.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{fileio::FileStamp, sys};
+
+/// How often the file is looked at when there are no notifications, and the longest the thread
+/// takes to notice it should stop.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// A file is reported once its stamp has stopped changing for this long, so a write in progress
+/// isn't picked up half done.
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub struct FileWatcher {
+    path: PathBuf,
+    changes: Receiver<()>,
+    stop: Arc<AtomicBool>,
+}
+
+impl FileWatcher {
+    pub fn new(path: &Path) -> Self {
+        let (tx, changes) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let watched = path.to_path_buf();
+        thread::spawn(move || {
+            let dir = match watched.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            let notifications = sys::DirectoryWatch::new(&dir);
+            let mut last = FileStamp::of(&watched);
+            while !thread_stop.load(Ordering::Relaxed) {
+                match &notifications {
+                    Some(watch) => {
+                        watch.wait(POLL_INTERVAL);
+                    }
+                    None => thread::sleep(POLL_INTERVAL),
+                }
+                let mut now = FileStamp::of(&watched);
+                if now == last {
+                    continue;
+                }
+                loop {
+                    thread::sleep(SETTLE_TIME);
+                    let settled = FileStamp::of(&watched);
+                    if settled == now {
+                        break;
+                    }
+                    now = settled;
+                }
+                last = now;
+                if tx.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            path: path.to_path_buf(),
+            changes,
+            stop,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file changed since the last call.
+    pub fn changed(&self) -> bool {
+        self.changes.try_iter().count() > 0
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}